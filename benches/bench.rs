@@ -1,9 +1,10 @@
 use std::{
     convert::{TryFrom, TryInto},
+    hint::black_box,
     io::Cursor,
 };
 
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{criterion_group, criterion_main, Criterion};
 use hex_literal::hex;
 use onc_rpc::{
     auth::{AuthFlavor, AuthUnixParams},
@@ -110,5 +111,67 @@ pub fn rpc_message(c: &mut Criterion) {
     });
 }
 
+/// Round trips a burst of 32 NULL calls through a [`UdpServer`] over loopback.
+///
+/// [`UdpServer`]: onc_rpc::server::UdpServer
+#[cfg(feature = "tokio")]
+pub fn udp_server(c: &mut Criterion) {
+    use onc_rpc::server::{Dispatcher, UdpServer};
+    use tokio::net::UdpSocket;
+
+    const BURST: u32 = 32;
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let (client, server_addr) = rt.block_on(async {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = socket.local_addr().unwrap();
+        let server = UdpServer::new(Dispatcher::new().with_procedure(
+            100099,
+            1,
+            1,
+            onc_rpc::server::Procedure::sync(|_ctx, args| Ok(args)),
+        ));
+        tokio::spawn(async move { server.serve(socket).await });
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        (client, server_addr)
+    });
+
+    let calls = (0..BURST)
+        .map(|xid| {
+            RpcMessage::new(
+                xid,
+                MessageType::Call(CallBody::new(
+                    100099,
+                    1,
+                    1,
+                    AuthFlavor::<&[u8]>::AuthNone(None),
+                    AuthFlavor::AuthNone(None),
+                    &[42; 16][..],
+                )),
+            )
+            .serialise_datagram()
+            .unwrap()
+        })
+        .collect::<Vec<_>>();
+
+    c.bench_function("udp_server_loopback_burst", |b| {
+        let mut buf = vec![0; 1500];
+        b.iter(|| {
+            rt.block_on(async {
+                for call in &calls {
+                    client.send_to(call, server_addr).await.unwrap();
+                }
+                for _ in 0..BURST {
+                    black_box(client.recv(&mut buf).await.unwrap());
+                }
+            })
+        })
+    });
+}
+
+#[cfg(not(feature = "tokio"))]
 criterion_group!(benches, auth, rpc_message);
+#[cfg(feature = "tokio")]
+criterion_group!(benches, auth, rpc_message, udp_server);
 criterion_main!(benches);
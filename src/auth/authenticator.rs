@@ -0,0 +1,759 @@
+use std::{
+    fmt::Debug,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::Arc,
+};
+
+use crate::{
+    auth::{
+        AuthFlavor, GroupResolver, PeerCredMismatch, PeerCredentials, ShortHandleCache,
+        SquashPolicy, UnixCredentials,
+    },
+    AuthError, CallBody,
+};
+
+/// The transport endpoint a call was received from.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Peer {
+    /// A caller connected over TCP or UDP.
+    Inet(SocketAddr),
+
+    /// A caller connected over a Unix domain socket, and the kernel-verified
+    /// credentials of the peer process if available.
+    Unix(Option<PeerCredentials>),
+}
+
+impl Peer {
+    /// The IP address of the caller, if connected over an IP transport.
+    pub fn ip(&self) -> Option<IpAddr> {
+        match self {
+            Self::Inet(addr) => Some(addr.ip()),
+            Self::Unix(_) => None,
+        }
+    }
+
+    /// Returns true if the caller is on the local machine - either connected
+    /// over a Unix domain socket, or from a loopback address.
+    pub fn is_local(&self) -> bool {
+        match self {
+            Self::Inet(addr) => addr.ip().to_canonical().is_loopback(),
+            Self::Unix(_) => true,
+        }
+    }
+}
+
+impl From<SocketAddr> for Peer {
+    fn from(v: SocketAddr) -> Self {
+        Self::Inet(v)
+    }
+}
+
+/// The identity of an authenticated caller.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Principal {
+    /// The caller has no identity, such as when using
+    /// [`AuthFlavor::AuthNone`].
+    Anonymous,
+
+    /// The caller is acting as a Unix user.
+    Unix(UnixCredentials),
+
+    /// The caller was authenticated by an auth flavor not defined in this
+    /// crate (such as `RPCSEC_GSS`), and is identified by an
+    /// application-defined name.
+    Named {
+        /// The auth flavor number used to authenticate the caller.
+        flavor: u32,
+
+        /// The name of the caller.
+        name: String,
+    },
+}
+
+/// The outcome of successfully authenticating a call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Authenticated {
+    principal: Principal,
+    verifier: AuthFlavor<Vec<u8>>,
+}
+
+impl Authenticated {
+    /// Construct a new `Authenticated` for `principal`, sending `verifier` in
+    /// the reply.
+    pub fn new(principal: Principal, verifier: AuthFlavor<Vec<u8>>) -> Self {
+        Self {
+            principal,
+            verifier,
+        }
+    }
+
+    /// The identity of the caller.
+    pub fn principal(&self) -> &Principal {
+        &self.principal
+    }
+
+    /// The verifier to send to the caller in the reply.
+    pub fn verifier(&self) -> &AuthFlavor<Vec<u8>> {
+        &self.verifier
+    }
+
+    /// Decompose this `Authenticated` into the principal and reply verifier.
+    pub fn into_parts(self) -> (Principal, AuthFlavor<Vec<u8>>) {
+        (self.principal, self.verifier)
+    }
+}
+
+/// The parts of an RPC call used to authenticate the caller.
+#[derive(Debug, Clone)]
+pub struct AuthRequest<'a> {
+    peer: &'a Peer,
+    program: u32,
+    program_version: u32,
+    procedure: u32,
+    credentials: AuthFlavor<&'a [u8]>,
+    verifier: AuthFlavor<&'a [u8]>,
+}
+
+impl<'a> AuthRequest<'a> {
+    /// Construct an `AuthRequest` for `call`, received from `peer`.
+    pub fn new<T, P>(peer: &'a Peer, call: &'a CallBody<T, P>) -> Self
+    where
+        T: AsRef<[u8]>,
+        P: AsRef<[u8]>,
+    {
+        Self {
+            peer,
+            program: call.program(),
+            program_version: call.program_version(),
+            procedure: call.procedure(),
+            credentials: call.auth_credentials().as_borrowed(),
+            verifier: call.auth_verifier().as_borrowed(),
+        }
+    }
+
+    /// The transport endpoint the call was received from.
+    pub fn peer(&self) -> &Peer {
+        self.peer
+    }
+
+    /// The program identifier of the call.
+    pub fn program(&self) -> u32 {
+        self.program
+    }
+
+    /// The program version of the call.
+    pub fn program_version(&self) -> u32 {
+        self.program_version
+    }
+
+    /// The procedure number of the call.
+    pub fn procedure(&self) -> u32 {
+        self.procedure
+    }
+
+    /// The credentials sent by the caller.
+    pub fn credentials(&self) -> &AuthFlavor<&'a [u8]> {
+        &self.credentials
+    }
+
+    /// The verifier sent by the caller.
+    pub fn verifier(&self) -> &AuthFlavor<&'a [u8]> {
+        &self.verifier
+    }
+}
+
+/// Authenticates the credentials and verifier of incoming calls.
+///
+/// An `Authenticator` either accepts a call, returning the [`Principal`] it is
+/// authenticated as and the verifier to send in the reply, or rejects it with
+/// the [`AuthError`] to send in a [`RejectedReply::AuthError`].
+///
+/// Authenticators can be composed - for example, to accept `AUTH_UNIX` only
+/// from local callers and otherwise require a stronger flavor:
+///
+/// ```
+/// use onc_rpc::auth::{Authenticator, NoneAuthenticator, UnixAuthenticator};
+///
+/// let auth = UnixAuthenticator::default()
+///     .when(|req| req.peer().is_local())
+///     .or(NoneAuthenticator);
+/// # let _ = auth;
+/// ```
+///
+/// [`RejectedReply::AuthError`]: crate::RejectedReply::AuthError
+pub trait Authenticator: Send + Sync {
+    /// Authenticate the call described by `req`.
+    fn authenticate(&self, req: &AuthRequest<'_>) -> Result<Authenticated, AuthError>;
+
+    /// Try `self`, and if the call is rejected, try `other`.
+    ///
+    /// If both reject the call, the more specific error is returned - an
+    /// authenticator rejecting a call with [`AuthError::BadCredentials`] or
+    /// [`AuthError::TooWeak`] typically does not handle the flavor at all, so
+    /// any other error takes precedence. If both are equally specific, the
+    /// error from `other` is returned.
+    fn or<B>(self, other: B) -> Or<Self, B>
+    where
+        Self: Sized,
+        B: Authenticator,
+    {
+        Or {
+            first: self,
+            second: other,
+        }
+    }
+
+    /// Only authenticate calls matching `predicate` with `self`, rejecting all
+    /// others with [`AuthError::TooWeak`].
+    fn when<F>(self, predicate: F) -> When<Self, F>
+    where
+        Self: Sized,
+        F: Fn(&AuthRequest<'_>) -> bool + Send + Sync,
+    {
+        When {
+            inner: self,
+            predicate,
+        }
+    }
+}
+
+impl<A> Authenticator for Arc<A>
+where
+    A: Authenticator + ?Sized,
+{
+    fn authenticate(&self, req: &AuthRequest<'_>) -> Result<Authenticated, AuthError> {
+        (**self).authenticate(req)
+    }
+}
+
+impl<A> Authenticator for Box<A>
+where
+    A: Authenticator + ?Sized,
+{
+    fn authenticate(&self, req: &AuthRequest<'_>) -> Result<Authenticated, AuthError> {
+        (**self).authenticate(req)
+    }
+}
+
+/// An [`Authenticator`] trying one authenticator, then another.
+///
+/// See [`Authenticator::or()`].
+#[derive(Debug, Clone)]
+pub struct Or<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A, B> Authenticator for Or<A, B>
+where
+    A: Authenticator,
+    B: Authenticator,
+{
+    fn authenticate(&self, req: &AuthRequest<'_>) -> Result<Authenticated, AuthError> {
+        let first = match self.first.authenticate(req) {
+            Ok(v) => return Ok(v),
+            Err(e) => e,
+        };
+
+        self.second.authenticate(req).map_err(|second| {
+            if specificity(&first) > specificity(&second) {
+                first
+            } else {
+                second
+            }
+        })
+    }
+}
+
+/// Rank how much `e` says about why a call was rejected, with higher values
+/// being more specific.
+fn specificity(e: &AuthError) -> u8 {
+    match e {
+        AuthError::TooWeak => 0,
+        AuthError::BadCredentials => 1,
+        _ => 2,
+    }
+}
+
+/// Reject calls with a verifier other than [`AuthFlavor::AuthNone`], as used
+/// by the `AUTH_NONE`, `AUTH_UNIX` and `AUTH_SHORT` flavors.
+fn check_none_verifier(req: &AuthRequest<'_>) -> Result<(), AuthError> {
+    match req.verifier() {
+        AuthFlavor::AuthNone(_) => Ok(()),
+        _ => Err(AuthError::BadVerifier),
+    }
+}
+
+/// An [`Authenticator`] applied only to calls matching a predicate.
+///
+/// See [`Authenticator::when()`].
+#[derive(Clone)]
+pub struct When<A, F> {
+    inner: A,
+    predicate: F,
+}
+
+impl<A, F> Authenticator for When<A, F>
+where
+    A: Authenticator,
+    F: Fn(&AuthRequest<'_>) -> bool + Send + Sync,
+{
+    fn authenticate(&self, req: &AuthRequest<'_>) -> Result<Authenticated, AuthError> {
+        if !(self.predicate)(req) {
+            return Err(AuthError::TooWeak);
+        }
+        self.inner.authenticate(req)
+    }
+}
+
+impl<A, F> Debug for When<A, F>
+where
+    A: Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("When")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+/// An [`Authenticator`] accepting [`AuthFlavor::AuthNone`] credentials as
+/// [`Principal::Anonymous`].
+///
+/// All other flavors are rejected with [`AuthError::BadCredentials`], and calls
+/// with a verifier other than [`AuthFlavor::AuthNone`] with
+/// [`AuthError::BadVerifier`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoneAuthenticator;
+
+impl Authenticator for NoneAuthenticator {
+    fn authenticate(&self, req: &AuthRequest<'_>) -> Result<Authenticated, AuthError> {
+        match req.credentials() {
+            AuthFlavor::AuthNone(_) => {
+                check_none_verifier(req)?;
+                Ok(Authenticated::new(
+                    Principal::Anonymous,
+                    AuthFlavor::AuthNone(None),
+                ))
+            }
+            _ => Err(AuthError::BadCredentials),
+        }
+    }
+}
+
+/// An [`Authenticator`] accepting [`AuthFlavor::AuthUnix`] credentials as a
+/// [`Principal::Unix`].
+///
+/// The identity claimed by the caller is trusted, optionally after resolving
+/// the full group list with a [`GroupResolver`] and applying a
+/// [`SquashPolicy`]. If configured with a [`ShortHandleCache`], an
+/// [`AuthFlavor::AuthShort`] handle is returned in the reply verifier of each
+/// `AUTH_UNIX` call - reusing the live handle previously issued for identical
+/// credentials - and `AUTH_SHORT` credentials are accepted.
+///
+/// Calls with a verifier other than [`AuthFlavor::AuthNone`] are rejected with
+/// [`AuthError::BadVerifier`].
+///
+/// Squash policies are evaluated against the caller IP address - callers
+/// connected over a Unix domain socket are treated as connecting from
+/// `127.0.0.1`.
+///
+/// Callers connected over a Unix domain socket can be checked against the
+/// [`PeerCredentials`] of the connection with
+/// [`UnixAuthenticator::with_peer_credentials()`].
+///
+/// All other flavors are rejected with [`AuthError::BadCredentials`].
+#[derive(Clone, Default)]
+pub struct UnixAuthenticator {
+    peer_cred: Option<PeerCredMismatch>,
+    groups: Option<Arc<dyn GroupResolver>>,
+    squash: Option<SquashPolicy>,
+    short: Option<Arc<ShortHandleCache>>,
+}
+
+impl UnixAuthenticator {
+    /// Verify the UID and GID claimed by callers connected over a Unix domain
+    /// socket against the [`PeerCredentials`] of the connection, taking the
+    /// `on_mismatch` action if they differ.
+    ///
+    /// Unix domain socket callers with no known peer credentials are rejected
+    /// with [`AuthError::BadCredentials`]. Callers connected over IP are not
+    /// checked.
+    pub fn with_peer_credentials(self, on_mismatch: PeerCredMismatch) -> Self {
+        Self {
+            peer_cred: Some(on_mismatch),
+            ..self
+        }
+    }
+
+    /// Replace the supplementary groups sent by the caller with those returned
+    /// by `resolver`, if any.
+    ///
+    /// Wrap the resolver in a [`GroupCache`](crate::auth::GroupCache) to
+    /// cache lookups.
+    pub fn with_group_resolver<R>(self, resolver: R) -> Self
+    where
+        R: GroupResolver + 'static,
+    {
+        Self {
+            groups: Some(Arc::new(resolver)),
+            ..self
+        }
+    }
+
+    /// Apply `policy` to the caller identity.
+    pub fn with_squash_policy(self, policy: SquashPolicy) -> Self {
+        Self {
+            squash: Some(policy),
+            ..self
+        }
+    }
+
+    /// Issue and accept `AUTH_SHORT` handles using `cache`.
+    pub fn with_short_handles(self, cache: Arc<ShortHandleCache>) -> Self {
+        Self {
+            short: Some(cache),
+            ..self
+        }
+    }
+
+    fn principal(&self, peer: &Peer, creds: UnixCredentials) -> Result<Principal, AuthError> {
+        let creds = match (self.peer_cred, peer) {
+            (Some(mode), Peer::Unix(Some(peer_creds))) => {
+                peer_creds.verify_credentials(creds, mode)?
+            }
+            (Some(_), Peer::Unix(None)) => return Err(AuthError::BadCredentials),
+            _ => creds,
+        };
+
+        let creds = match &self.groups {
+            Some(r) => match r.groups(creds.uid()) {
+                Some(gids) => UnixCredentials::new(creds.uid(), creds.gid(), gids),
+                None => creds,
+            },
+            None => creds,
+        };
+
+        let creds = match &self.squash {
+            Some(p) => p.squash(peer.ip().unwrap_or(Ipv4Addr::LOCALHOST.into()), creds),
+            None => creds,
+        };
+
+        Ok(Principal::Unix(creds))
+    }
+}
+
+impl Authenticator for UnixAuthenticator {
+    fn authenticate(&self, req: &AuthRequest<'_>) -> Result<Authenticated, AuthError> {
+        match (req.credentials(), &self.short) {
+            (AuthFlavor::AuthUnix(p), short) => {
+                check_none_verifier(req)?;
+                let principal = self.principal(req.peer(), p.into())?;
+
+                // Only issue a handle once the credentials are accepted.
+                let verifier = match short {
+                    Some(cache) => cache.issue(p),
+                    None => AuthFlavor::AuthNone(None),
+                };
+                Ok(Authenticated::new(principal, verifier))
+            }
+            (AuthFlavor::AuthShort(h), Some(cache)) => {
+                check_none_verifier(req)?;
+                let p = cache.resolve(h)?;
+                Ok(Authenticated::new(
+                    self.principal(req.peer(), UnixCredentials::from(&p))?,
+                    AuthFlavor::AuthNone(None),
+                ))
+            }
+            _ => Err(AuthError::BadCredentials),
+        }
+    }
+}
+
+impl Debug for UnixAuthenticator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UnixAuthenticator")
+            .field("peer_cred", &self.peer_cred)
+            .field("groups", &self.groups.is_some())
+            .field("squash", &self.squash)
+            .field("short", &self.short)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::auth::{AuthUnixParams, GroupFile, Squash, SquashRule};
+
+    fn call(creds: AuthFlavor<Vec<u8>>) -> CallBody<Vec<u8>, Vec<u8>> {
+        CallBody::new(100003, 3, 1, creds, AuthFlavor::AuthNone(None), vec![])
+    }
+
+    fn unix(uid: u32) -> AuthFlavor<Vec<u8>> {
+        AuthFlavor::AuthUnix(AuthUnixParams::new(0, b"host".to_vec(), uid, 20, [20]))
+    }
+
+    fn remote() -> Peer {
+        Peer::Inet("192.168.1.1:1234".parse().unwrap())
+    }
+
+    fn loopback() -> Peer {
+        Peer::Inet("127.0.0.1:1234".parse().unwrap())
+    }
+
+    /// A stand-in for a stronger auth flavor.
+    #[derive(Debug)]
+    struct TokenAuthenticator;
+
+    impl Authenticator for TokenAuthenticator {
+        fn authenticate(&self, req: &AuthRequest<'_>) -> Result<Authenticated, AuthError> {
+            match req.credentials() {
+                AuthFlavor::Unknown { id: 6, data } if *data == b"token" => Ok(Authenticated::new(
+                    Principal::Named {
+                        flavor: 6,
+                        name: "dom@EXAMPLE".to_string(),
+                    },
+                    AuthFlavor::AuthNone(None),
+                )),
+                AuthFlavor::Unknown { id: 6, .. } => Err(AuthError::BadCredentials),
+                _ => Err(AuthError::TooWeak),
+            }
+        }
+    }
+
+    #[test]
+    fn test_none() {
+        let c = call(AuthFlavor::AuthNone(None));
+        let got = NoneAuthenticator
+            .authenticate(&AuthRequest::new(&remote(), &c))
+            .expect("accept");
+        assert_eq!(got.principal(), &Principal::Anonymous);
+        assert_eq!(got.verifier(), &AuthFlavor::AuthNone(None));
+
+        let c = call(unix(501));
+        assert_eq!(
+            NoneAuthenticator.authenticate(&AuthRequest::new(&remote(), &c)),
+            Err(AuthError::BadCredentials)
+        );
+    }
+
+    #[test]
+    fn test_bad_verifier() {
+        let garbage = AuthFlavor::Unknown {
+            id: 42,
+            data: b"garbage".to_vec(),
+        };
+
+        let c = CallBody::new(100003, 3, 1, unix(501), garbage.clone(), vec![]);
+        let peer = remote();
+        let req = AuthRequest::new(&peer, &c);
+        assert_eq!(
+            UnixAuthenticator::default().authenticate(&req),
+            Err(AuthError::BadVerifier)
+        );
+
+        // The specific error is returned over the rejection of an
+        // authenticator not handling the flavor, in either order.
+        assert_eq!(
+            UnixAuthenticator::default()
+                .or(NoneAuthenticator)
+                .authenticate(&req),
+            Err(AuthError::BadVerifier)
+        );
+        assert_eq!(
+            NoneAuthenticator
+                .or(UnixAuthenticator::default())
+                .authenticate(&req),
+            Err(AuthError::BadVerifier)
+        );
+
+        let c = CallBody::new(100003, 3, 1, AuthFlavor::AuthNone(None), garbage, vec![]);
+        assert_eq!(
+            NoneAuthenticator.authenticate(&AuthRequest::new(&remote(), &c)),
+            Err(AuthError::BadVerifier)
+        );
+    }
+
+    #[test]
+    fn test_unix() {
+        let auth = UnixAuthenticator::default();
+
+        let c = call(unix(501));
+        let got = auth
+            .authenticate(&AuthRequest::new(&remote(), &c))
+            .expect("accept");
+        assert_eq!(
+            got.principal(),
+            &Principal::Unix(UnixCredentials::new(501, 20, [20]))
+        );
+
+        let c = call(AuthFlavor::AuthNone(None));
+        assert_eq!(
+            auth.authenticate(&AuthRequest::new(&remote(), &c)),
+            Err(AuthError::BadCredentials)
+        );
+
+        // AUTH_SHORT is rejected without a handle cache.
+        let c = call(AuthFlavor::AuthShort(vec![1, 2, 3]));
+        assert_eq!(
+            auth.authenticate(&AuthRequest::new(&remote(), &c)),
+            Err(AuthError::BadCredentials)
+        );
+    }
+
+    #[test]
+    fn test_unix_groups_and_squash() {
+        let auth = UnixAuthenticator::default()
+            .with_group_resolver(GroupFile::parse(
+                "root:x:0:0::/:/bin/sh\ndom:x:501:20::/:/bin/sh",
+                "admin:x:80:dom",
+            ))
+            .with_squash_policy(
+                SquashPolicy::new(SquashRule::new(Squash::Root)).with_client_rule(
+                    "127.0.0.0".parse().unwrap(),
+                    8,
+                    SquashRule::new(Squash::None),
+                ),
+            );
+
+        let c = call(unix(501));
+        let got = auth.authenticate(&AuthRequest::new(&remote(), &c)).unwrap();
+        assert_eq!(
+            got.principal(),
+            &Principal::Unix(UnixCredentials::new(501, 20, [20, 80]))
+        );
+
+        // Root is squashed remotely, but not locally.
+        let c = call(unix(0));
+        let got = auth.authenticate(&AuthRequest::new(&remote(), &c)).unwrap();
+        assert!(matches!(got.principal(), Principal::Unix(c) if c.uid() == 65534));
+
+        let got = auth
+            .authenticate(&AuthRequest::new(&loopback(), &c))
+            .unwrap();
+        assert!(matches!(got.principal(), Principal::Unix(c) if c.uid() == 0));
+
+        let got = auth
+            .authenticate(&AuthRequest::new(&Peer::Unix(None), &c))
+            .unwrap();
+        assert!(matches!(got.principal(), Principal::Unix(c) if c.uid() == 0));
+    }
+
+    #[test]
+    fn test_unix_short_handles() {
+        let cache = Arc::new(ShortHandleCache::new(10, Duration::from_secs(60)));
+        let auth = UnixAuthenticator::default().with_short_handles(Arc::clone(&cache));
+
+        let c = call(unix(501));
+        let (principal, verifier) = auth
+            .authenticate(&AuthRequest::new(&remote(), &c))
+            .unwrap()
+            .into_parts();
+        let AuthFlavor::AuthShort(handle) = verifier else {
+            panic!("expected short handle verifier");
+        };
+
+        // Repeated AUTH_UNIX calls are given the same handle.
+        let again = auth
+            .authenticate(&AuthRequest::new(&remote(), &call(unix(501))))
+            .unwrap();
+        assert_eq!(again.verifier(), &AuthFlavor::AuthShort(handle.clone()));
+        assert_eq!(cache.len(), 1);
+
+        let c = call(AuthFlavor::AuthShort(handle.clone()));
+        let got = auth.authenticate(&AuthRequest::new(&remote(), &c)).unwrap();
+        assert_eq!(got.principal(), &principal);
+        assert_eq!(got.verifier(), &AuthFlavor::AuthNone(None));
+
+        // Revoked handles require the client to resend the full credentials.
+        cache.revoke(&handle);
+        assert_eq!(
+            auth.authenticate(&AuthRequest::new(&remote(), &c)),
+            Err(AuthError::RejectedCredentials)
+        );
+    }
+
+    #[test]
+    fn test_unix_peer_credentials() {
+        let peer = Peer::Unix(Some(PeerCredentials::new(None, 501, 20)));
+        let forged = call(unix(0));
+
+        let auth = UnixAuthenticator::default().with_peer_credentials(PeerCredMismatch::Reject);
+        let c = call(unix(501));
+        assert!(auth.authenticate(&AuthRequest::new(&peer, &c)).is_ok());
+        assert_eq!(
+            auth.authenticate(&AuthRequest::new(&peer, &forged)),
+            Err(AuthError::BadCredentials)
+        );
+
+        // Unverifiable Unix domain socket callers are rejected, IP callers are
+        // not checked.
+        assert_eq!(
+            auth.authenticate(&AuthRequest::new(&Peer::Unix(None), &c)),
+            Err(AuthError::BadCredentials)
+        );
+        assert!(auth
+            .authenticate(&AuthRequest::new(&remote(), &forged))
+            .is_ok());
+
+        let auth = UnixAuthenticator::default().with_peer_credentials(PeerCredMismatch::Replace);
+        let got = auth
+            .authenticate(&AuthRequest::new(&peer, &forged))
+            .unwrap();
+        assert_eq!(
+            got.principal(),
+            &Principal::Unix(UnixCredentials::new(501, 20, []))
+        );
+    }
+
+    #[test]
+    fn test_compose_loopback_unix_or_token() {
+        let auth = UnixAuthenticator::default()
+            .when(|req| req.peer().is_local())
+            .or(TokenAuthenticator);
+
+        // AUTH_UNIX is accepted from loopback.
+        let c = call(unix(501));
+        assert!(auth
+            .authenticate(&AuthRequest::new(&loopback(), &c))
+            .is_ok());
+
+        // But too weak from a remote peer.
+        assert_eq!(
+            auth.authenticate(&AuthRequest::new(&remote(), &c)),
+            Err(AuthError::TooWeak)
+        );
+
+        // The stronger flavor is accepted from anywhere.
+        let c = call(AuthFlavor::Unknown {
+            id: 6,
+            data: b"token".to_vec(),
+        });
+        let got = auth.authenticate(&AuthRequest::new(&remote(), &c)).unwrap();
+        assert!(matches!(
+            got.principal(),
+            Principal::Named { flavor: 6, .. }
+        ));
+
+        let c = call(AuthFlavor::Unknown {
+            id: 6,
+            data: b"forged".to_vec(),
+        });
+        assert_eq!(
+            auth.authenticate(&AuthRequest::new(&remote(), &c)),
+            Err(AuthError::BadCredentials)
+        );
+
+        // The composed authenticator is object safe.
+        let boxed: Box<dyn Authenticator> = Box::new(auth);
+        let c = call(unix(501));
+        assert!(boxed
+            .authenticate(&AuthRequest::new(&Peer::Unix(None), &c))
+            .is_ok());
+    }
+}
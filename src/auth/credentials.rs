@@ -0,0 +1,61 @@
+use crate::auth::AuthUnixParams;
+
+/// The effective Unix identity of a caller, used by a server for authorisation
+/// decisions.
+///
+/// Unlike [`AuthUnixParams`], which describes the identity exactly as sent on
+/// the wire (and is limited to 16 supplementary groups), `UnixCredentials` is
+/// the identity a server has decided to act as, after applying any group
+/// resolution or identity mapping policy.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct UnixCredentials {
+    uid: u32,
+    gid: u32,
+    gids: Vec<u32>,
+}
+
+impl UnixCredentials {
+    /// Construct a new set of credentials.
+    pub fn new(uid: u32, gid: u32, gids: impl IntoIterator<Item = u32>) -> Self {
+        Self {
+            uid,
+            gid,
+            gids: gids.into_iter().collect(),
+        }
+    }
+
+    /// The effective Unix user ID.
+    pub fn uid(&self) -> u32 {
+        self.uid
+    }
+
+    /// The effective primary Unix group ID.
+    pub fn gid(&self) -> u32 {
+        self.gid
+    }
+
+    /// The supplementary Unix group IDs, which may contain more than the 16
+    /// groups permitted on the wire.
+    pub fn gids(&self) -> &[u32] {
+        &self.gids
+    }
+
+    /// Returns true if `gid` is either the primary group, or one of the
+    /// supplementary groups.
+    pub fn in_group(&self, gid: u32) -> bool {
+        self.gid == gid || self.gids.contains(&gid)
+    }
+}
+
+impl<T> From<&AuthUnixParams<T>> for UnixCredentials
+where
+    T: AsRef<[u8]>,
+{
+    fn from(p: &AuthUnixParams<T>) -> Self {
+        Self::new(
+            p.uid(),
+            p.gid(),
+            p.gids().unwrap_or_default().iter().copied(),
+        )
+    }
+}
@@ -7,14 +7,28 @@ use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 
 use crate::{auth::AuthUnixParams, Error, Opaque};
 
-const AUTH_NONE: u32 = 0;
-const AUTH_UNIX: u32 = 1;
-const AUTH_SHORT: u32 = 2;
+pub(crate) const AUTH_NONE: u32 = 0;
+pub(crate) const AUTH_UNIX: u32 = 1;
+pub(crate) const AUTH_SHORT: u32 = 2;
+pub(crate) const AUTH_TLS: u32 = 7;
+
+/// The opaque verifier body sent by a server in reply to an [`AUTH_TLS`]
+/// probe to indicate it is willing to upgrade the connection to TLS.
+///
+/// See [RFC 9289 section 4.1](https://www.rfc-editor.org/rfc/rfc9289#section-4.1).
+///
+/// [`AUTH_TLS`]: AuthFlavor::AuthTls
+pub const STARTTLS_VERIFIER: &[u8] = b"STARTTLS";
 
 /// A set of basic auth flavor types
 /// [described](https://tools.ietf.org/html/rfc5531#section-8.2) in RFC 5531.
 ///
 /// The deprecated `AUTH_DH` is not supported, nor is GSS.
+///
+/// The `AUTH_TLS` flavor from [RFC 9289] is included to allow a client to
+/// probe a server for RPC-over-TLS support.
+///
+/// [RFC 9289]: https://www.rfc-editor.org/rfc/rfc9289
 #[non_exhaustive]
 #[derive(Debug, PartialEq, Clone)]
 pub enum AuthFlavor<T>
@@ -35,6 +49,13 @@ where
     /// The provided opaque auth payload must not exceed 200 bytes in length.
     AuthShort(T),
 
+    /// `AUTH_TLS`, used only as the credential of a NULL procedure call probing
+    /// the server for RPC-over-TLS support.
+    ///
+    /// The spec requires the credential body to be empty - a non-empty body is
+    /// rejected with [`Error::InvalidAuthData`] when deserialising.
+    AuthTls,
+
     /// An authentication credential unknown to this library, but possibly valid
     /// and acceptable by the server.
     Unknown {
@@ -57,6 +78,7 @@ impl<'a> AuthFlavor<&'a [u8]> {
             AUTH_NONE => AuthFlavor::new_none(r)?,
             AUTH_UNIX => AuthFlavor::new_unix(r)?,
             AUTH_SHORT => AuthFlavor::new_short(r)?,
+            AUTH_TLS => AuthFlavor::new_tls(r)?,
             // 3 => AuthFlavor::AuthDH,
             // 6 => AuthFlavor::RpcSecGSS,
             v => AuthFlavor::Unknown {
@@ -92,6 +114,14 @@ impl<'a> AuthFlavor<&'a [u8]> {
             Opaque::from_wire(r, 200)?.into_inner(),
         ))
     }
+
+    fn new_tls(r: &mut Cursor<&'a [u8]>) -> Result<Self, Error> {
+        if !Opaque::from_wire(r, 200)?.into_inner().is_empty() {
+            return Err(Error::InvalidAuthData);
+        }
+
+        Ok(AuthFlavor::AuthTls)
+    }
 }
 
 impl<T> AuthFlavor<T>
@@ -116,7 +146,7 @@ where
                 Opaque::from_user_payload(data).serialise_into(&mut buf)
             }
             // No payload has a length of 0.
-            Self::AuthNone(None) => {
+            Self::AuthNone(None) | Self::AuthTls => {
                 buf.write_u32::<BigEndian>(0)?;
                 Ok(())
             }
@@ -128,12 +158,43 @@ where
         }
     }
 
+    /// Returns a copy of this `AuthFlavor` borrowing the underlying byte
+    /// buffers.
+    pub fn as_borrowed(&self) -> AuthFlavor<&[u8]> {
+        match self {
+            Self::AuthNone(d) => AuthFlavor::AuthNone(d.as_ref().map(AsRef::as_ref)),
+            Self::AuthUnix(p) => AuthFlavor::AuthUnix(p.as_borrowed()),
+            Self::AuthShort(d) => AuthFlavor::AuthShort(d.as_ref()),
+            Self::AuthTls => AuthFlavor::AuthTls,
+            Self::Unknown { id, data } => AuthFlavor::Unknown {
+                id: *id,
+                data: data.as_ref(),
+            },
+        }
+    }
+
+    /// Returns a copy of this `AuthFlavor` owning copies of the underlying
+    /// byte buffers.
+    pub fn into_owned(self) -> AuthFlavor<Vec<u8>> {
+        match self {
+            Self::AuthNone(d) => AuthFlavor::AuthNone(d.map(|v| v.as_ref().to_vec())),
+            Self::AuthUnix(p) => AuthFlavor::AuthUnix(p.into_owned()),
+            Self::AuthShort(d) => AuthFlavor::AuthShort(d.as_ref().to_vec()),
+            Self::AuthTls => AuthFlavor::AuthTls,
+            Self::Unknown { id, data } => AuthFlavor::Unknown {
+                id,
+                data: data.as_ref().to_vec(),
+            },
+        }
+    }
+
     /// Returns the ID value used to identify the variant in the wire protocol.
     pub fn id(&self) -> u32 {
         match self {
             Self::AuthNone(_) => AUTH_NONE,
             Self::AuthUnix(_) => AUTH_UNIX,
             Self::AuthShort(_) => AUTH_SHORT,
+            Self::AuthTls => AUTH_TLS,
             Self::Unknown { id, data: _ } => *id,
         }
     }
@@ -142,7 +203,7 @@ where
     pub fn associated_data_len(&self) -> u32 {
         match self {
             Self::AuthNone(Some(d)) => d.as_ref().len() as u32,
-            Self::AuthNone(None) => 0,
+            Self::AuthNone(None) | Self::AuthTls => 0,
             Self::AuthUnix(p) => p.associated_data_len(),
             Self::AuthShort(d) => d.as_ref().len() as u32,
             Self::Unknown { id: _id, data } => data.as_ref().len() as u32,
@@ -160,7 +221,7 @@ where
         // Add the flavor size
         l += match self {
             #[allow(clippy::identity_op)]
-            Self::AuthNone(None) => {
+            Self::AuthNone(None) | Self::AuthTls => {
                 // length prefix u32 + data length
                 4 + 0
             }
@@ -209,6 +270,8 @@ impl TryFrom<crate::Bytes> for AuthFlavor<crate::Bytes> {
                 Self::AuthUnix(params)
             }
             AUTH_SHORT => Self::AuthShort(auth_data),
+            AUTH_TLS if auth_data.is_empty() => Self::AuthTls,
+            AUTH_TLS => return Err(Error::InvalidAuthData),
             // 3 => AuthFlavor::AuthDH,
             // 6 => AuthFlavor::RpcSecGSS,
             id => Self::Unknown {
@@ -230,7 +293,7 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_auth_unix_unaligned_machinename<'a>() {
+    fn test_auth_unix_unaligned_machinename() {
         #[rustfmt::skip]
         // Credentials
         //     Flavor: AUTH_UNIX (1)
@@ -246,7 +309,7 @@ mod tests {
             "0000000100000024000000000000000f4c4150544f502d315151425044474d00000000000000000000000000"
         );
 
-        let f: AuthFlavor<&'a [u8]> = RAW.as_ref().try_into().expect("failed to parse message");
+        let f: AuthFlavor<&[u8]> = RAW.as_ref().try_into().expect("failed to parse message");
         assert_eq!(f.serialised_len(), 44);
         assert_eq!(f.id(), AUTH_UNIX);
         assert_eq!(f.associated_data_len(), 27);
@@ -266,7 +329,7 @@ mod tests {
     }
 
     #[test]
-    fn test_auth_unix<'a>() {
+    fn test_auth_unix() {
         #[rustfmt::skip]
         // Credentials
         //     Flavor: AUTH_UNIX (1)
@@ -300,7 +363,7 @@ mod tests {
             02100000064000000cc000000fa0000018b0000018e0000018f"
         );
 
-        let f: AuthFlavor<&'a [u8]> = RAW.as_ref().try_into().expect("failed to parse message");
+        let f: AuthFlavor<&[u8]> = RAW.as_ref().try_into().expect("failed to parse message");
         assert_eq!(f.serialised_len(), 92);
         assert_eq!(f.id(), AUTH_UNIX);
         assert_eq!(f.associated_data_len(), 92 - 4 - 4 - 4 - 4); // - auth flavor - auth size - gids length - name length
@@ -320,7 +383,7 @@ mod tests {
     }
 
     #[test]
-    fn test_auth_none<'a>() {
+    fn test_auth_none() {
         const RAW: [u8; 92] = hex!(
             "
             00 00 00 00
@@ -330,7 +393,7 @@ mod tests {
             cc000000fa0000018b0000018e0000018f"
         );
 
-        let f: AuthFlavor<&'a [u8]> = RAW.as_ref().try_into().expect("failed to parse message");
+        let f: AuthFlavor<&[u8]> = RAW.as_ref().try_into().expect("failed to parse message");
         assert_eq!(f.serialised_len(), 92);
         assert_eq!(f.id(), AUTH_NONE);
         assert_eq!(f.associated_data_len(), 92 - 4 - 4);
@@ -344,7 +407,7 @@ mod tests {
     }
 
     #[test]
-    fn test_auth_short<'a>() {
+    fn test_auth_short() {
         const RAW: [u8; 92] = hex!(
             "
             00 00 00 02
@@ -354,7 +417,7 @@ mod tests {
             cc000000fa0000018b0000018e0000018f"
         );
 
-        let f: AuthFlavor<&'a [u8]> = RAW.as_ref().try_into().expect("failed to parse message");
+        let f: AuthFlavor<&[u8]> = RAW.as_ref().try_into().expect("failed to parse message");
         assert_eq!(f.serialised_len(), 92);
         assert_eq!(f.id(), AUTH_SHORT);
         assert_eq!(f.associated_data_len(), 92 - 4 - 4);
@@ -368,7 +431,7 @@ mod tests {
     }
 
     #[test]
-    fn test_auth_unknown<'a>() {
+    fn test_auth_unknown() {
         const RAW: [u8; 92] = hex!(
             "
             00 00 00 FF
@@ -378,7 +441,7 @@ mod tests {
             cc000000fa0000018b0000018e0000018f"
         );
 
-        let f: AuthFlavor<&'a [u8]> = RAW.as_ref().try_into().expect("failed to parse message");
+        let f: AuthFlavor<&[u8]> = RAW.as_ref().try_into().expect("failed to parse message");
         assert_eq!(f.serialised_len(), 92);
         assert_eq!(f.id(), 255);
         assert_eq!(f.associated_data_len(), 92 - 4 - 4);
@@ -391,4 +454,33 @@ mod tests {
         assert_eq!(id, f.id());
         assert_eq!(data.len(), f.associated_data_len() as usize);
     }
+
+    #[test]
+    fn test_auth_tls() {
+        const RAW: [u8; 8] = hex!("00000007 00000000");
+
+        let f: AuthFlavor<&[u8]> = RAW.as_ref().try_into().expect("failed to parse message");
+        assert_eq!(f, AuthFlavor::AuthTls);
+        assert_eq!(f.serialised_len(), 8);
+        assert_eq!(f.id(), AUTH_TLS);
+        assert_eq!(f.associated_data_len(), 0);
+
+        let mut c = Cursor::new(Vec::new());
+        f.serialise_into(&mut c).expect("serialise failed");
+        assert_eq!(c.into_inner().as_slice(), RAW.as_ref());
+    }
+
+    #[test]
+    fn test_auth_tls_with_body() {
+        const RAW: [u8; 12] = hex!("00000007 00000004 42424242");
+
+        let got = AuthFlavor::<&[u8]>::try_from(RAW.as_ref());
+        assert_eq!(got, Err(Error::InvalidAuthData));
+
+        #[cfg(feature = "bytes")]
+        {
+            let got = AuthFlavor::try_from(crate::Bytes::from_static(&RAW));
+            assert_eq!(got, Err(Error::InvalidAuthData));
+        }
+    }
 }
@@ -0,0 +1,356 @@
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::auth::{AuthUnixParams, UnixCredentials};
+
+/// A source of supplementary group membership for a Unix user ID.
+///
+/// Implementations are used by a [`GroupCache`] to replace the (at most 16)
+/// group IDs sent in [`AuthUnixParams`] with the full list of groups the user
+/// is a member of, in the same way as `rpc.mountd --manage-gids`.
+pub trait GroupResolver: Send + Sync {
+    /// Return the full list of group IDs `uid` is a member of, or `None` if
+    /// the user is not known to this resolver.
+    fn groups(&self, uid: u32) -> Option<Vec<u32>>;
+}
+
+impl<T> GroupResolver for Arc<T>
+where
+    T: GroupResolver + ?Sized,
+{
+    fn groups(&self, uid: u32) -> Option<Vec<u32>> {
+        (**self).groups(uid)
+    }
+}
+
+/// A [`GroupResolver`] backed by the contents of `/etc/passwd` and
+/// `/etc/group` formatted files.
+///
+/// The groups for a user are their primary group (from the passwd file),
+/// followed by every group listing the user's name as a member. Comments,
+/// blank lines and malformed entries are skipped.
+///
+/// The files are read once at construction time.
+#[derive(Debug, Clone, Default)]
+pub struct GroupFile {
+    groups: HashMap<u32, Vec<u32>>,
+}
+
+impl GroupFile {
+    /// Read and parse the passwd and group files at the given paths.
+    pub fn load(
+        passwd_path: impl AsRef<Path>,
+        group_path: impl AsRef<Path>,
+    ) -> Result<Self, std::io::Error> {
+        let passwd = std::fs::read_to_string(passwd_path)?;
+        let group = std::fs::read_to_string(group_path)?;
+        Ok(Self::parse(&passwd, &group))
+    }
+
+    /// Parse the contents of a passwd and group file.
+    pub fn parse(passwd: &str, group: &str) -> Self {
+        // Map of user name -> (uid, primary gid).
+        let users = entries(passwd)
+            .filter_map(|f| {
+                let uid = f.get(2)?.parse::<u32>().ok()?;
+                let gid = f.get(3)?.parse::<u32>().ok()?;
+                Some((f[0], (uid, gid)))
+            })
+            .collect::<HashMap<_, _>>();
+
+        let mut groups = users
+            .values()
+            .map(|&(uid, gid)| (uid, vec![gid]))
+            .collect::<HashMap<_, _>>();
+
+        for f in entries(group) {
+            let (Some(gid), Some(members)) = (f.get(2).and_then(|v| v.parse().ok()), f.get(3))
+            else {
+                continue;
+            };
+
+            for name in members.split(',').map(str::trim) {
+                let Some(&(uid, _)) = users.get(name) else {
+                    continue;
+                };
+                let list = groups.entry(uid).or_default();
+                if !list.contains(&gid) {
+                    list.push(gid);
+                }
+            }
+        }
+
+        Self { groups }
+    }
+}
+
+impl GroupResolver for GroupFile {
+    fn groups(&self, uid: u32) -> Option<Vec<u32>> {
+        self.groups.get(&uid).cloned()
+    }
+}
+
+/// Split a colon-delimited database file into the fields of each entry.
+fn entries(data: &str) -> impl Iterator<Item = Vec<&str>> {
+    data.lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(|l| l.split(':').collect::<Vec<_>>())
+        .filter(|f| f.len() >= 4)
+}
+
+/// A [`GroupResolver`] using the system name service switch (NSS), resolving
+/// groups with `getpwuid_r()` and `getgrouplist()`.
+#[cfg(all(feature = "nss", unix))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NssGroupResolver;
+
+#[cfg(all(feature = "nss", unix))]
+impl GroupResolver for NssGroupResolver {
+    fn groups(&self, uid: u32) -> Option<Vec<u32>> {
+        let user = uzers::get_user_by_uid(uid)?;
+        let groups = uzers::get_user_groups(user.name(), user.primary_group_id())?;
+        Some(groups.iter().map(|g| g.gid()).collect())
+    }
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    groups: Option<Arc<[u32]>>,
+    resolved_at: Instant,
+}
+
+/// A TTL cache of group memberships resolved by a [`GroupResolver`], mapping
+/// decoded [`AuthUnixParams`] to the [`UnixCredentials`] a server should act
+/// as.
+///
+/// The `AUTH_UNIX` credential limits callers to 16 supplementary groups,
+/// resulting in incorrect permission checks for users in more groups.
+/// [`GroupCache::effective_credentials()`] replaces the groups sent on the wire
+/// with the full list from the resolver, keeping the wire UID and primary
+/// GID. If the resolver does not know the user, the wire groups are used.
+///
+/// Both positive and negative lookups are cached for the configured TTL. Once
+/// the cache holds `capacity` users, expired entries are dropped, followed by
+/// the oldest entry if necessary.
+#[derive(Debug)]
+pub struct GroupCache<R> {
+    resolver: R,
+    ttl: Duration,
+    capacity: usize,
+    entries: Mutex<HashMap<u32, CacheEntry>>,
+}
+
+impl<R> GroupCache<R>
+where
+    R: GroupResolver,
+{
+    /// Construct a cache of at most `capacity` users, resolving groups with
+    /// `resolver` and caching the result for `ttl`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn new(resolver: R, capacity: usize, ttl: Duration) -> Self {
+        assert!(capacity > 0, "group cache capacity must be non-zero");
+
+        Self {
+            resolver,
+            ttl,
+            capacity,
+            entries: Mutex::default(),
+        }
+    }
+
+    /// Return the full list of groups for `uid`, using the cached value if it
+    /// has not expired.
+    pub fn groups(&self, uid: u32) -> Option<Arc<[u32]>> {
+        self.groups_at(uid, Instant::now())
+    }
+
+    /// Map the decoded `params` to the credentials a request should be
+    /// authorised as, replacing the wire groups with the resolved groups.
+    pub fn effective_credentials<T>(&self, params: &AuthUnixParams<T>) -> UnixCredentials
+    where
+        T: AsRef<[u8]>,
+    {
+        match self.groups(params.uid()) {
+            Some(groups) => {
+                UnixCredentials::new(params.uid(), params.gid(), groups.iter().copied())
+            }
+            None => UnixCredentials::from(params),
+        }
+    }
+
+    /// Discard any cached groups for `uid`.
+    pub fn invalidate(&self, uid: u32) {
+        self.entries.lock().unwrap().remove(&uid);
+    }
+
+    fn groups_at(&self, uid: u32, now: Instant) -> Option<Arc<[u32]>> {
+        if let Some(e) = self.entries.lock().unwrap().get(&uid) {
+            if now.duration_since(e.resolved_at) < self.ttl {
+                return e.groups.clone();
+            }
+        }
+
+        // Resolve without holding the lock, as the resolver may block.
+        let groups = self.resolver.groups(uid).map(Arc::from);
+
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.capacity && !entries.contains_key(&uid) {
+            entries.retain(|_, e| now.duration_since(e.resolved_at) < self.ttl);
+
+            if entries.len() >= self.capacity {
+                if let Some(oldest) = entries
+                    .iter()
+                    .min_by_key(|(_, e)| e.resolved_at)
+                    .map(|(uid, _)| *uid)
+                {
+                    entries.remove(&oldest);
+                }
+            }
+        }
+        entries.insert(
+            uid,
+            CacheEntry {
+                groups: groups.clone(),
+                resolved_at: now,
+            },
+        );
+
+        groups
+    }
+}
+
+impl<R> GroupResolver for GroupCache<R>
+where
+    R: GroupResolver,
+{
+    fn groups(&self, uid: u32) -> Option<Vec<u32>> {
+        Self::groups(self, uid).map(|g| g.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    const PASSWD: &str = "
+# comment
+root:x:0:0:root:/root:/bin/bash
+dom:x:501:20:Dom:/home/dom:/bin/zsh
+bob:x:502:502::/home/bob:/bin/sh
+broken:x:notanumber:1::/:/bin/false
+";
+
+    const GROUP: &str = "
+wheel:x:0:root
+staff:x:20:
+admin:x:80:dom,bob
+docker:x:999:dom
+bob:x:502:
+bad
+";
+
+    #[test]
+    fn test_group_file() {
+        let f = GroupFile::parse(PASSWD, GROUP);
+
+        assert_eq!(f.groups(0), Some(vec![0]));
+        assert_eq!(f.groups(501), Some(vec![20, 80, 999]));
+        assert_eq!(f.groups(502), Some(vec![502, 80]));
+        assert_eq!(f.groups(42), None);
+    }
+
+    /// A resolver returning more groups than fit in `AUTH_UNIX`, counting the
+    /// number of lookups.
+    #[derive(Debug, Default)]
+    struct CountingResolver {
+        calls: AtomicUsize,
+    }
+
+    impl GroupResolver for CountingResolver {
+        fn groups(&self, uid: u32) -> Option<Vec<u32>> {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            (uid == 501).then(|| (1000..1020).collect())
+        }
+    }
+
+    #[test]
+    fn test_effective_credentials() {
+        let cache = GroupCache::new(CountingResolver::default(), 10, Duration::from_secs(60));
+
+        let params = AuthUnixParams::new(0, b"".as_slice(), 501, 20, [1, 2, 3]);
+        let creds = cache.effective_credentials(&params);
+        assert_eq!(creds.uid(), 501);
+        assert_eq!(creds.gid(), 20);
+        assert_eq!(creds.gids(), (1000..1020).collect::<Vec<_>>().as_slice());
+        assert!(creds.in_group(1019));
+        assert!(!creds.in_group(1));
+
+        // Unknown users fall back to the wire groups.
+        let params = AuthUnixParams::new(0, b"".as_slice(), 42, 20, [1, 2, 3]);
+        let creds = cache.effective_credentials(&params);
+        assert_eq!(creds.gids(), &[1, 2, 3]);
+    }
+
+    #[test]
+    fn test_cache_ttl() {
+        let cache = GroupCache::new(CountingResolver::default(), 10, Duration::from_secs(60));
+        let now = Instant::now();
+
+        assert!(cache.groups_at(501, now).is_some());
+        assert!(cache
+            .groups_at(501, now + Duration::from_secs(59))
+            .is_some());
+        assert_eq!(cache.resolver.calls.load(Ordering::Relaxed), 1);
+
+        // Negative results are cached too.
+        assert!(cache.groups_at(42, now).is_none());
+        assert!(cache.groups_at(42, now).is_none());
+        assert_eq!(cache.resolver.calls.load(Ordering::Relaxed), 2);
+
+        // Expired entries are resolved again.
+        assert!(cache
+            .groups_at(501, now + Duration::from_secs(60))
+            .is_some());
+        assert_eq!(cache.resolver.calls.load(Ordering::Relaxed), 3);
+
+        cache.invalidate(501);
+        assert!(cache
+            .groups_at(501, now + Duration::from_secs(61))
+            .is_some());
+        assert_eq!(cache.resolver.calls.load(Ordering::Relaxed), 4);
+    }
+
+    #[test]
+    fn test_cache_capacity() {
+        let cache = GroupCache::new(CountingResolver::default(), 2, Duration::from_secs(60));
+        let now = Instant::now();
+
+        cache.groups_at(1, now);
+        cache.groups_at(2, now + Duration::from_secs(1));
+        cache.groups_at(3, now + Duration::from_secs(2));
+
+        let entries = cache.entries.lock().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(!entries.contains_key(&1));
+    }
+
+    #[test]
+    #[cfg(all(feature = "nss", target_os = "linux"))]
+    fn test_nss_current_user() {
+        let uid = rustix::process::getuid().as_raw();
+        let groups = NssGroupResolver
+            .groups(uid)
+            .expect("current user must resolve");
+        assert!(!groups.is_empty());
+    }
+}
@@ -1,7 +1,22 @@
-//! A set of basic auth flavors specified in RFC 5531.
+//! A set of basic auth flavors specified in RFC 5531, and server-side helpers
+//! for authenticating and mapping caller identities.
 
+mod authenticator;
+mod credentials;
 mod flavor;
+mod groups;
+mod peercred;
+mod registry;
+mod short;
+mod squash;
 mod unix_params;
 
+pub use authenticator::*;
+pub use credentials::*;
 pub use flavor::*;
+pub use groups::*;
+pub use peercred::*;
+pub use registry::*;
+pub use short::*;
+pub use squash::*;
 pub use unix_params::*;
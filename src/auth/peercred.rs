@@ -0,0 +1,146 @@
+use crate::{
+    auth::{AuthUnixParams, UnixCredentials},
+    AuthError,
+};
+
+/// The identity of the process at the other end of a Unix domain socket, as
+/// reported by the kernel (`SO_PEERCRED`).
+///
+/// The kernel records the effective user and group ID of the peer at the time
+/// the connection was established, and unlike the identity in
+/// [`AuthUnixParams`], these cannot be forged by the caller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PeerCredentials {
+    pid: Option<u32>,
+    uid: u32,
+    gid: u32,
+}
+
+impl PeerCredentials {
+    /// Construct a set of peer credentials.
+    pub fn new(pid: Option<u32>, uid: u32, gid: u32) -> Self {
+        Self { pid, uid, gid }
+    }
+
+    /// Read the `SO_PEERCRED` credentials of the peer connected to `socket`.
+    ///
+    /// Requires the `process` feature.
+    #[cfg(all(feature = "process", target_os = "linux"))]
+    pub fn from_socket(socket: &std::os::unix::net::UnixStream) -> Result<Self, std::io::Error> {
+        let cred = rustix::net::sockopt::socket_peercred(socket)?;
+        Ok(Self {
+            pid: u32::try_from(cred.pid.as_raw_pid()).ok(),
+            uid: cred.uid.as_raw(),
+            gid: cred.gid.as_raw(),
+        })
+    }
+
+    /// The process ID of the peer, if known.
+    pub fn pid(&self) -> Option<u32> {
+        self.pid
+    }
+
+    /// The effective user ID of the peer.
+    pub fn uid(&self) -> u32 {
+        self.uid
+    }
+
+    /// The effective group ID of the peer.
+    pub fn gid(&self) -> u32 {
+        self.gid
+    }
+
+    /// Check the identity claimed in `params` against the kernel-verified
+    /// identity of the peer.
+    ///
+    /// If the claimed UID and GID match the peer, the claimed credentials are
+    /// returned. Otherwise the action taken is determined by `on_mismatch`.
+    pub fn verify<T>(
+        &self,
+        params: &AuthUnixParams<T>,
+        on_mismatch: PeerCredMismatch,
+    ) -> Result<UnixCredentials, AuthError>
+    where
+        T: AsRef<[u8]>,
+    {
+        self.verify_credentials(UnixCredentials::from(params), on_mismatch)
+    }
+
+    pub(crate) fn verify_credentials(
+        &self,
+        creds: UnixCredentials,
+        on_mismatch: PeerCredMismatch,
+    ) -> Result<UnixCredentials, AuthError> {
+        if creds.uid() == self.uid && creds.gid() == self.gid {
+            return Ok(creds);
+        }
+
+        match on_mismatch {
+            PeerCredMismatch::Reject => Err(AuthError::BadCredentials),
+            PeerCredMismatch::Replace => Ok(UnixCredentials::new(self.uid, self.gid, [])),
+        }
+    }
+}
+
+/// The action taken when the identity claimed in an `AUTH_UNIX` credential
+/// does not match the [`PeerCredentials`] of the connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PeerCredMismatch {
+    /// Reject the call with [`AuthError::BadCredentials`].
+    #[default]
+    Reject,
+
+    /// Discard the claimed identity and act as the peer UID and GID instead.
+    ///
+    /// The claimed supplementary groups cannot be trusted either, and are
+    /// dropped - use a [`GroupResolver`](crate::auth::GroupResolver) to
+    /// resolve the groups of the peer.
+    Replace,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify() {
+        let peer = PeerCredentials::new(Some(42), 501, 20);
+
+        let params = AuthUnixParams::new(0, b"host".as_slice(), 501, 20, [20, 80]);
+        for mode in [PeerCredMismatch::Reject, PeerCredMismatch::Replace] {
+            assert_eq!(
+                peer.verify(&params, mode),
+                Ok(UnixCredentials::new(501, 20, [20, 80]))
+            );
+        }
+
+        // A forged UID.
+        let params = AuthUnixParams::new(0, b"host".as_slice(), 0, 20, [0]);
+        assert_eq!(
+            peer.verify(&params, PeerCredMismatch::Reject),
+            Err(AuthError::BadCredentials)
+        );
+        assert_eq!(
+            peer.verify(&params, PeerCredMismatch::Replace),
+            Ok(UnixCredentials::new(501, 20, []))
+        );
+
+        // A forged GID.
+        let params = AuthUnixParams::new(0, b"host".as_slice(), 501, 0, [20]);
+        assert_eq!(
+            peer.verify(&params, PeerCredMismatch::Reject),
+            Err(AuthError::BadCredentials)
+        );
+    }
+
+    #[test]
+    #[cfg(all(feature = "process", target_os = "linux"))]
+    fn test_from_socket() {
+        let (a, _b) = std::os::unix::net::UnixStream::pair().unwrap();
+        let got = PeerCredentials::from_socket(&a).unwrap();
+
+        assert_eq!(got.uid(), rustix::process::geteuid().as_raw());
+        assert_eq!(got.gid(), rustix::process::getegid().as_raw());
+        assert_eq!(got.pid(), Some(std::process::id()));
+    }
+}
@@ -0,0 +1,491 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    fmt::Debug,
+    io::Write,
+    sync::{Arc, OnceLock},
+};
+
+use crate::{
+    auth::{AuthFlavor, AUTH_NONE, AUTH_SHORT, AUTH_TLS, AUTH_UNIX},
+    pad_length, Error,
+};
+
+/// The flavor discriminants decoded by this crate, which never appear as
+/// [`AuthFlavor::Unknown`].
+const BUILTIN_FLAVORS: [u32; 4] = [AUTH_NONE, AUTH_UNIX, AUTH_SHORT, AUTH_TLS];
+
+/// The maximum length of the opaque body of an auth flavor.
+const MAX_BODY_LEN: usize = 200;
+
+/// A decoder and encoder for the opaque body of an auth flavor not defined by
+/// this crate.
+///
+/// Implementations are registered with a [`FlavorRegistry`] for a specific
+/// flavor number.
+pub trait FlavorCodec: Send + Sync + 'static {
+    /// The typed representation of the flavor body.
+    type Value: Debug + PartialEq + Send + Sync + 'static;
+
+    /// Decode the opaque flavor body (excluding the length prefix and padding).
+    fn decode(&self, data: &[u8]) -> Result<Self::Value, Error>;
+
+    /// Encode `value` into `buf`, producing the opaque flavor body (excluding
+    /// the length prefix and padding).
+    fn encode(&self, value: &Self::Value, buf: &mut Vec<u8>);
+}
+
+/// An object-safe wrapper over a [`FlavorCodec`] operating on type-erased
+/// values.
+trait ErasedCodec: Send + Sync {
+    fn decode(&self, data: &[u8]) -> Result<Box<dyn Any + Send + Sync>, Error>;
+    fn encode(&self, value: &dyn Any, buf: &mut Vec<u8>);
+    fn eq(&self, a: &dyn Any, b: &dyn Any) -> bool;
+    fn debug(&self, value: &dyn Any, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result;
+    fn value_type(&self) -> TypeId;
+}
+
+impl<C> ErasedCodec for C
+where
+    C: FlavorCodec,
+{
+    fn decode(&self, data: &[u8]) -> Result<Box<dyn Any + Send + Sync>, Error> {
+        Ok(Box::new(FlavorCodec::decode(self, data)?))
+    }
+
+    fn encode(&self, value: &dyn Any, buf: &mut Vec<u8>) {
+        FlavorCodec::encode(self, downcast::<C>(value), buf)
+    }
+
+    fn eq(&self, a: &dyn Any, b: &dyn Any) -> bool {
+        downcast::<C>(a) == downcast::<C>(b)
+    }
+
+    fn debug(&self, value: &dyn Any, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        downcast::<C>(value).fmt(f)
+    }
+
+    fn value_type(&self) -> TypeId {
+        TypeId::of::<C::Value>()
+    }
+}
+
+/// Downcast `v`, which is always the codec's value type by construction.
+fn downcast<C: FlavorCodec>(v: &dyn Any) -> &C::Value {
+    v.downcast_ref()
+        .expect("custom flavor value does not match codec type")
+}
+
+/// A typed auth flavor decoded by a codec registered in a [`FlavorRegistry`].
+///
+/// The typed value can be accessed with [`CustomFlavor::downcast_ref()`], and
+/// converted back into an [`AuthFlavor::Unknown`] for serialisation with
+/// [`CustomFlavor::to_auth_flavor()`].
+///
+/// Two `CustomFlavor` instances are equal if they have the same flavor number
+/// and equal typed values.
+pub struct CustomFlavor {
+    id: u32,
+    value: Box<dyn Any + Send + Sync>,
+    codec: Arc<dyn ErasedCodec>,
+
+    /// The encoded opaque body, populated on first use.
+    body: OnceLock<Vec<u8>>,
+}
+
+impl CustomFlavor {
+    /// The flavor number of this auth flavor.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Returns a reference to the typed value if it is of type `V`.
+    pub fn downcast_ref<V: 'static>(&self) -> Option<&V> {
+        self.value.downcast_ref()
+    }
+
+    /// Returns the typed value if it is of type `V`, or `self` otherwise.
+    pub fn downcast<V: 'static>(self) -> Result<V, Self> {
+        if !self.value.is::<V>() {
+            return Err(self);
+        }
+        Ok(*self.value.downcast().expect("type checked above"))
+    }
+
+    /// The encoded opaque body of this flavor.
+    fn body(&self) -> &[u8] {
+        self.body.get_or_init(|| {
+            let mut buf = Vec::new();
+            self.codec.encode(&*self.value, &mut buf);
+            buf
+        })
+    }
+
+    /// Encode this flavor as an [`AuthFlavor::Unknown`] for serialisation.
+    ///
+    /// Returns [`Error::InvalidLength`] if the encoded body exceeds the 200
+    /// byte protocol limit.
+    pub fn to_auth_flavor(&self) -> Result<AuthFlavor<Vec<u8>>, Error> {
+        let data = self.body();
+        if data.len() > MAX_BODY_LEN {
+            return Err(Error::InvalidLength);
+        }
+
+        Ok(AuthFlavor::Unknown {
+            id: self.id,
+            data: data.to_vec(),
+        })
+    }
+
+    /// Serialises this flavor into `buf`, in the same wire format as
+    /// [`AuthFlavor::serialise_into()`].
+    pub fn serialise_into<W: Write>(&self, buf: W) -> Result<(), std::io::Error> {
+        self.to_auth_flavor()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?
+            .serialise_into(buf)
+    }
+
+    /// Returns the on-wire length of this flavor once serialised, including
+    /// discriminator and length values.
+    pub fn serialised_len(&self) -> u32 {
+        let len = self.body().len() as u32;
+        4 + 4 + len + pad_length(len)
+    }
+}
+
+impl PartialEq for CustomFlavor {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+            && self.codec.value_type() == other.codec.value_type()
+            && self.codec.eq(&*self.value, &*other.value)
+    }
+}
+
+impl Debug for CustomFlavor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        struct Value<'a>(&'a CustomFlavor);
+        impl Debug for Value<'_> {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                self.0.codec.debug(&*self.0.value, f)
+            }
+        }
+
+        f.debug_struct("CustomFlavor")
+            .field("id", &self.id)
+            .field("value", &Value(self))
+            .finish()
+    }
+}
+
+/// A set of [`FlavorCodec`] implementations for auth flavors not defined by
+/// this crate, keyed by flavor number.
+///
+/// Vendor and site-specific auth flavors are decoded by this crate as
+/// [`AuthFlavor::Unknown`]. A `FlavorRegistry` maps these to typed
+/// [`CustomFlavor`] values, and typed values back to an `AuthFlavor` for
+/// serialisation.
+///
+/// ```
+/// use onc_rpc::{
+///     auth::{AuthFlavor, FlavorCodec, FlavorRegistry},
+///     Error,
+/// };
+///
+/// #[derive(Debug, PartialEq)]
+/// struct Token(u32);
+///
+/// struct TokenCodec;
+///
+/// impl FlavorCodec for TokenCodec {
+///     type Value = Token;
+///
+///     fn decode(&self, data: &[u8]) -> Result<Token, Error> {
+///         let v = data.try_into().map_err(|_| Error::InvalidAuthData)?;
+///         Ok(Token(u32::from_be_bytes(v)))
+///     }
+///
+///     fn encode(&self, value: &Token, buf: &mut Vec<u8>) {
+///         buf.extend_from_slice(&value.0.to_be_bytes());
+///     }
+/// }
+///
+/// let mut registry = FlavorRegistry::default();
+/// registry.register(400_000, TokenCodec);
+///
+/// // A flavor as decoded from a message.
+/// let flavor = AuthFlavor::Unknown {
+///     id: 400_000,
+///     data: [0, 0, 0, 42].as_slice(),
+/// };
+///
+/// let custom = registry.decode(&flavor).unwrap().expect("registered flavor");
+/// assert_eq!(custom.downcast_ref::<Token>(), Some(&Token(42)));
+/// assert_eq!(custom.serialised_len(), flavor.serialised_len());
+/// ```
+#[derive(Default, Clone)]
+pub struct FlavorRegistry {
+    codecs: HashMap<u32, Arc<dyn ErasedCodec>>,
+}
+
+impl FlavorRegistry {
+    /// Register `codec` to decode and encode flavor number `id`, replacing any
+    /// codec previously registered for `id`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` is a flavor decoded by this crate (such as `AUTH_UNIX`),
+    /// which never appears as an [`AuthFlavor::Unknown`].
+    pub fn register<C: FlavorCodec>(&mut self, id: u32, codec: C) {
+        assert!(
+            !BUILTIN_FLAVORS.contains(&id),
+            "cannot register codec for built-in auth flavor {id}"
+        );
+        self.codecs.insert(id, Arc::new(codec));
+    }
+
+    /// Returns true if a codec is registered for flavor number `id`.
+    pub fn contains(&self, id: u32) -> bool {
+        self.codecs.contains_key(&id)
+    }
+
+    /// Decode `flavor` using the registered codec for its flavor number.
+    ///
+    /// Returns `Ok(None)` if `flavor` is not an [`AuthFlavor::Unknown`], or no
+    /// codec is registered for its flavor number. Errors returned by the codec
+    /// are propagated.
+    pub fn decode<T>(&self, flavor: &AuthFlavor<T>) -> Result<Option<CustomFlavor>, Error>
+    where
+        T: AsRef<[u8]>,
+    {
+        let AuthFlavor::Unknown { id, data } = flavor else {
+            return Ok(None);
+        };
+        let Some(codec) = self.codecs.get(id) else {
+            return Ok(None);
+        };
+
+        Ok(Some(CustomFlavor {
+            id: *id,
+            value: codec.decode(data.as_ref())?,
+            codec: Arc::clone(codec),
+            body: OnceLock::new(),
+        }))
+    }
+
+    /// Wrap `value` as a [`CustomFlavor`] with flavor number `id`.
+    ///
+    /// Returns `None` if no codec is registered for `id`, or the codec
+    /// registered for `id` does not encode values of type `V`.
+    pub fn wrap<V>(&self, id: u32, value: V) -> Option<CustomFlavor>
+    where
+        V: Send + Sync + 'static,
+    {
+        let codec = self.codecs.get(&id)?;
+        if codec.value_type() != TypeId::of::<V>() {
+            return None;
+        }
+
+        Some(CustomFlavor {
+            id,
+            value: Box::new(value),
+            codec: Arc::clone(codec),
+            body: OnceLock::new(),
+        })
+    }
+}
+
+impl Debug for FlavorRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut ids = self.codecs.keys().collect::<Vec<_>>();
+        ids.sort_unstable();
+        f.debug_struct("FlavorRegistry")
+            .field("flavors", &ids)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::TryFrom, io::Cursor};
+
+    use super::*;
+    use crate::{CallBody, MessageType, RpcMessage};
+
+    const FLAVOR_KEY: u32 = 390_042;
+
+    #[derive(Debug, PartialEq, Clone)]
+    struct KeyCred {
+        key_id: u32,
+        name: String,
+    }
+
+    struct KeyCodec;
+
+    impl FlavorCodec for KeyCodec {
+        type Value = KeyCred;
+
+        fn decode(&self, data: &[u8]) -> Result<KeyCred, Error> {
+            if data.len() < 4 {
+                return Err(Error::InvalidAuthData);
+            }
+            let key_id = u32::from_be_bytes(data[..4].try_into().unwrap());
+            let name = std::str::from_utf8(&data[4..])?.to_string();
+            Ok(KeyCred { key_id, name })
+        }
+
+        fn encode(&self, value: &KeyCred, buf: &mut Vec<u8>) {
+            buf.extend_from_slice(&value.key_id.to_be_bytes());
+            buf.extend_from_slice(value.name.as_bytes());
+        }
+    }
+
+    fn registry() -> FlavorRegistry {
+        let mut r = FlavorRegistry::default();
+        r.register(FLAVOR_KEY, KeyCodec);
+        r
+    }
+
+    fn cred() -> KeyCred {
+        KeyCred {
+            key_id: 42,
+            name: "bananas".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_round_trip_message() {
+        let r = registry();
+        let custom = r.wrap(FLAVOR_KEY, cred()).expect("registered");
+        let creds = custom.to_auth_flavor().expect("encode");
+        assert_eq!(custom.serialised_len(), creds.serialised_len());
+
+        let msg = RpcMessage::new(
+            1,
+            MessageType::Call(CallBody::new(
+                100000,
+                1,
+                0,
+                creds,
+                AuthFlavor::AuthNone(None),
+                vec![],
+            )),
+        );
+        let buf = msg.serialise().expect("serialise");
+
+        let msg = RpcMessage::try_from(buf.as_slice()).expect("parse");
+        let got = r
+            .decode(msg.call_body().unwrap().auth_credentials())
+            .expect("decode")
+            .expect("registered flavor");
+
+        assert_eq!(got.id(), FLAVOR_KEY);
+        assert_eq!(got.downcast_ref::<KeyCred>(), Some(&cred()));
+        assert_eq!(got, custom);
+
+        let mut c = Cursor::new(Vec::new());
+        got.serialise_into(&mut c).expect("serialise custom");
+        assert_eq!(c.into_inner().len(), got.serialised_len() as usize);
+
+        assert_eq!(got.downcast::<KeyCred>().ok(), Some(cred()));
+    }
+
+    #[test]
+    fn test_equality() {
+        let r = registry();
+        let a = r.wrap(FLAVOR_KEY, cred()).unwrap();
+        let b = r.wrap(FLAVOR_KEY, cred()).unwrap();
+        assert_eq!(a, b);
+
+        let mut other = cred();
+        other.key_id = 43;
+        let c = r.wrap(FLAVOR_KEY, other).unwrap();
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn test_decode_unregistered() {
+        let r = registry();
+
+        let flavor = AuthFlavor::Unknown {
+            id: 4242,
+            data: b"".as_slice(),
+        };
+        assert_eq!(r.decode(&flavor), Ok(None));
+        assert_eq!(r.decode(&AuthFlavor::<&[u8]>::AuthNone(None)), Ok(None));
+    }
+
+    #[test]
+    fn test_decode_error() {
+        let r = registry();
+
+        let flavor = AuthFlavor::Unknown {
+            id: FLAVOR_KEY,
+            data: b"\x00".as_slice(),
+        };
+        assert_eq!(r.decode(&flavor), Err(Error::InvalidAuthData));
+    }
+
+    #[test]
+    fn test_wrap_wrong_type() {
+        let r = registry();
+        assert!(r.wrap(FLAVOR_KEY, 42_u32).is_none());
+        assert!(r.wrap(4242, cred()).is_none());
+    }
+
+    #[test]
+    fn test_encode_too_long() {
+        let r = registry();
+        let custom = r
+            .wrap(
+                FLAVOR_KEY,
+                KeyCred {
+                    key_id: 1,
+                    name: "a".repeat(200),
+                },
+            )
+            .unwrap();
+
+        assert_eq!(custom.to_auth_flavor(), Err(Error::InvalidLength));
+        assert!(custom.serialise_into(Vec::new()).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "built-in auth flavor")]
+    fn test_register_builtin() {
+        FlavorRegistry::default().register(1, KeyCodec);
+    }
+
+    #[test]
+    fn test_body_encoded_once() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static ENCODED: AtomicUsize = AtomicUsize::new(0);
+
+        struct CountingCodec;
+
+        impl FlavorCodec for CountingCodec {
+            type Value = KeyCred;
+
+            fn decode(&self, data: &[u8]) -> Result<KeyCred, Error> {
+                FlavorCodec::decode(&KeyCodec, data)
+            }
+
+            fn encode(&self, value: &KeyCred, buf: &mut Vec<u8>) {
+                ENCODED.fetch_add(1, Ordering::Relaxed);
+                FlavorCodec::encode(&KeyCodec, value, buf)
+            }
+        }
+
+        let mut r = FlavorRegistry::default();
+        r.register(FLAVOR_KEY, CountingCodec);
+        let custom = r.wrap(FLAVOR_KEY, cred()).expect("registered");
+
+        let mut buf = Vec::new();
+        for _ in 0..3 {
+            buf.clear();
+            custom.serialise_into(&mut buf).expect("serialise");
+            assert_eq!(custom.serialised_len() as usize, buf.len());
+        }
+        assert_eq!(ENCODED.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,397 @@
+use std::{
+    collections::{hash_map::RandomState, HashMap, VecDeque},
+    hash::{BuildHasher, Hasher},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    auth::{AuthFlavor, AuthUnixParams},
+    AuthError,
+};
+
+/// The length of the opaque handles issued by a [`ShortHandleCache`].
+const HANDLE_LEN: usize = 16;
+
+type Handle = [u8; HANDLE_LEN];
+
+#[derive(Debug)]
+struct Entry {
+    params: AuthUnixParams<Vec<u8>>,
+    issued_at: Instant,
+
+    /// The wire encoding of `params`, the key of this entry in
+    /// [`State::handles`].
+    key: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    entries: HashMap<Handle, Entry>,
+
+    /// The handle issued for each set of credentials, keyed by their wire
+    /// encoding.
+    handles: HashMap<Vec<u8>, Handle>,
+
+    /// Handles in issue order, used to evict the oldest entries first.
+    ///
+    /// This may contain handles that have since been revoked or replaced,
+    /// which are skipped when evicting.
+    order: VecDeque<(Instant, Handle)>,
+
+    /// A monotonic counter mixed into each issued handle.
+    next_id: u64,
+}
+
+impl State {
+    /// Remove the entry for `handle`, if any.
+    fn remove(&mut self, handle: &Handle) -> Option<Entry> {
+        let entry = self.entries.remove(handle)?;
+
+        // Leave the mapping to a newer handle for the same credentials.
+        if self.handles.get(&entry.key) == Some(handle) {
+            self.handles.remove(&entry.key);
+        }
+        Some(entry)
+    }
+}
+
+/// A server-side cache issuing and resolving [`AuthFlavor::AuthShort`] handles.
+///
+/// After successfully authenticating a call bearing
+/// [`AuthFlavor::AuthUnix`] credentials, a server may [`issue()`] a short
+/// handle and return it in the reply verifier. Clients then send the handle as
+/// an `AUTH_SHORT` credential in subsequent calls, which the server
+/// [`resolve()`]s back to the original [`AuthUnixParams`].
+///
+/// Issuing a handle for credentials that already hold a live handle returns the
+/// existing handle, so clients repeatedly sending the same `AUTH_UNIX`
+/// credentials do not fill the cache and evict the handles of other clients.
+///
+/// Handles expire once they are older than the configured TTL, and the oldest
+/// handles are evicted once the cache reaches its capacity. Unknown, evicted
+/// and expired handles are rejected with [`AuthError::RejectedCredentials`],
+/// which instructs the client to fall back to sending the full `AUTH_UNIX`
+/// credentials.
+///
+/// Handles are opaque, unpredictable byte strings but, like `AUTH_UNIX`
+/// itself, provide no actual security.
+///
+/// [`issue()`]: ShortHandleCache::issue
+/// [`resolve()`]: ShortHandleCache::resolve
+#[derive(Debug)]
+pub struct ShortHandleCache {
+    state: Mutex<State>,
+    capacity: usize,
+    ttl: Duration,
+    hasher: RandomState,
+}
+
+impl ShortHandleCache {
+    /// Construct a cache holding at most `capacity` handles, each valid for
+    /// `ttl` after being issued.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        assert!(capacity > 0, "short handle cache capacity must be non-zero");
+
+        Self {
+            state: Mutex::default(),
+            capacity,
+            ttl,
+            hasher: RandomState::new(),
+        }
+    }
+
+    /// Return a short handle for `params` as the [`AuthFlavor::AuthShort`]
+    /// verifier to send in the reply.
+    ///
+    /// If a live handle was previously issued for identical `params`, that
+    /// handle is returned rather than minting a new one.
+    pub fn issue<T>(&self, params: &AuthUnixParams<T>) -> AuthFlavor<Vec<u8>>
+    where
+        T: AsRef<[u8]>,
+    {
+        self.issue_at(params, Instant::now())
+    }
+
+    /// Resolve a short handle sent by a client back to the [`AuthUnixParams`]
+    /// it was issued for.
+    ///
+    /// Returns [`AuthError::RejectedCredentials`] if the handle is unknown, or
+    /// has expired or been evicted.
+    pub fn resolve(&self, handle: &[u8]) -> Result<AuthUnixParams<Vec<u8>>, AuthError> {
+        self.resolve_at(handle, Instant::now())
+    }
+
+    /// Invalidate `handle`, causing subsequent use to be rejected.
+    ///
+    /// Returns `true` if the handle was valid.
+    pub fn revoke(&self, handle: &[u8]) -> bool {
+        let Ok(handle) = Handle::try_from(handle) else {
+            return false;
+        };
+        self.state.lock().unwrap().remove(&handle).is_some()
+    }
+
+    /// Returns the number of handles currently held, including any that have
+    /// expired but have not yet been evicted.
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().entries.len()
+    }
+
+    /// Returns `true` if the cache holds no handles.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn issue_at<T>(&self, params: &AuthUnixParams<T>, now: Instant) -> AuthFlavor<Vec<u8>>
+    where
+        T: AsRef<[u8]>,
+    {
+        let mut key = Vec::with_capacity(params.serialised_len() as usize);
+        params.serialise_into(&mut key).expect("serialise into vec");
+
+        let mut state = self.state.lock().unwrap();
+
+        // Reuse the handle already issued for these credentials, if it has not
+        // expired (expired handles are removed by the eviction below).
+        if let Some(handle) = state.handles.get(&key) {
+            if now.duration_since(state.entries[handle].issued_at) < self.ttl {
+                return AuthFlavor::AuthShort(handle.to_vec());
+            }
+        }
+
+        self.evict(&mut state, now);
+
+        // The handle is a unique counter value, followed by a keyed hash of it
+        // to make handles impractical to guess.
+        let id = state.next_id;
+        state.next_id = state.next_id.wrapping_add(1);
+
+        let mut h = self.hasher.build_hasher();
+        h.write_u64(id);
+
+        let mut handle = [0; HANDLE_LEN];
+        handle[..8].copy_from_slice(&id.to_be_bytes());
+        handle[8..].copy_from_slice(&h.finish().to_be_bytes());
+
+        state.entries.insert(
+            handle,
+            Entry {
+                params: params.as_borrowed().into_owned(),
+                issued_at: now,
+                key: key.clone(),
+            },
+        );
+        state.handles.insert(key, handle);
+        state.order.push_back((now, handle));
+
+        AuthFlavor::AuthShort(handle.to_vec())
+    }
+
+    fn resolve_at(
+        &self,
+        handle: &[u8],
+        now: Instant,
+    ) -> Result<AuthUnixParams<Vec<u8>>, AuthError> {
+        let handle = Handle::try_from(handle).map_err(|_| AuthError::RejectedCredentials)?;
+
+        let mut state = self.state.lock().unwrap();
+        match state.entries.get(&handle) {
+            Some(e) if now.duration_since(e.issued_at) < self.ttl => Ok(e.params.clone()),
+            Some(_) => {
+                state.remove(&handle);
+                Err(AuthError::RejectedCredentials)
+            }
+            None => Err(AuthError::RejectedCredentials),
+        }
+    }
+
+    /// Remove expired entries, and the oldest entries beyond the capacity limit
+    /// (leaving room for one more insert).
+    fn evict(&self, state: &mut State, now: Instant) {
+        while let Some(&(issued_at, handle)) = state.order.front() {
+            let expired = now.duration_since(issued_at) >= self.ttl;
+            if !expired && state.entries.len() < self.capacity {
+                break;
+            }
+
+            state.order.pop_front();
+
+            // Only remove the entry if it is the one this queue element was
+            // pushed for.
+            if state
+                .entries
+                .get(&handle)
+                .is_some_and(|e| e.issued_at == issued_at)
+            {
+                state.remove(&handle);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(uid: u32) -> AuthUnixParams<&'static [u8]> {
+        AuthUnixParams::new(42, b"bananas.local".as_slice(), uid, 20, [20, 501])
+    }
+
+    fn handle(f: AuthFlavor<Vec<u8>>) -> Vec<u8> {
+        match f {
+            AuthFlavor::AuthShort(h) => h,
+            v => panic!("unexpected verifier {:?}", v),
+        }
+    }
+
+    #[test]
+    fn test_issue_resolve() {
+        let cache = ShortHandleCache::new(10, Duration::from_secs(60));
+
+        let a = handle(cache.issue(&params(501)));
+        let b = handle(cache.issue(&params(502)));
+        assert_ne!(a, b);
+        assert_eq!(cache.len(), 2);
+
+        let got = cache.resolve(&a).expect("resolve a");
+        assert_eq!(got.uid(), 501);
+        assert_eq!(got.machine_name_str(), "bananas.local");
+        assert_eq!(got.gids(), Some([20, 501].as_slice()));
+
+        assert_eq!(cache.resolve(&b).expect("resolve b").uid(), 502);
+    }
+
+    #[test]
+    fn test_unknown_handle() {
+        let cache = ShortHandleCache::new(10, Duration::from_secs(60));
+        let mut h = handle(cache.issue(&params(501)));
+
+        // Tamper with the handle.
+        h[15] ^= 0xFF;
+        assert_eq!(cache.resolve(&h), Err(AuthError::RejectedCredentials));
+
+        // Wrong length.
+        assert_eq!(cache.resolve(b"short"), Err(AuthError::RejectedCredentials));
+    }
+
+    #[test]
+    fn test_ttl_expiry() {
+        let cache = ShortHandleCache::new(10, Duration::from_secs(60));
+        let now = Instant::now();
+
+        let h = handle(cache.issue_at(&params(501), now));
+        assert!(cache.resolve_at(&h, now + Duration::from_secs(59)).is_ok());
+        assert_eq!(
+            cache.resolve_at(&h, now + Duration::from_secs(60)),
+            Err(AuthError::RejectedCredentials)
+        );
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_expired_evicted_on_issue() {
+        let cache = ShortHandleCache::new(10, Duration::from_secs(60));
+        let now = Instant::now();
+
+        cache.issue_at(&params(501), now);
+        cache.issue_at(&params(502), now + Duration::from_secs(30));
+        assert_eq!(cache.len(), 2);
+
+        cache.issue_at(&params(503), now + Duration::from_secs(61));
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_capacity_eviction() {
+        let cache = ShortHandleCache::new(2, Duration::from_secs(60));
+
+        let a = handle(cache.issue(&params(1)));
+        let b = handle(cache.issue(&params(2)));
+        let c = handle(cache.issue(&params(3)));
+        assert_eq!(cache.len(), 2);
+
+        // The oldest handle is evicted first.
+        assert_eq!(cache.resolve(&a), Err(AuthError::RejectedCredentials));
+        assert_eq!(cache.resolve(&b).unwrap().uid(), 2);
+        assert_eq!(cache.resolve(&c).unwrap().uid(), 3);
+    }
+
+    #[test]
+    fn test_revoke() {
+        let cache = ShortHandleCache::new(2, Duration::from_secs(60));
+
+        let a = handle(cache.issue(&params(1)));
+        assert!(cache.revoke(&a));
+        assert!(!cache.revoke(&a));
+        assert_eq!(cache.resolve(&a), Err(AuthError::RejectedCredentials));
+
+        // Revoked handles do not count towards the capacity.
+        let b = handle(cache.issue(&params(2)));
+        let c = handle(cache.issue(&params(3)));
+        assert!(cache.resolve(&b).is_ok());
+        assert!(cache.resolve(&c).is_ok());
+    }
+
+    #[test]
+    fn test_reissue_reuses_handle() {
+        let cache = ShortHandleCache::new(2, Duration::from_secs(60));
+        let now = Instant::now();
+
+        let a = handle(cache.issue_at(&params(1), now));
+        let b = handle(cache.issue_at(&params(2), now));
+
+        // Repeatedly issuing for the same credentials returns the same handle,
+        // and does not evict the handles of other credentials.
+        for _ in 0..5 {
+            assert_eq!(handle(cache.issue_at(&params(1), now)), a);
+        }
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.resolve_at(&b, now).unwrap().uid(), 2);
+
+        // Once expired, a new handle is issued.
+        let later = now + Duration::from_secs(60);
+        let c = handle(cache.issue_at(&params(1), later));
+        assert_ne!(a, c);
+        assert_eq!(
+            cache.resolve_at(&a, later),
+            Err(AuthError::RejectedCredentials)
+        );
+        assert_eq!(cache.resolve_at(&c, later).unwrap().uid(), 1);
+
+        // A revoked handle is replaced.
+        assert!(cache.revoke(&c));
+        let d = handle(cache.issue_at(&params(1), later));
+        assert_ne!(c, d);
+        assert_eq!(cache.resolve_at(&d, later).unwrap().uid(), 1);
+    }
+
+    #[test]
+    fn test_remove_keeps_newer_mapping() {
+        let mut state = State::default();
+        let entry = |key: &[u8]| Entry {
+            params: params(1).as_borrowed().into_owned(),
+            issued_at: Instant::now(),
+            key: key.to_vec(),
+        };
+
+        // An old handle for the same credentials outlives its replacement
+        // being issued.
+        state.entries.insert([1; HANDLE_LEN], entry(b"creds"));
+        state.entries.insert([2; HANDLE_LEN], entry(b"creds"));
+        state.handles.insert(b"creds".to_vec(), [2; HANDLE_LEN]);
+
+        assert!(state.remove(&[1; HANDLE_LEN]).is_some());
+        assert_eq!(
+            state.handles.get(b"creds".as_slice()),
+            Some(&[2; HANDLE_LEN])
+        );
+
+        assert!(state.remove(&[2; HANDLE_LEN]).is_some());
+        assert!(state.handles.is_empty());
+    }
+}
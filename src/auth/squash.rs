@@ -0,0 +1,375 @@
+use std::net::IpAddr;
+
+use crate::auth::{AuthFlavor, UnixCredentials};
+
+/// The conventional "nobody" user and group ID used for anonymous callers.
+const NOBODY: u32 = 65534;
+
+/// The identity mapping mode applied by a [`SquashRule`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Squash {
+    /// Callers are authorised as the identity they claim, including root.
+    ///
+    /// This is `no_root_squash` in `exports(5)`.
+    None,
+
+    /// Callers claiming UID 0 or GID 0 are mapped to the anonymous identity.
+    ///
+    /// This is `root_squash` in `exports(5)`.
+    Root,
+
+    /// All callers are mapped to the anonymous identity.
+    ///
+    /// This is `all_squash` in `exports(5)`.
+    All,
+}
+
+/// A [`Squash`] mode, and the anonymous identity callers are mapped to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SquashRule {
+    squash: Squash,
+    anon_uid: u32,
+    anon_gid: u32,
+}
+
+impl SquashRule {
+    /// Construct a rule applying `squash`, mapping squashed callers to the
+    /// "nobody" user and group (65534).
+    pub fn new(squash: Squash) -> Self {
+        Self {
+            squash,
+            anon_uid: NOBODY,
+            anon_gid: NOBODY,
+        }
+    }
+
+    /// Map squashed callers to `anon_uid` and `anon_gid`.
+    ///
+    /// These are `anonuid` and `anongid` in `exports(5)`.
+    pub fn with_anonymous(self, anon_uid: u32, anon_gid: u32) -> Self {
+        Self {
+            anon_uid,
+            anon_gid,
+            ..self
+        }
+    }
+
+    /// The [`Squash`] mode of this rule.
+    pub fn squash(&self) -> Squash {
+        self.squash
+    }
+
+    /// The anonymous user and group ID squashed callers are mapped to.
+    pub fn anonymous(&self) -> UnixCredentials {
+        UnixCredentials::new(self.anon_uid, self.anon_gid, [])
+    }
+
+    /// Apply this rule to `creds`.
+    pub fn apply(&self, creds: UnixCredentials) -> UnixCredentials {
+        match self.squash {
+            Squash::None => creds,
+            Squash::All => self.anonymous(),
+            Squash::Root => {
+                let map_gid = |gid| if gid == 0 { self.anon_gid } else { gid };
+                let uid = if creds.uid() == 0 {
+                    self.anon_uid
+                } else {
+                    creds.uid()
+                };
+                UnixCredentials::new(
+                    uid,
+                    map_gid(creds.gid()),
+                    creds.gids().iter().copied().map(map_gid),
+                )
+            }
+        }
+    }
+}
+
+impl Default for SquashRule {
+    /// The `exports(5)` default of `root_squash` to the "nobody" identity.
+    fn default() -> Self {
+        Self::new(Squash::Root)
+    }
+}
+
+/// A client address range a [`SquashRule`] applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ClientMatch {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl ClientMatch {
+    fn matches(&self, addr: IpAddr) -> bool {
+        match (self.addr, addr.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(addr)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u32::from(net) & mask == u32::from(addr) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(addr)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u128::from(net) & mask == u128::from(addr) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// An identity squashing policy, mapping the credentials of incoming calls to
+/// the effective identity a server should authorise them as.
+///
+/// This implements the `root_squash`, `all_squash`, `anonuid` and `anongid`
+/// export semantics of NFS servers (see `exports(5)`), optionally varying by
+/// client address.
+///
+/// Rules added with [`SquashPolicy::with_client_rule()`] are evaluated in the
+/// order they were added, with the first rule matching the client address
+/// being applied. If no rule matches, the default rule is applied.
+///
+/// ```
+/// use onc_rpc::auth::{AuthFlavor, AuthUnixParams, Squash, SquashPolicy, SquashRule};
+///
+/// // Squash root everywhere, except from the trusted admin host.
+/// let policy = SquashPolicy::new(SquashRule::new(Squash::Root))
+///     .with_client_rule("192.168.1.1".parse().unwrap(), 32, SquashRule::new(Squash::None));
+///
+/// let creds = AuthFlavor::AuthUnix(AuthUnixParams::new(0, "host", 0, 0, None));
+///
+/// let admin = policy.effective_credentials("192.168.1.1".parse().unwrap(), &creds);
+/// assert_eq!(admin.uid(), 0);
+///
+/// let other = policy.effective_credentials("192.168.1.2".parse().unwrap(), &creds);
+/// assert_eq!(other.uid(), 65534);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct SquashPolicy {
+    default: SquashRule,
+    rules: Vec<(ClientMatch, SquashRule)>,
+}
+
+impl SquashPolicy {
+    /// Construct a policy applying `default` to all clients.
+    pub fn new(default: SquashRule) -> Self {
+        Self {
+            default,
+            rules: Vec::new(),
+        }
+    }
+
+    /// Apply `rule` to clients with an address within `addr` / `prefix_len`.
+    ///
+    /// IPv4 rules also match IPv4-mapped IPv6 client addresses, and rules for
+    /// an IPv4-mapped IPv6 range (such as `::ffff:10.0.0.0/104`) are treated
+    /// as the equivalent IPv4 rule.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `prefix_len` exceeds the number of bits in `addr`, or if
+    /// `addr` is an IPv4-mapped IPv6 address and `prefix_len` is less than 96
+    /// (the range is not contained within the IPv4-mapped address space).
+    pub fn with_client_rule(mut self, addr: IpAddr, prefix_len: u8, rule: SquashRule) -> Self {
+        let canonical = addr.to_canonical();
+        let prefix_len = match (addr, canonical) {
+            (IpAddr::V6(_), IpAddr::V4(_)) => {
+                assert!(
+                    (96..=128).contains(&prefix_len),
+                    "invalid prefix length {prefix_len} for IPv4-mapped address"
+                );
+                prefix_len - 96
+            }
+            (IpAddr::V4(_), _) => {
+                assert!(prefix_len <= 32, "invalid prefix length {prefix_len}");
+                prefix_len
+            }
+            (IpAddr::V6(_), _) => {
+                assert!(prefix_len <= 128, "invalid prefix length {prefix_len}");
+                prefix_len
+            }
+        };
+
+        self.rules.push((
+            ClientMatch {
+                addr: canonical,
+                prefix_len,
+            },
+            rule,
+        ));
+        self
+    }
+
+    /// Return the [`SquashRule`] that applies to calls from `client`.
+    pub fn rule_for(&self, client: IpAddr) -> &SquashRule {
+        self.rules
+            .iter()
+            .find(|(m, _)| m.matches(client))
+            .map(|(_, r)| r)
+            .unwrap_or(&self.default)
+    }
+
+    /// Map the `credentials` of a call from `client` to the effective identity
+    /// it should be authorised as.
+    ///
+    /// [`AuthFlavor::AuthUnix`] credentials are squashed according to the
+    /// applicable rule. All other flavors ([`AuthFlavor::AuthNone`],
+    /// [`AuthFlavor::Unknown`], etc) carry no Unix identity and are mapped to
+    /// the anonymous identity. [`AuthFlavor::AuthShort`] handles should be
+    /// resolved to their [`AuthUnixParams`](crate::auth::AuthUnixParams) and
+    /// passed to [`SquashPolicy::squash()`] instead.
+    pub fn effective_credentials<T>(
+        &self,
+        client: IpAddr,
+        credentials: &AuthFlavor<T>,
+    ) -> UnixCredentials
+    where
+        T: AsRef<[u8]>,
+    {
+        let rule = self.rule_for(client);
+        match credentials {
+            AuthFlavor::AuthUnix(p) => rule.apply(UnixCredentials::from(p)),
+            _ => rule.anonymous(),
+        }
+    }
+
+    /// Apply the rule for `client` to an already-resolved identity, such as
+    /// the output of a [`GroupCache`](crate::auth::GroupCache).
+    pub fn squash(&self, client: IpAddr, creds: UnixCredentials) -> UnixCredentials {
+        self.rule_for(client).apply(creds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::AuthUnixParams;
+
+    use super::*;
+
+    fn unix(uid: u32, gid: u32, gids: &[u32]) -> AuthFlavor<&'static [u8]> {
+        AuthFlavor::AuthUnix(AuthUnixParams::new(
+            0,
+            b"host".as_slice(),
+            uid,
+            gid,
+            gids.iter().copied(),
+        ))
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_root_squash() {
+        let policy = SquashPolicy::default();
+        let client = ip("10.0.0.1");
+
+        let got = policy.effective_credentials(client, &unix(0, 0, &[0, 10]));
+        assert_eq!(got, UnixCredentials::new(NOBODY, NOBODY, [NOBODY, 10]));
+
+        // Non-root users are unchanged.
+        let got = policy.effective_credentials(client, &unix(501, 20, &[20, 80]));
+        assert_eq!(got, UnixCredentials::new(501, 20, [20, 80]));
+
+        // A non-root user in the root group has only the group squashed.
+        let got = policy.effective_credentials(client, &unix(501, 0, &[]));
+        assert_eq!(got, UnixCredentials::new(501, NOBODY, []));
+    }
+
+    #[test]
+    fn test_all_squash_anonymous_ids() {
+        let policy = SquashPolicy::new(SquashRule::new(Squash::All).with_anonymous(1000, 1001));
+
+        let got = policy.effective_credentials(ip("10.0.0.1"), &unix(501, 20, &[20, 80]));
+        assert_eq!(got, UnixCredentials::new(1000, 1001, []));
+    }
+
+    #[test]
+    fn test_no_squash() {
+        let policy = SquashPolicy::new(SquashRule::new(Squash::None));
+
+        let got = policy.effective_credentials(ip("10.0.0.1"), &unix(0, 0, &[0]));
+        assert_eq!(got, UnixCredentials::new(0, 0, [0]));
+    }
+
+    #[test]
+    fn test_non_unix_flavors_are_anonymous() {
+        let policy = SquashPolicy::new(SquashRule::new(Squash::None).with_anonymous(7, 8));
+        let client = ip("10.0.0.1");
+        let anon = UnixCredentials::new(7, 8, []);
+
+        assert_eq!(
+            policy.effective_credentials(client, &AuthFlavor::<&[u8]>::AuthNone(None)),
+            anon
+        );
+        assert_eq!(
+            policy.effective_credentials(
+                client,
+                &AuthFlavor::Unknown {
+                    id: 42,
+                    data: b"".as_slice()
+                }
+            ),
+            anon
+        );
+    }
+
+    #[test]
+    fn test_client_rules() {
+        let policy = SquashPolicy::new(SquashRule::new(Squash::All))
+            .with_client_rule(ip("10.0.0.1"), 32, SquashRule::new(Squash::None))
+            .with_client_rule(ip("10.0.0.0"), 8, SquashRule::new(Squash::Root))
+            .with_client_rule(ip("fd00::"), 8, SquashRule::new(Squash::None));
+
+        let root = unix(0, 0, &[]);
+        let uid = |addr| policy.effective_credentials(ip(addr), &root).uid();
+
+        assert_eq!(uid("10.0.0.1"), 0);
+        assert_eq!(uid("::ffff:10.0.0.1"), 0);
+        assert_eq!(uid("10.1.2.3"), NOBODY);
+        assert_eq!(policy.rule_for(ip("10.1.2.3")).squash(), Squash::Root);
+        assert_eq!(uid("fd12::1"), 0);
+        assert_eq!(uid("192.168.1.1"), NOBODY);
+        assert_eq!(policy.rule_for(ip("192.168.1.1")).squash(), Squash::All);
+
+        // A zero length prefix matches everything of the same family.
+        let policy = SquashPolicy::new(SquashRule::new(Squash::All)).with_client_rule(
+            ip("0.0.0.0"),
+            0,
+            SquashRule::new(Squash::None),
+        );
+        assert_eq!(policy.rule_for(ip("1.2.3.4")).squash(), Squash::None);
+        assert_eq!(policy.rule_for(ip("::1")).squash(), Squash::All);
+    }
+
+    #[test]
+    fn test_mapped_client_rule() {
+        let policy = SquashPolicy::new(SquashRule::new(Squash::All)).with_client_rule(
+            ip("::ffff:10.0.0.0"),
+            104,
+            SquashRule::new(Squash::None),
+        );
+
+        assert_eq!(policy.rule_for(ip("10.1.2.3")).squash(), Squash::None);
+        assert_eq!(
+            policy.rule_for(ip("::ffff:10.1.2.3")).squash(),
+            Squash::None
+        );
+        assert_eq!(policy.rule_for(ip("11.0.0.1")).squash(), Squash::All);
+        assert_eq!(policy.rule_for(ip("::ffff:11.0.0.1")).squash(), Squash::All);
+        assert_eq!(policy.rule_for(ip("fd00::1")).squash(), Squash::All);
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid prefix length")]
+    fn test_mapped_client_rule_short_prefix() {
+        let _ = SquashPolicy::default().with_client_rule(
+            ip("::ffff:10.0.0.0"),
+            64,
+            SquashRule::new(Squash::None),
+        );
+    }
+}
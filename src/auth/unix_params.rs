@@ -175,6 +175,30 @@ where
         Ok(())
     }
 
+    /// Returns a copy of these `AuthUnixParams` borrowing the machine name
+    /// buffer.
+    pub fn as_borrowed(&self) -> AuthUnixParams<&[u8]> {
+        AuthUnixParams {
+            stamp: self.stamp,
+            machine_name: Opaque::from_user_payload(self.machine_name.as_ref()),
+            uid: self.uid,
+            gid: self.gid,
+            gids: self.gids.clone(),
+        }
+    }
+
+    /// Returns a copy of these `AuthUnixParams` owning a copy of the machine
+    /// name buffer.
+    pub fn into_owned(self) -> AuthUnixParams<Vec<u8>> {
+        AuthUnixParams {
+            stamp: self.stamp,
+            machine_name: Opaque::from_user_payload(self.machine_name.as_ref().to_vec()),
+            uid: self.uid,
+            gid: self.gid,
+            gids: self.gids,
+        }
+    }
+
     /// An arbitrary ID generated by the caller.
     pub fn stamp(&self) -> u32 {
         self.stamp
@@ -245,6 +269,60 @@ where
     }
 }
 
+/// Construction from the credentials of the running process.
+#[cfg(all(feature = "process", target_os = "linux"))]
+impl AuthUnixParams<Vec<u8>> {
+    /// Construct an `AuthUnixParams` describing the current process, using the
+    /// effective UID and GID, the supplementary groups returned by
+    /// `getgroups()`, and the hostname of the machine.
+    ///
+    /// The stamp is generated from the current time, as is conventional for
+    /// `AUTH_UNIX` clients.
+    ///
+    /// The protocol permits at most 16 supplementary groups - if the process
+    /// is a member of more, only the first 16 are included and the returned
+    /// `bool` is `true`. Hostnames longer than the 255 byte protocol maximum are
+    /// truncated.
+    ///
+    /// Requires the `process` feature.
+    pub fn from_current_process() -> Result<(Self, bool), std::io::Error> {
+        use rustix::{process, system};
+
+        let groups = process::getgroups()?
+            .into_iter()
+            .map(|g| g.as_raw())
+            .collect::<Vec<_>>();
+
+        let stamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as u32)
+            .unwrap_or_default();
+
+        Ok(Self::from_process_parts(
+            stamp,
+            system::uname().nodename().to_bytes().to_vec(),
+            process::geteuid().as_raw(),
+            process::getegid().as_raw(),
+            &groups,
+        ))
+    }
+
+    fn from_process_parts(
+        stamp: u32,
+        mut machine_name: Vec<u8>,
+        uid: u32,
+        gid: u32,
+        groups: &[u32],
+    ) -> (Self, bool) {
+        machine_name.truncate(MAX_MACHINE_NAME_LEN as usize);
+
+        let truncated = groups.len() > MAX_GIDS;
+        let gids = groups.iter().copied().take(MAX_GIDS);
+
+        (Self::new(stamp, machine_name, uid, gid, gids), truncated)
+    }
+}
+
 #[cfg(feature = "bytes")]
 impl TryFrom<crate::Bytes> for AuthUnixParams<crate::Bytes> {
     type Error = Error;
@@ -494,4 +572,60 @@ mod tests {
             ],
         );
     }
+
+    /// Read the value of `key` from `/proc/self/status`.
+    #[cfg(all(feature = "process", target_os = "linux"))]
+    fn proc_status(key: &str) -> Vec<u32> {
+        let status = std::fs::read_to_string("/proc/self/status").expect("read status");
+        status
+            .lines()
+            .find_map(|l| l.strip_prefix(key))
+            .expect("missing status key")
+            .split_whitespace()
+            .map(|v| v.parse().expect("invalid id"))
+            .collect()
+    }
+
+    #[test]
+    #[cfg(all(feature = "process", target_os = "linux"))]
+    fn test_from_current_process() {
+        let (params, truncated) = AuthUnixParams::from_current_process().expect("read creds");
+
+        // The effective IDs are the second value on each line.
+        assert_eq!(params.uid(), proc_status("Uid:")[1]);
+        assert_eq!(params.gid(), proc_status("Gid:")[1]);
+
+        let groups = proc_status("Groups:");
+        assert_eq!(truncated, groups.len() > MAX_GIDS);
+        assert_eq!(
+            params.gids().unwrap_or_default(),
+            &groups[..groups.len().min(MAX_GIDS)]
+        );
+
+        let hostname = std::fs::read_to_string("/proc/sys/kernel/hostname").expect("hostname");
+        assert_eq!(params.machine_name_str(), hostname.trim_end());
+
+        // The result must be serialisable.
+        let mut buf = Vec::new();
+        params.serialise_into(&mut buf).expect("serialise");
+        assert_eq!(buf.len(), params.serialised_len() as usize);
+    }
+
+    #[test]
+    #[cfg(all(feature = "process", target_os = "linux"))]
+    fn test_from_process_parts_truncates() {
+        let groups = (0..20).collect::<Vec<u32>>();
+        let (params, truncated) =
+            AuthUnixParams::from_process_parts(42, vec![b'a'; 300], 501, 20, &groups);
+
+        assert!(truncated);
+        assert_eq!(params.gids(), Some(&groups[..16]));
+        assert_eq!(params.machine_name().len(), 255);
+
+        let (params, truncated) =
+            AuthUnixParams::from_process_parts(42, b"host".to_vec(), 501, 20, &groups[..16]);
+        assert!(!truncated);
+        assert_eq!(params.gids(), Some(&groups[..16]));
+        assert_eq!(params.stamp(), 42);
+    }
 }
@@ -5,9 +5,9 @@ use std::{
 
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 
-use crate::{auth::AuthFlavor, Error};
+use crate::{auth::AuthFlavor, AcceptedStatus, AuthError, Error, RejectedReply, ReplyBody};
 
-const RPC_VERSION: u32 = 2;
+pub(crate) const RPC_VERSION: u32 = 2;
 
 /// A request invoking an RPC.
 ///
@@ -165,6 +165,118 @@ where
     }
 }
 
+/// Helpers building the reply to a received call.
+///
+/// The accepted replies include `verifier`, such as the one returned by an
+/// [`Authenticator`](crate::auth::Authenticator), or an `AUTH_NONE` verifier
+/// if `None`.
+///
+/// ```
+/// use onc_rpc::{auth::AuthFlavor, CallBody, ReplyBody};
+///
+/// let call = CallBody::new(
+///     100003,
+///     3,
+///     1,
+///     AuthFlavor::<&[u8]>::AuthNone(None),
+///     AuthFlavor::AuthNone(None),
+///     &[][..],
+/// );
+///
+/// // Only versions 2 and 3 of the program are supported.
+/// let reply: ReplyBody<Vec<u8>, Vec<u8>> = match call.program_version() {
+///     2 | 3 => call.success(None, vec![42]),
+///     _ => call.prog_mismatch(None, 2, 3),
+/// };
+/// ```
+impl<T, P> CallBody<T, P>
+where
+    T: AsRef<[u8]>,
+    P: AsRef<[u8]>,
+{
+    /// Reply with the serialised procedure `result` (`SUCCESS`).
+    pub fn success<V, R>(&self, verifier: Option<AuthFlavor<V>>, result: R) -> ReplyBody<V, R>
+    where
+        V: AsRef<[u8]>,
+        R: AsRef<[u8]>,
+    {
+        ReplyBody::accepted(verifier, AcceptedStatus::Success(result))
+    }
+
+    /// Reply that the program is not available (`PROG_UNAVAIL`).
+    pub fn prog_unavail<V, R>(&self, verifier: Option<AuthFlavor<V>>) -> ReplyBody<V, R>
+    where
+        V: AsRef<[u8]>,
+        R: AsRef<[u8]>,
+    {
+        ReplyBody::accepted(verifier, AcceptedStatus::ProgramUnavailable)
+    }
+
+    /// Reply that the program version is not supported, and that versions
+    /// `low` to `high` are (`PROG_MISMATCH`).
+    pub fn prog_mismatch<V, R>(
+        &self,
+        verifier: Option<AuthFlavor<V>>,
+        low: u32,
+        high: u32,
+    ) -> ReplyBody<V, R>
+    where
+        V: AsRef<[u8]>,
+        R: AsRef<[u8]>,
+    {
+        ReplyBody::accepted(verifier, AcceptedStatus::ProgramMismatch { low, high })
+    }
+
+    /// Reply that the procedure is not available (`PROC_UNAVAIL`).
+    pub fn proc_unavail<V, R>(&self, verifier: Option<AuthFlavor<V>>) -> ReplyBody<V, R>
+    where
+        V: AsRef<[u8]>,
+        R: AsRef<[u8]>,
+    {
+        ReplyBody::accepted(verifier, AcceptedStatus::ProcedureUnavailable)
+    }
+
+    /// Reply that the call arguments could not be decoded (`GARBAGE_ARGS`).
+    pub fn garbage_args<V, R>(&self, verifier: Option<AuthFlavor<V>>) -> ReplyBody<V, R>
+    where
+        V: AsRef<[u8]>,
+        R: AsRef<[u8]>,
+    {
+        ReplyBody::accepted(verifier, AcceptedStatus::GarbageArgs)
+    }
+
+    /// Reply that the server experienced an internal error (`SYSTEM_ERR`).
+    pub fn system_err<V, R>(&self, verifier: Option<AuthFlavor<V>>) -> ReplyBody<V, R>
+    where
+        V: AsRef<[u8]>,
+        R: AsRef<[u8]>,
+    {
+        ReplyBody::accepted(verifier, AcceptedStatus::SystemError)
+    }
+
+    /// Reject the call as using an unsupported RPC version (`RPC_MISMATCH`),
+    /// reporting version 2 as the only supported version.
+    pub fn rpc_mismatch<V, R>(&self) -> ReplyBody<V, R>
+    where
+        V: AsRef<[u8]>,
+        R: AsRef<[u8]>,
+    {
+        ReplyBody::Denied(RejectedReply::RpcVersionMismatch {
+            low: RPC_VERSION,
+            high: RPC_VERSION,
+        })
+    }
+
+    /// Reject the call as failing authentication (`AUTH_ERROR`).
+    pub fn auth_error<V, R>(&self, err: AuthError) -> ReplyBody<V, R>
+    where
+        V: AsRef<[u8]>,
+        R: AsRef<[u8]>,
+    {
+        ReplyBody::Denied(RejectedReply::AuthError(err))
+    }
+}
+
 impl<'a> TryFrom<&'a [u8]> for CallBody<&'a [u8], &'a [u8]> {
     type Error = Error;
 
@@ -224,4 +336,41 @@ mod tests {
         let _call: CallBody<&[u8], &[u8; 4]> =
             CallBody::new(100000, 42, 13, auth.clone(), auth, &payload);
     }
+
+    #[test]
+    fn test_reply_helpers() {
+        let call = CallBody::new(
+            100003,
+            3,
+            1,
+            AuthFlavor::<&[u8]>::AuthNone(None),
+            AuthFlavor::AuthNone(None),
+            &[][..],
+        );
+
+        let reply: ReplyBody<Vec<u8>, Vec<u8>> = call.success(None, vec![42]);
+        assert_eq!(
+            reply,
+            ReplyBody::Accepted(crate::AcceptedReply::new(
+                AuthFlavor::AuthNone(None),
+                AcceptedStatus::Success(vec![42]),
+            ))
+        );
+
+        let verifier = AuthFlavor::AuthNone(Some(vec![1, 2, 3, 4]));
+        let reply: ReplyBody<Vec<u8>, Vec<u8>> = call.garbage_args(Some(verifier.clone()));
+        assert_eq!(
+            reply,
+            ReplyBody::Accepted(crate::AcceptedReply::new(
+                verifier,
+                AcceptedStatus::GarbageArgs,
+            ))
+        );
+
+        let reply: ReplyBody<Vec<u8>, Vec<u8>> = call.rpc_mismatch();
+        assert_eq!(
+            reply,
+            ReplyBody::Denied(RejectedReply::RpcVersionMismatch { low: 2, high: 2 })
+        );
+    }
 }
@@ -0,0 +1,115 @@
+use std::time::Duration;
+
+/// An exponential backoff schedule, used to space out retransmissions and
+/// reconnection attempts.
+///
+/// The first interval is `initial`, with each subsequent interval multiplied
+/// by `factor` up to a maximum of `max_interval`. The schedule ends after the
+/// first attempt and `retries` further attempts.
+///
+/// The default schedule waits 1 second after the first attempt, doubling up to
+/// 8 seconds, for 4 retries (23 seconds in total).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    initial: Duration,
+    max_interval: Duration,
+    factor: u32,
+    retries: usize,
+}
+
+impl Backoff {
+    /// Construct a schedule starting at `initial`, doubling up to 8 seconds for
+    /// 4 retries.
+    pub fn new(initial: Duration) -> Self {
+        Self {
+            initial,
+            max_interval: Duration::from_secs(8),
+            factor: 2,
+            retries: 4,
+        }
+    }
+
+    /// Cap each interval at `max_interval`.
+    pub fn with_max_interval(self, max_interval: Duration) -> Self {
+        Self {
+            max_interval,
+            ..self
+        }
+    }
+
+    /// Multiply each interval by `factor` to produce the next.
+    ///
+    /// A factor of 1 produces a constant interval.
+    pub fn with_factor(self, factor: u32) -> Self {
+        Self { factor, ..self }
+    }
+
+    /// Make at most `retries` attempts after the first.
+    pub fn with_retries(self, retries: usize) -> Self {
+        Self { retries, ..self }
+    }
+
+    /// The number of attempts made after the first.
+    pub fn retries(&self) -> usize {
+        self.retries
+    }
+
+    /// The interval to wait after each attempt, yielding `retries + 1` values.
+    pub fn intervals(&self) -> impl Iterator<Item = Duration> {
+        let Self {
+            max_interval,
+            factor,
+            ..
+        } = *self;
+
+        std::iter::successors(Some(self.initial.min(max_interval)), move |d| {
+            Some(d.saturating_mul(factor).min(max_interval))
+        })
+        .take(self.retries.saturating_add(1))
+    }
+
+    /// The total time spent waiting over the whole schedule.
+    pub fn total(&self) -> Duration {
+        self.intervals().sum()
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(v: u64) -> Duration {
+        Duration::from_millis(v)
+    }
+
+    #[test]
+    fn test_default() {
+        let got = Backoff::default().intervals().collect::<Vec<_>>();
+        assert_eq!(got, [1, 2, 4, 8, 8].map(Duration::from_secs).as_slice());
+        assert_eq!(Backoff::default().total(), Duration::from_secs(23));
+    }
+
+    #[test]
+    fn test_schedule() {
+        let b = Backoff::new(ms(100))
+            .with_factor(3)
+            .with_max_interval(ms(1000))
+            .with_retries(5);
+        let got = b.intervals().collect::<Vec<_>>();
+        assert_eq!(got, [100, 300, 900, 1000, 1000, 1000].map(ms).as_slice());
+
+        // Constant interval.
+        let b = Backoff::new(ms(10)).with_factor(1).with_retries(2);
+        assert_eq!(b.intervals().collect::<Vec<_>>(), [10, 10, 10].map(ms));
+
+        // No retries.
+        let b = Backoff::new(ms(10)).with_retries(0);
+        assert_eq!(b.intervals().collect::<Vec<_>>(), [ms(10)]);
+    }
+}
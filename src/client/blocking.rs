@@ -0,0 +1,887 @@
+use std::{
+    io::{Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    auth::AuthFlavor,
+    client::{reply_outcome, reply_result, ClientError, VerifierCheck, XidGenerator},
+    read_record, AuthError, CallBody, MessageType, RpcError, RpcMessage,
+};
+
+/// The default call timeout, matching the traditional `clnt_call()` default.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(25);
+
+/// The default maximum length of a reply record.
+pub const DEFAULT_MAX_REPLY_LEN: usize = 4 * 1024 * 1024;
+
+/// A bidirectional byte stream supporting I/O timeouts, used as the transport
+/// of a [`Client`].
+pub trait Transport: Read + Write {
+    /// Set the timeout for read operations, or block indefinitely if `None`.
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), std::io::Error>;
+
+    /// Set the timeout for write operations, or block indefinitely if `None`.
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> Result<(), std::io::Error>;
+}
+
+impl Transport for TcpStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), std::io::Error> {
+        Self::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> Result<(), std::io::Error> {
+        Self::set_write_timeout(self, timeout)
+    }
+}
+
+#[cfg(unix)]
+impl Transport for std::os::unix::net::UnixStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), std::io::Error> {
+        Self::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> Result<(), std::io::Error> {
+        Self::set_write_timeout(self, timeout)
+    }
+}
+
+#[cfg(feature = "tls")]
+impl<S> Transport for rustls::StreamOwned<rustls::ClientConnection, S>
+where
+    S: Transport,
+{
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), std::io::Error> {
+        self.sock.set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> Result<(), std::io::Error> {
+        self.sock.set_write_timeout(timeout)
+    }
+}
+
+/// Applies the time remaining until `deadline` as the timeout of each I/O
+/// operation on the wrapped [`Transport`].
+struct Deadline<'a, S> {
+    stream: &'a mut S,
+    deadline: Option<Instant>,
+}
+
+impl<S> Deadline<'_, S>
+where
+    S: Transport,
+{
+    fn remaining(&self) -> Result<Option<Duration>, std::io::Error> {
+        let Some(deadline) = self.deadline else {
+            return Ok(None);
+        };
+
+        match deadline.checked_duration_since(Instant::now()) {
+            Some(d) if !d.is_zero() => Ok(Some(d)),
+            _ => Err(std::io::ErrorKind::TimedOut.into()),
+        }
+    }
+}
+
+impl<S> Read for Deadline<'_, S>
+where
+    S: Transport,
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.stream.set_read_timeout(self.remaining()?)?;
+        self.stream.read(buf)
+    }
+}
+
+impl<S> Write for Deadline<'_, S>
+where
+    S: Transport,
+{
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.stream.set_write_timeout(self.remaining()?)?;
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.stream.set_write_timeout(self.remaining()?)?;
+        self.stream.flush()
+    }
+}
+
+/// A synchronous RPC client for stream transports.
+///
+/// `Client` allocates a transaction ID for each call, sends the call using
+/// record marking, and reads replies until the one matching the call is
+/// received. Replies to other transaction IDs are discarded.
+///
+/// Calls are made one at a time, and block until the reply is received or the
+/// call timeout elapses. If a call fails part way through sending the call or
+/// receiving the reply (including by timing out) the state of the connection
+/// is unknown, and all further calls return [`ClientError::ConnectionBroken`].
+///
+/// ```no_run
+/// use onc_rpc::{
+///     auth::{AuthFlavor, AuthUnixParams},
+///     client::Client,
+/// };
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let mut client = Client::<std::net::TcpStream>::connect("127.0.0.1:2049")?
+///     .with_credentials(AuthFlavor::AuthUnix(AuthUnixParams::new(
+///         0,
+///         b"host".to_vec(),
+///         501,
+///         20,
+///         None,
+///     )));
+///
+/// // Call the NULL procedure of NFSv3.
+/// let reply = client.call(100003, 3, 0, &[])?;
+/// assert!(reply.is_empty());
+/// # Ok(())
+/// # }
+/// ```
+pub struct Client<S> {
+    stream: S,
+    xids: XidGenerator,
+    credentials: AuthFlavor<Vec<u8>>,
+    timeout: Option<Duration>,
+    max_reply_len: usize,
+    verifier_check: Option<VerifierCheck>,
+    buf: Vec<u8>,
+    broken: bool,
+}
+
+impl Client<TcpStream> {
+    /// Connect to the RPC server at `addr` over TCP.
+    pub fn connect(addr: impl ToSocketAddrs) -> Result<Self, std::io::Error> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        Ok(Self::new(stream))
+    }
+}
+
+#[cfg(unix)]
+impl Client<std::os::unix::net::UnixStream> {
+    /// Connect to the RPC server listening on the Unix domain socket at
+    /// `path`.
+    pub fn connect(path: impl AsRef<std::path::Path>) -> Result<Self, std::io::Error> {
+        std::os::unix::net::UnixStream::connect(path).map(Self::new)
+    }
+}
+
+impl<S> Client<S>
+where
+    S: Transport,
+{
+    /// Construct a client sending calls over the already-connected `stream`.
+    ///
+    /// Calls are sent with [`AuthFlavor::AuthNone`] credentials, a timeout of
+    /// [`DEFAULT_TIMEOUT`], and accept replies of at most
+    /// [`DEFAULT_MAX_REPLY_LEN`] bytes.
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            xids: XidGenerator::new(),
+            credentials: AuthFlavor::AuthNone(None),
+            timeout: Some(DEFAULT_TIMEOUT),
+            max_reply_len: DEFAULT_MAX_REPLY_LEN,
+            verifier_check: None,
+            buf: Vec::new(),
+            broken: false,
+        }
+    }
+
+    /// Send `credentials` with each call.
+    pub fn with_credentials(self, credentials: AuthFlavor<Vec<u8>>) -> Self {
+        Self {
+            credentials,
+            ..self
+        }
+    }
+
+    /// Fail calls that do not receive a reply within `timeout`, or wait
+    /// indefinitely if `None`.
+    pub fn with_timeout(self, timeout: Option<Duration>) -> Self {
+        Self { timeout, ..self }
+    }
+
+    /// Reject replies longer than `max_reply_len` bytes.
+    pub fn with_max_reply_len(self, max_reply_len: usize) -> Self {
+        Self {
+            max_reply_len,
+            ..self
+        }
+    }
+
+    /// Validate the auth verifier of each accepted reply with `check`.
+    ///
+    /// Calls whose reply verifier is rejected by `check` fail with
+    /// [`RpcError::VerifierRejected`](crate::RpcError::VerifierRejected),
+    /// regardless of the reply status.
+    pub fn with_verifier_check<F>(self, check: F) -> Self
+    where
+        F: Fn(&AuthFlavor<&[u8]>) -> Result<(), AuthError> + Send + Sync + 'static,
+    {
+        Self {
+            verifier_check: Some(VerifierCheck::new(check)),
+            ..self
+        }
+    }
+
+    /// The credentials sent with each call.
+    pub fn credentials(&self) -> &AuthFlavor<Vec<u8>> {
+        &self.credentials
+    }
+
+    /// Replace the credentials sent with subsequent calls.
+    pub fn set_credentials(&mut self, credentials: AuthFlavor<Vec<u8>>) {
+        self.credentials = credentials;
+    }
+
+    /// Call the NULL procedure (procedure 0) of `program` / `program_version`,
+    /// returning the round-trip time.
+    pub fn ping(&mut self, program: u32, program_version: u32) -> Result<Duration, ClientError> {
+        let start = Instant::now();
+        self.call(program, program_version, 0, &[])?;
+        Ok(start.elapsed())
+    }
+
+    /// Call `procedure` of `program` / `program_version` with the serialised
+    /// `args`, returning the serialised result of a successful call.
+    ///
+    /// The client default timeout applies.
+    pub fn call(
+        &mut self,
+        program: u32,
+        program_version: u32,
+        procedure: u32,
+        args: &[u8],
+    ) -> Result<Vec<u8>, ClientError> {
+        self.call_with_timeout(program, program_version, procedure, args, self.timeout)
+    }
+
+    /// Call `procedure` of `program` / `program_version` with the serialised
+    /// `args`, failing with [`ClientError::Timeout`] if no reply is received
+    /// within `timeout`.
+    pub fn call_with_timeout(
+        &mut self,
+        program: u32,
+        program_version: u32,
+        procedure: u32,
+        args: &[u8],
+        timeout: Option<Duration>,
+    ) -> Result<Vec<u8>, ClientError> {
+        if self.broken {
+            return Err(ClientError::ConnectionBroken);
+        }
+
+        let xid = self.xids.next();
+        let deadline = timeout.map(|t| Instant::now() + t);
+
+        let mut buf = std::mem::take(&mut self.buf);
+        buf.clear();
+        self.serialise_call(xid, program, program_version, procedure, args, &mut buf)?;
+        self.buf = buf;
+
+        // Any error until the reply is read leaves the stream in an unknown
+        // state.
+        self.broken = true;
+        self.exchange(xid, deadline, |_| {})?;
+        self.broken = false;
+
+        let msg = RpcMessage::try_from(self.buf.as_slice()).map_err(ClientError::Rpc)?;
+        reply_result(msg, self.verifier_check.as_ref())
+    }
+
+    /// Append a call record with `xid` to `buf`.
+    fn serialise_call(
+        &self,
+        xid: u32,
+        program: u32,
+        program_version: u32,
+        procedure: u32,
+        args: &[u8],
+        buf: &mut Vec<u8>,
+    ) -> Result<(), std::io::Error> {
+        RpcMessage::new(
+            xid,
+            MessageType::Call(CallBody::new(
+                program,
+                program_version,
+                procedure,
+                self.credentials.as_borrowed(),
+                AuthFlavor::AuthNone(None),
+                args,
+            )),
+        )
+        .serialise_into(buf)
+    }
+
+    /// Start a [`Batch`] of calls, sent together without waiting for replies.
+    pub fn batch(&mut self) -> Batch<'_, S> {
+        Batch {
+            client: self,
+            buf: Vec::new(),
+            xids: Vec::new(),
+        }
+    }
+
+    /// Send the serialised calls in the buffer, and read records into the
+    /// buffer until the reply to `xid` is received.
+    ///
+    /// Any other records read are passed to `other`.
+    fn exchange<F>(
+        &mut self,
+        xid: u32,
+        deadline: Option<Instant>,
+        mut other: F,
+    ) -> Result<(), ClientError>
+    where
+        F: FnMut(&[u8]),
+    {
+        let mut io = Deadline {
+            stream: &mut self.stream,
+            deadline,
+        };
+
+        io.write_all(&self.buf)?;
+        io.flush()?;
+
+        loop {
+            read_record(&mut io, &mut self.buf, self.max_reply_len)?;
+
+            // Pass on replies to other calls, such as a late reply to a call
+            // that was retransmitted by the server.
+            match self.buf.get(4..8) {
+                Some(v) if v != xid.to_be_bytes() => other(&self.buf),
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// Returns a reference to the underlying stream.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Consume this client, returning the underlying stream.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S> std::fmt::Debug for Client<S>
+where
+    S: std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Client")
+            .field("stream", &self.stream)
+            .field("credentials", &self.credentials)
+            .field("timeout", &self.timeout)
+            .field("max_reply_len", &self.max_reply_len)
+            .field("verifier_check", &self.verifier_check)
+            .field("broken", &self.broken)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A batch of calls made by a [`Client`], created by [`Client::batch()`].
+///
+/// Batching pipelines calls that do not need a reply, such as bulk lock or
+/// attribute updates. Calls added with [`Batch::push()`] are queued in a single
+/// write buffer, and sent together with a final call by [`Batch::flush()`],
+/// which waits for the reply to only the final call.
+///
+/// The server is expected to suppress successful replies to batched calls,
+/// but may reply with an error. As the server processes calls on a connection
+/// in order, any error replies to batched calls are received before the reply
+/// to the final call, and are returned in the [`BatchResult`]. Successful
+/// replies to batched calls are discarded.
+///
+/// Dropping a `Batch` without flushing it discards the queued calls.
+///
+/// ```no_run
+/// use std::net::TcpStream;
+///
+/// use onc_rpc::client::Client;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let mut client = Client::<TcpStream>::connect("127.0.0.1:4045")?;
+///
+/// let mut batch = client.batch();
+/// for args in [b"a", b"b", b"c"] {
+///     batch.push(100021, 4, 2, args)?;
+/// }
+///
+/// let result = batch.flush(100021, 4, 0, &[])?;
+/// for (index, error) in result.errors() {
+///     eprintln!("batched call {index} failed: {error}");
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Batch<'a, S> {
+    client: &'a mut Client<S>,
+    buf: Vec<u8>,
+
+    /// The xids of the queued calls, in the order they were pushed.
+    xids: Vec<u32>,
+}
+
+impl<S> Batch<'_, S>
+where
+    S: Transport,
+{
+    /// Queue a call to `procedure` of `program` / `program_version` with the
+    /// serialised `args`, expecting no reply.
+    pub fn push(
+        &mut self,
+        program: u32,
+        program_version: u32,
+        procedure: u32,
+        args: &[u8],
+    ) -> Result<(), ClientError> {
+        let xid = self.client.xids.next();
+        self.client.serialise_call(
+            xid,
+            program,
+            program_version,
+            procedure,
+            args,
+            &mut self.buf,
+        )?;
+        self.xids.push(xid);
+        Ok(())
+    }
+
+    /// The number of queued calls.
+    pub fn len(&self) -> usize {
+        self.xids.len()
+    }
+
+    /// Returns true if no calls have been queued.
+    pub fn is_empty(&self) -> bool {
+        self.xids.is_empty()
+    }
+
+    /// Send the queued calls followed by a call to `procedure` of `program` /
+    /// `program_version` with the serialised `args`, and wait for the reply to
+    /// the final call.
+    ///
+    /// The client default timeout applies to the whole batch.
+    pub fn flush(
+        self,
+        program: u32,
+        program_version: u32,
+        procedure: u32,
+        args: &[u8],
+    ) -> Result<BatchResult, ClientError> {
+        let Self {
+            client,
+            mut buf,
+            xids,
+        } = self;
+
+        if client.broken {
+            return Err(ClientError::ConnectionBroken);
+        }
+
+        let xid = client.xids.next();
+        let deadline = client.timeout.map(|t| Instant::now() + t);
+        client.serialise_call(xid, program, program_version, procedure, args, &mut buf)?;
+        client.buf = buf;
+
+        let mut errors = Vec::new();
+        let check = client.verifier_check.clone();
+
+        client.broken = true;
+        client.exchange(xid, deadline, |record| {
+            let Ok(msg) = RpcMessage::try_from(record) else {
+                return;
+            };
+            let Some(index) = xids.iter().position(|v| *v == msg.xid()) else {
+                return;
+            };
+            if let Ok(Err(e)) = reply_outcome(msg, check.as_ref()) {
+                errors.push((index, e));
+            }
+        })?;
+        client.broken = false;
+
+        let msg = RpcMessage::try_from(client.buf.as_slice()).map_err(ClientError::Rpc)?;
+        Ok(BatchResult {
+            reply: reply_outcome(msg, client.verifier_check.as_ref())?,
+            errors,
+        })
+    }
+}
+
+/// The outcome of a flushed [`Batch`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchResult {
+    reply: Result<Vec<u8>, RpcError>,
+    errors: Vec<(usize, RpcError)>,
+}
+
+impl BatchResult {
+    /// The serialised result of the final call, or the error returned by the
+    /// server.
+    pub fn reply(&self) -> Result<&[u8], &RpcError> {
+        self.reply.as_deref()
+    }
+
+    /// The errors returned by the server for batched calls, with the index of
+    /// the call in the order it was pushed.
+    pub fn errors(&self) -> &[(usize, RpcError)] {
+        &self.errors
+    }
+
+    /// Consume this result, returning the final call result.
+    pub fn into_reply(self) -> Result<Vec<u8>, RpcError> {
+        self.reply
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::TcpListener,
+        sync::mpsc::{channel, Receiver},
+        thread,
+    };
+
+    use super::*;
+    use crate::{
+        auth::AuthUnixParams, AcceptedReply, AcceptedStatus, RejectedReply, ReplyBody, RpcError,
+    };
+
+    /// A description of a call received by the test server.
+    type Received = (u32, u32, u32, bool, Vec<u8>);
+
+    fn reply<P: AsRef<[u8]>>(xid: u32, body: ReplyBody<&[u8], P>) -> Vec<u8> {
+        RpcMessage::new(xid, MessageType::Reply(body))
+            .serialise()
+            .unwrap()
+    }
+
+    fn success(xid: u32, payload: &[u8]) -> Vec<u8> {
+        reply(
+            xid,
+            ReplyBody::Accepted(AcceptedReply::new(
+                AuthFlavor::AuthNone(None),
+                AcceptedStatus::Success(payload),
+            )),
+        )
+    }
+
+    /// Serve calls read from `stream`, writing the records returned by
+    /// `handler` for each.
+    fn serve<S, F>(mut stream: S, handler: F) -> Receiver<Received>
+    where
+        S: Read + Write + Send + 'static,
+        F: Fn(u32, &CallBody<&[u8], &[u8]>) -> Vec<Vec<u8>> + Send + 'static,
+    {
+        let (tx, rx) = channel();
+        thread::spawn(move || {
+            let mut buf = Vec::new();
+            while read_record(&mut stream, &mut buf, 1024 * 1024).is_ok() {
+                let msg = RpcMessage::try_from(buf.as_slice()).unwrap();
+                let call = msg.call_body().unwrap();
+                let _ = tx.send((
+                    call.program(),
+                    call.program_version(),
+                    call.procedure(),
+                    matches!(call.auth_credentials(), AuthFlavor::AuthUnix(_)),
+                    call.payload().to_vec(),
+                ));
+                for r in handler(msg.xid(), call) {
+                    stream.write_all(&r).unwrap();
+                }
+            }
+        });
+        rx
+    }
+
+    fn tcp_pair() -> (TcpStream, TcpStream) {
+        let l = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(l.local_addr().unwrap()).unwrap();
+        let (server, _) = l.accept().unwrap();
+        (client, server)
+    }
+
+    #[test]
+    fn test_call_success() {
+        let (client, server) = tcp_pair();
+        let rx = serve(server, |xid, call| {
+            let mut payload = call.payload().to_vec();
+            payload.reverse();
+            vec![success(xid, &payload)]
+        });
+
+        let mut client = Client::new(client).with_credentials(AuthFlavor::AuthUnix(
+            AuthUnixParams::new(0, b"host".to_vec(), 501, 20, None),
+        ));
+
+        assert_eq!(
+            client.call(100003, 3, 1, &[1, 2, 3, 4]).unwrap(),
+            [4, 3, 2, 1]
+        );
+        assert_eq!(rx.recv().unwrap(), (100003, 3, 1, true, vec![1, 2, 3, 4]));
+
+        client.set_credentials(AuthFlavor::AuthNone(None));
+        assert!(client.call(100003, 3, 0, &[]).unwrap().is_empty());
+        assert_eq!(rx.recv().unwrap(), (100003, 3, 0, false, vec![]));
+    }
+
+    #[test]
+    fn test_ping() {
+        let (client, server) = tcp_pair();
+        let rx = serve(server, |xid, _call| vec![success(xid, &[])]);
+
+        let mut client = Client::new(client);
+        let rtt = client.ping(100003, 3).unwrap();
+        assert!(rtt < DEFAULT_TIMEOUT);
+        assert_eq!(rx.recv().unwrap(), (100003, 3, 0, false, vec![]));
+    }
+
+    #[test]
+    fn test_stale_reply_discarded() {
+        let (client, server) = tcp_pair();
+        serve(server, |xid, _call| {
+            vec![
+                success(xid.wrapping_sub(1), b"stale"),
+                success(xid, b"fresh"),
+            ]
+        });
+
+        let mut client = Client::new(client);
+        assert_eq!(client.call(1, 1, 1, &[]).unwrap(), b"fresh");
+        assert_eq!(client.call(1, 1, 1, &[]).unwrap(), b"fresh");
+    }
+
+    #[test]
+    fn test_error_replies() {
+        let (client, server) = tcp_pair();
+        serve(server, |xid, call| {
+            let body: ReplyBody<&[u8], &[u8]> = match call.procedure() {
+                1 => ReplyBody::Accepted(AcceptedReply::new(
+                    AuthFlavor::AuthNone(None),
+                    AcceptedStatus::ProgramMismatch { low: 2, high: 4 },
+                )),
+                2 => ReplyBody::Denied(RejectedReply::AuthError(AuthError::TooWeak)),
+                3 => ReplyBody::Denied(RejectedReply::RpcVersionMismatch { low: 3, high: 3 }),
+                _ => ReplyBody::Accepted(AcceptedReply::new(
+                    AuthFlavor::AuthNone(None),
+                    AcceptedStatus::GarbageArgs,
+                )),
+            };
+            vec![reply(xid, body)]
+        });
+
+        let mut client = Client::new(client);
+        assert!(matches!(
+            client.call(1, 1, 1, &[]),
+            Err(ClientError::Reply(RpcError::ProgramMismatch {
+                low: 2,
+                high: 4
+            }))
+        ));
+        assert!(matches!(
+            client.call(1, 1, 2, &[]),
+            Err(ClientError::Reply(RpcError::AuthError(AuthError::TooWeak)))
+        ));
+        assert!(matches!(
+            client.call(1, 1, 3, &[]),
+            Err(ClientError::Reply(RpcError::RpcVersionMismatch {
+                low: 3,
+                high: 3
+            }))
+        ));
+        assert!(matches!(
+            client.call(1, 1, 4, &[]),
+            Err(ClientError::Reply(RpcError::GarbageArgs))
+        ));
+    }
+
+    #[test]
+    fn test_verifier_check() {
+        let (client, server) = tcp_pair();
+        serve(server, |xid, call| {
+            vec![reply(
+                xid,
+                ReplyBody::Accepted(AcceptedReply::new(
+                    AuthFlavor::AuthShort(b"handle".as_slice()),
+                    AcceptedStatus::Success(call.payload()),
+                )),
+            )]
+        });
+
+        let mut client = Client::new(client).with_verifier_check(|v| match v {
+            AuthFlavor::AuthShort(b"handle") => Ok(()),
+            _ => Err(AuthError::InvalidResponseVerifier),
+        });
+        assert_eq!(client.call(1, 1, 1, b"hello").unwrap(), b"hello");
+
+        let mut client = client.with_verifier_check(|_| Err(AuthError::BadVerifier));
+        assert!(matches!(
+            client.call(1, 1, 1, b"hello"),
+            Err(ClientError::Reply(RpcError::VerifierRejected(
+                AuthError::BadVerifier
+            )))
+        ));
+    }
+
+    #[test]
+    fn test_batch() {
+        let (client, server) = tcp_pair();
+        let rx = serve(server, |xid, call| {
+            let status = match (call.procedure(), *call.payload()) {
+                // Successful replies to batched calls are suppressed.
+                (2, b"bad") => AcceptedStatus::GarbageArgs,
+                (2, _) => return vec![],
+                (_, p) => AcceptedStatus::Success(p),
+            };
+            vec![reply(
+                xid,
+                ReplyBody::Accepted(AcceptedReply::new(AuthFlavor::AuthNone(None), status)),
+            )]
+        });
+
+        let mut client = Client::new(client);
+        let mut batch = client.batch();
+        assert!(batch.is_empty());
+        for args in [b"one", b"bad", b"two"] {
+            batch.push(100021, 4, 2, args).unwrap();
+        }
+        assert_eq!(batch.len(), 3);
+
+        let got = batch.flush(100021, 4, 0, b"flush").unwrap();
+        assert_eq!(got.reply(), Ok(b"flush".as_slice()));
+        assert_eq!(got.errors(), [(1, RpcError::GarbageArgs)]);
+
+        // The calls were received in order.
+        let procs = rx
+            .try_iter()
+            .map(|(_, _, proc, _, payload)| (proc, payload))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            procs,
+            [
+                (2, b"one".to_vec()),
+                (2, b"bad".to_vec()),
+                (2, b"two".to_vec()),
+                (0, b"flush".to_vec()),
+            ]
+        );
+
+        // The client remains usable.
+        assert_eq!(client.call(1, 1, 1, b"after").unwrap(), b"after");
+    }
+
+    #[test]
+    fn test_batch_unsuppressed_replies() {
+        // A server that replies to every call.
+        let (client, server) = tcp_pair();
+        serve(server, |xid, call| {
+            let status = match call.procedure() {
+                3 => AcceptedStatus::ProcedureUnavailable,
+                _ => AcceptedStatus::Success(call.payload()),
+            };
+            vec![reply(
+                xid,
+                ReplyBody::Accepted(AcceptedReply::new(AuthFlavor::AuthNone(None), status)),
+            )]
+        });
+
+        let mut client = Client::new(client);
+        let mut batch = client.batch();
+        batch.push(1, 1, 1, b"ok").unwrap();
+        batch.push(1, 1, 1, b"ok").unwrap();
+
+        // Successful replies to batched calls are discarded, while an error
+        // reply to the final call is returned.
+        let got = batch.flush(1, 1, 3, &[]).unwrap();
+        assert!(got.errors().is_empty());
+        assert_eq!(got.into_reply(), Err(RpcError::ProcedureUnavailable));
+    }
+
+    #[test]
+    fn test_timeout() {
+        let (client, server) = tcp_pair();
+        serve(server, |_xid, _call| vec![]);
+
+        let mut client = Client::new(client);
+        let start = Instant::now();
+        assert!(matches!(
+            client.call_with_timeout(1, 1, 1, &[], Some(Duration::from_millis(50))),
+            Err(ClientError::Timeout)
+        ));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        // The connection state is unknown after a timeout.
+        assert!(matches!(
+            client.call(1, 1, 1, &[]),
+            Err(ClientError::ConnectionBroken)
+        ));
+    }
+
+    #[test]
+    fn test_max_reply_len() {
+        let (client, server) = tcp_pair();
+        serve(server, |xid, _call| vec![success(xid, &[42; 64])]);
+
+        let mut client = Client::new(client).with_max_reply_len(32);
+        assert!(matches!(
+            client.call(1, 1, 1, &[]),
+            Err(ClientError::Rpc(crate::Error::RecordTooLarge {
+                max: 32,
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_unexpected_call() {
+        let (client, server) = tcp_pair();
+        serve(server, |xid, _call| {
+            vec![RpcMessage::new(
+                xid,
+                MessageType::Call(CallBody::new(
+                    1,
+                    1,
+                    1,
+                    AuthFlavor::<&[u8]>::AuthNone(None),
+                    AuthFlavor::AuthNone(None),
+                    &[],
+                )),
+            )
+            .serialise()
+            .unwrap()]
+        });
+
+        let mut client = Client::new(client);
+        assert!(matches!(
+            client.call(1, 1, 1, &[]),
+            Err(ClientError::UnexpectedMessage)
+        ));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_unix_socket() {
+        use std::os::unix::net::{UnixListener, UnixStream};
+
+        let path = std::env::temp_dir().join(format!("onc-rpc-client-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let l = UnixListener::bind(&path).unwrap();
+
+        let mut client = Client::<UnixStream>::connect(&path).unwrap();
+        let (server, _) = l.accept().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        serve(server, |xid, call| vec![success(xid, call.payload())]);
+        assert_eq!(client.call(1, 1, 1, b"ping").unwrap(), b"ping");
+    }
+}
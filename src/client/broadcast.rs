@@ -0,0 +1,541 @@
+use std::{
+    collections::HashSet,
+    net::{SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    auth::AuthFlavor,
+    client::{Backoff, ClientError, XidGenerator, DEFAULT_MAX_DATAGRAM_LEN},
+    CallBody, MessageType, RpcError, RpcMessage,
+};
+
+/// A UDP client sending a single call to a broadcast or multicast address, and
+/// collecting the replies of every server that answers.
+///
+/// This is the equivalent of the traditional `clnt_broadcast()`. The call is
+/// resent after each [`Backoff`] interval, and replies matching the call
+/// transaction ID are collected until the schedule is exhausted. Each
+/// responder is yielded at most once, so duplicate replies to resent calls are
+/// discarded.
+///
+/// [`BroadcastClient::bind()`] enables `SO_BROADCAST` on the socket. To send
+/// to a multicast group, configure the multicast TTL and interface of the
+/// socket with [`BroadcastClient::get_ref()`] as required.
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use onc_rpc::client::{Backoff, BroadcastClient};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let client = BroadcastClient::bind("0.0.0.0:0")?
+///     .with_backoff(Backoff::new(Duration::from_secs(1)).with_retries(2));
+///
+/// // Find every portmapper on the local network.
+/// for reply in client.call("255.255.255.255:111".parse()?, 100000, 2, 0, &[])? {
+///     let reply = reply?;
+///     println!("portmapper at {}", reply.from());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct BroadcastClient {
+    socket: UdpSocket,
+    xids: XidGenerator,
+    credentials: AuthFlavor<Vec<u8>>,
+    backoff: Backoff,
+    max_reply_len: usize,
+}
+
+impl BroadcastClient {
+    /// Bind a socket to the local address `addr`, and enable sending to
+    /// broadcast addresses.
+    pub fn bind(addr: impl std::net::ToSocketAddrs) -> Result<Self, std::io::Error> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_broadcast(true)?;
+        Ok(Self::new(socket))
+    }
+
+    /// Construct a client sending calls over the unconnected `socket`.
+    ///
+    /// Calls are sent with [`AuthFlavor::AuthNone`] credentials, resent using
+    /// the default [`Backoff`] schedule, and accept replies of at most
+    /// [`DEFAULT_MAX_DATAGRAM_LEN`] bytes.
+    pub fn new(socket: UdpSocket) -> Self {
+        Self {
+            socket,
+            xids: XidGenerator::new(),
+            credentials: AuthFlavor::AuthNone(None),
+            backoff: Backoff::default(),
+            max_reply_len: DEFAULT_MAX_DATAGRAM_LEN,
+        }
+    }
+
+    /// Send `credentials` with each call.
+    pub fn with_credentials(self, credentials: AuthFlavor<Vec<u8>>) -> Self {
+        Self {
+            credentials,
+            ..self
+        }
+    }
+
+    /// Resend calls, and collect replies, according to `backoff`.
+    ///
+    /// Replies are collected for [`Backoff::total()`] after the call is first
+    /// sent.
+    pub fn with_backoff(self, backoff: Backoff) -> Self {
+        Self { backoff, ..self }
+    }
+
+    /// Discard reply datagrams longer than `max_reply_len` bytes.
+    pub fn with_max_reply_len(self, max_reply_len: usize) -> Self {
+        Self {
+            max_reply_len,
+            ..self
+        }
+    }
+
+    /// The credentials sent with each call.
+    pub fn credentials(&self) -> &AuthFlavor<Vec<u8>> {
+        &self.credentials
+    }
+
+    /// Replace the credentials sent with subsequent calls.
+    pub fn set_credentials(&mut self, credentials: AuthFlavor<Vec<u8>>) {
+        self.credentials = credentials;
+    }
+
+    /// Send a call to `procedure` of `program` / `program_version` with the
+    /// serialised `args` to `dest`, returning an iterator of the replies.
+    ///
+    /// The call is sent immediately, and the iterator blocks until the next
+    /// reply is received, resending the call as the schedule requires. It
+    /// returns `None` once the schedule is exhausted, or an error if sending or
+    /// receiving fails.
+    pub fn call(
+        &self,
+        dest: SocketAddr,
+        program: u32,
+        program_version: u32,
+        procedure: u32,
+        args: &[u8],
+    ) -> Result<BroadcastReplies<'_>, ClientError> {
+        let xid = self.xids.next();
+        let call = RpcMessage::new(
+            xid,
+            MessageType::Call(CallBody::new(
+                program,
+                program_version,
+                procedure,
+                self.credentials.as_borrowed(),
+                AuthFlavor::AuthNone(None),
+                args,
+            )),
+        )
+        .serialise_datagram()?;
+
+        let mut intervals = self.backoff.intervals().collect::<Vec<_>>().into_iter();
+
+        self.socket.send_to(&call, dest)?;
+        let deadline = intervals.next().map(|v| Instant::now() + v);
+
+        Ok(BroadcastReplies {
+            client: self,
+            dest,
+            xid,
+            call,
+            intervals,
+            deadline,
+            seen: HashSet::new(),
+            // Allow one extra byte to detect (and discard) oversized
+            // datagrams, which are otherwise silently truncated.
+            buf: vec![0; self.max_reply_len + 1],
+        })
+    }
+
+    /// Returns a reference to the underlying socket.
+    pub fn get_ref(&self) -> &UdpSocket {
+        &self.socket
+    }
+
+    /// Consume this client, returning the underlying socket.
+    pub fn into_inner(self) -> UdpSocket {
+        self.socket
+    }
+}
+
+/// A reply to a call sent by a [`BroadcastClient`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct BroadcastReply {
+    from: SocketAddr,
+    result: Result<Vec<u8>, RpcError>,
+}
+
+impl BroadcastReply {
+    /// The address of the server that sent this reply.
+    pub fn from(&self) -> SocketAddr {
+        self.from
+    }
+
+    /// The serialised result of a successful call, or the error returned by
+    /// the server.
+    pub fn result(&self) -> Result<&[u8], &RpcError> {
+        self.result.as_deref()
+    }
+
+    /// Consume this reply, returning the responder address and call result.
+    pub fn into_parts(self) -> (SocketAddr, Result<Vec<u8>, RpcError>) {
+        (self.from, self.result)
+    }
+}
+
+/// An iterator of [`BroadcastReply`] to a call, returned by
+/// [`BroadcastClient::call()`].
+#[derive(Debug)]
+pub struct BroadcastReplies<'a> {
+    client: &'a BroadcastClient,
+    dest: SocketAddr,
+    xid: u32,
+    call: Vec<u8>,
+    intervals: std::vec::IntoIter<Duration>,
+
+    /// The end of the current interval, or `None` once the schedule is
+    /// exhausted.
+    deadline: Option<Instant>,
+
+    /// The addresses of servers that have replied.
+    seen: HashSet<SocketAddr>,
+    buf: Vec<u8>,
+}
+
+impl BroadcastReplies<'_> {
+    /// The transaction ID of the call.
+    pub fn xid(&self) -> u32 {
+        self.xid
+    }
+
+    /// Receive the next datagram, resending the call at the end of each
+    /// interval, or return `None` once the schedule is exhausted.
+    fn recv(&mut self) -> Result<Option<(usize, SocketAddr)>, std::io::Error> {
+        loop {
+            let Some(deadline) = self.deadline else {
+                return Ok(None);
+            };
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                self.deadline = self.intervals.next().map(|v| Instant::now() + v);
+                if self.deadline.is_some() {
+                    self.client.socket.send_to(&self.call, self.dest)?;
+                }
+                continue;
+            }
+
+            self.client.socket.set_read_timeout(Some(remaining))?;
+            match self.client.socket.recv_from(&mut self.buf) {
+                Ok(v) => return Ok(Some(v)),
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    continue
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl Iterator for BroadcastReplies<'_> {
+    type Item = Result<BroadcastReply, ClientError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (n, from) = match self.recv() {
+                Ok(Some(v)) => v,
+                Ok(None) => return None,
+                Err(e) => {
+                    // Stop iterating after an I/O error.
+                    self.deadline = None;
+                    return Some(Err(e.into()));
+                }
+            };
+
+            if n > self.client.max_reply_len || self.seen.contains(&from) {
+                continue;
+            }
+
+            let reply = match RpcMessage::from_datagram(&self.buf[..n]) {
+                Ok(msg) if msg.xid() == self.xid => msg.into_reply_body(),
+                _ => continue,
+            };
+            let Some(reply) = reply else {
+                continue;
+            };
+
+            self.seen.insert(from);
+            return Some(Ok(BroadcastReply {
+                from,
+                result: reply.into_result().map(<[u8]>::to_vec),
+            }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::Ipv4Addr, thread};
+
+    use super::*;
+    use crate::{AcceptedReply, AcceptedStatus, ReplyBody};
+
+    /// The resend interval used by tests, short enough to keep them fast.
+    const TEST_INTERVAL: Duration = Duration::from_millis(50);
+
+    fn backoff() -> Backoff {
+        Backoff::new(TEST_INTERVAL).with_factor(1).with_retries(2)
+    }
+
+    /// Build the datagrams sent by responder `id` in reply to `call`.
+    ///
+    /// Procedure 0 is answered with the payload `[id]`, and all others with
+    /// `PROC_UNAVAIL`. Each reply is preceded by a stale reply and garbage,
+    /// which the client must ignore.
+    fn reply(id: u8, call: &[u8]) -> Vec<Vec<u8>> {
+        let msg = RpcMessage::from_datagram(call).unwrap();
+        let payload = [id];
+        let status = match msg.call_body().unwrap().procedure() {
+            0 => AcceptedStatus::Success(payload.as_slice()),
+            _ => AcceptedStatus::ProcedureUnavailable,
+        };
+        let reply = |xid| {
+            RpcMessage::new(
+                xid,
+                MessageType::Reply(ReplyBody::<&[u8], &[u8]>::Accepted(AcceptedReply::new(
+                    AuthFlavor::AuthNone(None),
+                    status.clone(),
+                ))),
+            )
+            .serialise_datagram()
+            .unwrap()
+        };
+
+        vec![
+            reply(msg.xid().wrapping_sub(1)),
+            b"garbage".to_vec(),
+            reply(msg.xid()),
+        ]
+    }
+
+    /// Spawn a listener standing in for a broadcast address, answering each
+    /// call from `n` sockets with distinct addresses as if sent by `n` hosts.
+    ///
+    /// Responders listed in `drop_first` ignore the first call.
+    fn fan_out(n: u8, drop_first: &[u8]) -> (SocketAddr, Vec<SocketAddr>) {
+        let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let responders = (0..n)
+            .map(|_| UdpSocket::bind("127.0.0.1:0").unwrap())
+            .collect::<Vec<_>>();
+        let responder_addrs = responders.iter().map(|s| s.local_addr().unwrap()).collect();
+
+        let mut drop_next = (0..n).map(|i| drop_first.contains(&i)).collect::<Vec<_>>();
+        thread::spawn(move || {
+            let mut buf = vec![0; 1024];
+            loop {
+                let (len, from) = listener.recv_from(&mut buf).unwrap();
+                for (id, socket) in responders.iter().enumerate() {
+                    if std::mem::take(&mut drop_next[id]) {
+                        continue;
+                    }
+                    for d in reply(id as u8, &buf[..len]) {
+                        socket.send_to(&d, from).unwrap();
+                    }
+                }
+            }
+        });
+
+        (addr, responder_addrs)
+    }
+
+    #[test]
+    fn test_collect_replies() {
+        let (addr, responders) = fan_out(3, &[]);
+        let client = BroadcastClient::bind("127.0.0.1:0")
+            .unwrap()
+            .with_backoff(backoff());
+
+        let start = Instant::now();
+        let mut got = client
+            .call(addr, 100000, 2, 0, &[])
+            .unwrap()
+            .map(|r| r.unwrap().into_parts())
+            .collect::<Vec<_>>();
+
+        // Replies are collected for the whole schedule.
+        assert!(start.elapsed() >= TEST_INTERVAL * 3);
+
+        // Each responder is yielded once, despite the call being resent.
+        got.sort_by_key(|(from, _)| *from);
+        let mut want = responders
+            .into_iter()
+            .enumerate()
+            .map(|(id, from)| (from, Ok(vec![id as u8])))
+            .collect::<Vec<_>>();
+        want.sort_by_key(|(from, _)| *from);
+        assert_eq!(got, want);
+    }
+
+    #[test]
+    fn test_resend_reaches_dropped() {
+        // Responder 1 only answers the resent call.
+        let (addr, responders) = fan_out(2, &[1]);
+        let client = BroadcastClient::bind("127.0.0.1:0")
+            .unwrap()
+            .with_backoff(backoff());
+
+        let mut replies = client.call(addr, 1, 1, 0, &[]).unwrap();
+        let first = replies.next().unwrap().unwrap();
+        assert_eq!(first.from(), responders[0]);
+        assert_eq!(first.result(), Ok([0].as_slice()));
+
+        let second = replies.next().unwrap().unwrap();
+        assert_eq!(second.from(), responders[1]);
+        assert_eq!(second.result(), Ok([1].as_slice()));
+
+        assert!(replies.next().is_none());
+    }
+
+    #[test]
+    fn test_error_replies() {
+        let (addr, _) = fan_out(2, &[]);
+        let client = BroadcastClient::bind("127.0.0.1:0")
+            .unwrap()
+            .with_backoff(backoff().with_retries(0));
+
+        let got = client
+            .call(addr, 1, 1, 42, &[])
+            .unwrap()
+            .map(|r| r.unwrap().into_parts().1)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            got,
+            [
+                Err(RpcError::ProcedureUnavailable),
+                Err(RpcError::ProcedureUnavailable)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_no_replies() {
+        let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let client = BroadcastClient::bind("127.0.0.1:0")
+            .unwrap()
+            .with_backoff(backoff());
+
+        let mut replies = client
+            .call(silent.local_addr().unwrap(), 1, 1, 0, &[])
+            .unwrap();
+        assert!(replies.next().is_none());
+
+        // The call was sent once, and resent twice.
+        silent.set_nonblocking(true).unwrap();
+        let mut buf = [0; 1024];
+        let mut xids = Vec::new();
+        while let Ok(n) = silent.recv(&mut buf) {
+            xids.push(RpcMessage::from_datagram(&buf[..n]).unwrap().xid());
+        }
+        assert_eq!(xids, [replies.xid(); 3]);
+    }
+
+    /// Spawn `n` responders listening on the same port of the loopback
+    /// interface, each replying from a distinct loopback address as separate
+    /// hosts would, returning the port and reply addresses.
+    ///
+    /// If `group` is set, each responder joins the multicast group on the
+    /// loopback interface.
+    #[cfg(target_os = "linux")]
+    fn shared_port(n: u8, group: Option<Ipv4Addr>) -> (u16, Vec<SocketAddr>) {
+        use rustix::net::{bind, socket, sockopt::set_socket_reuseaddr, AddressFamily, SocketType};
+
+        let mut port = 0;
+        let mut responders = Vec::new();
+        for id in 0..n {
+            let fd = socket(AddressFamily::INET, SocketType::DGRAM, None).unwrap();
+            set_socket_reuseaddr(&fd, true).unwrap();
+            bind(&fd, &SocketAddr::from((Ipv4Addr::UNSPECIFIED, port))).unwrap();
+            let socket = UdpSocket::from(fd);
+            port = socket.local_addr().unwrap().port();
+
+            if let Some(group) = group {
+                socket
+                    .join_multicast_v4(&group, &Ipv4Addr::LOCALHOST)
+                    .unwrap();
+            }
+
+            let reply_socket = UdpSocket::bind((Ipv4Addr::new(127, 0, 0, 2 + id), 0)).unwrap();
+            responders.push(reply_socket.local_addr().unwrap());
+
+            thread::spawn(move || {
+                let mut buf = vec![0; 1024];
+                loop {
+                    let (n, from) = socket.recv_from(&mut buf).unwrap();
+                    for d in reply(id, &buf[..n]) {
+                        reply_socket.send_to(&d, from).unwrap();
+                    }
+                }
+            });
+        }
+
+        (port, responders)
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_loopback_broadcast() {
+        let (port, responders) = shared_port(3, None);
+        let client = BroadcastClient::bind("127.0.0.1:0")
+            .unwrap()
+            .with_backoff(backoff().with_retries(0));
+
+        let mut got = client
+            .call(
+                (Ipv4Addr::new(127, 255, 255, 255), port).into(),
+                1,
+                1,
+                0,
+                &[],
+            )
+            .unwrap()
+            .map(|r| r.unwrap().from())
+            .collect::<Vec<_>>();
+        got.sort();
+        assert_eq!(got, responders);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_loopback_multicast() {
+        let group = Ipv4Addr::new(239, 255, 42, 99);
+        let (port, responders) = shared_port(3, Some(group));
+
+        let client = BroadcastClient::bind("127.0.0.1:0")
+            .unwrap()
+            .with_backoff(backoff().with_retries(0));
+        rustix::net::sockopt::set_ip_multicast_if(client.get_ref(), &Ipv4Addr::LOCALHOST).unwrap();
+
+        let mut got = client
+            .call((group, port).into(), 1, 1, 0, &[])
+            .unwrap()
+            .map(|r| r.unwrap().from())
+            .collect::<Vec<_>>();
+        got.sort();
+        assert_eq!(got, responders);
+    }
+}
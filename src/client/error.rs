@@ -0,0 +1,124 @@
+use std::sync::Arc;
+
+use thiserror::Error;
+
+use crate::{auth::AuthFlavor, AuthError, RpcError, RpcMessage};
+
+/// Errors returned by an RPC client.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ClientError {
+    /// An I/O error occurred on the underlying transport.
+    #[error("i/o error: {0}")]
+    Io(std::io::Error),
+
+    /// No reply was received before the call timeout elapsed.
+    #[error("call timed out")]
+    Timeout,
+
+    /// The reply could not be read or parsed.
+    #[error("invalid reply: {0}")]
+    Rpc(crate::Error),
+
+    /// The server sent a message that is not a reply.
+    #[error("unexpected message from server")]
+    UnexpectedMessage,
+
+    /// A previous call failed part way through sending the call or reading
+    /// the reply, leaving the connection in an unknown state.
+    ///
+    /// The client must be reconnected.
+    #[error("connection broken by a previous error")]
+    ConnectionBroken,
+
+    /// The connection to the server was closed before the reply was
+    /// received.
+    #[error("connection closed")]
+    Disconnected,
+
+    /// The connection to the server was lost after sending a call that is not
+    /// idempotent, and the call was not retried.
+    ///
+    /// The server may or may not have executed the call.
+    #[error("connection lost after sending call, outcome unknown")]
+    OutcomeUnknown,
+
+    /// The server replied, but the call was not successful.
+    #[error("{0}")]
+    Reply(#[from] RpcError),
+}
+
+impl From<crate::Error> for ClientError {
+    fn from(v: crate::Error) -> Self {
+        match v {
+            crate::Error::IOError(
+                std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut,
+                _,
+            ) => Self::Timeout,
+            crate::Error::IOError(kind, msg) => Self::Io(std::io::Error::new(kind, msg)),
+            v => Self::Rpc(v),
+        }
+    }
+}
+
+impl From<std::io::Error> for ClientError {
+    fn from(v: std::io::Error) -> Self {
+        match v.kind() {
+            std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut => Self::Timeout,
+            _ => Self::Io(v),
+        }
+    }
+}
+
+type CheckFn = dyn Fn(&AuthFlavor<&[u8]>) -> Result<(), AuthError> + Send + Sync;
+
+/// A caller-supplied check of the auth verifier in accepted replies.
+///
+/// See [`crate::ReplyBody::into_result_with_verifier()`].
+#[derive(Clone)]
+pub(crate) struct VerifierCheck(Arc<CheckFn>);
+
+impl VerifierCheck {
+    pub(crate) fn new<F>(check: F) -> Self
+    where
+        F: Fn(&AuthFlavor<&[u8]>) -> Result<(), AuthError> + Send + Sync + 'static,
+    {
+        Self(Arc::new(check))
+    }
+}
+
+impl std::fmt::Debug for VerifierCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("VerifierCheck")
+    }
+}
+
+/// Map a reply to the payload of a successful call, or the error describing
+/// why the call failed, validating the reply verifier with `check` if set.
+pub(crate) fn reply_result(
+    msg: RpcMessage<&[u8], &[u8]>,
+    check: Option<&VerifierCheck>,
+) -> Result<Vec<u8>, ClientError> {
+    Ok(reply_outcome(msg, check)??)
+}
+
+/// Map a reply to the payload of a successful call, or the [`RpcError`]
+/// describing why the call failed, validating the reply verifier with `check`
+/// if set.
+///
+/// Returns [`ClientError::UnexpectedMessage`] if `msg` is not a reply.
+pub(crate) fn reply_outcome(
+    msg: RpcMessage<&[u8], &[u8]>,
+    check: Option<&VerifierCheck>,
+) -> Result<Result<Vec<u8>, RpcError>, ClientError> {
+    let reply = msg
+        .into_reply_body()
+        .ok_or(ClientError::UnexpectedMessage)?;
+
+    let payload = match check {
+        Some(check) => reply.into_result_with_verifier(|v| (check.0)(v)),
+        None => reply.into_result(),
+    };
+
+    Ok(payload.map(<[u8]>::to_vec))
+}
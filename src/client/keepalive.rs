@@ -0,0 +1,63 @@
+use std::time::Duration;
+
+/// A keepalive schedule for an [`AsyncClient`](crate::client::AsyncClient)
+/// connection, configured with
+/// [`AsyncClientBuilder::with_keepalive()`](crate::client::AsyncClientBuilder::with_keepalive).
+///
+/// Once no reply has been received on the connection for `interval`, a NULL
+/// (procedure 0) call to `program` / `version` is sent. If `max_failures`
+/// consecutive pings go unanswered for `timeout`, the connection is closed and
+/// all pending calls fail with
+/// [`ClientError::Disconnected`](crate::client::ClientError::Disconnected).
+///
+/// Any reply to a ping, including an error reply such as `PROG_UNAVAIL`, shows
+/// the server is alive.
+///
+/// By default, idle connections are pinged every 30 seconds, and closed after
+/// 3 pings go unanswered for 10 seconds each.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keepalive {
+    pub(crate) program: u32,
+    pub(crate) version: u32,
+    pub(crate) interval: Duration,
+    pub(crate) timeout: Duration,
+    pub(crate) max_failures: u32,
+}
+
+impl Keepalive {
+    /// Ping the NULL procedure of `program` / `version` using the default
+    /// schedule.
+    pub fn new(program: u32, version: u32) -> Self {
+        Self {
+            program,
+            version,
+            interval: Duration::from_secs(30),
+            timeout: Duration::from_secs(10),
+            max_failures: 3,
+        }
+    }
+
+    /// Ping after the connection has been idle for `interval`, and wait
+    /// `interval` between unanswered pings.
+    pub fn with_interval(self, interval: Duration) -> Self {
+        Self { interval, ..self }
+    }
+
+    /// Consider a ping failed if no reply is received within `timeout`.
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self { timeout, ..self }
+    }
+
+    /// Close the connection after `max_failures` consecutive failed pings.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_failures` is 0.
+    pub fn with_max_failures(self, max_failures: u32) -> Self {
+        assert!(max_failures > 0, "max_failures must be non-zero");
+        Self {
+            max_failures,
+            ..self
+        }
+    }
+}
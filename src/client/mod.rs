@@ -0,0 +1,50 @@
+//! RPC clients, handling transaction ID allocation, record marking and reply
+//! matching.
+//!
+//! [`Client`] is a synchronous client for stream transports (TCP, Unix domain
+//! sockets, or any other [`Transport`]), and [`UdpClient`] a synchronous client
+//! retransmitting calls over UDP, both built only on the standard library.
+//! [`BroadcastClient`] sends a call to a broadcast or multicast address,
+//! collecting the replies of every server that answers.
+//!
+//! With the `tokio` feature enabled, [`AsyncClient`] multiplexes many
+//! concurrent calls over a single connection, and [`ReconnectingClient`]
+//! reconnects and retries calls when the connection fails. A [`Keepalive`]
+//! schedule pings idle [`AsyncClient`] connections, closing them once the
+//! server stops responding.
+//!
+//! With the `tower` feature enabled, [`AsyncClient`] implements
+//! `tower_service::Service<CallBody>`, allowing tower middleware to wrap it.
+//!
+//! [`VersionNegotiator`] selects the highest program version supported by both
+//! the client and server, using any of the above clients.
+
+mod backoff;
+mod blocking;
+mod broadcast;
+mod error;
+#[cfg(feature = "tokio")]
+mod keepalive;
+#[cfg(feature = "tokio")]
+mod multiplexed;
+mod negotiate;
+#[cfg(feature = "tokio")]
+mod reconnect;
+#[cfg(feature = "tower")]
+mod service;
+mod udp;
+mod xid;
+
+pub use backoff::*;
+pub use blocking::*;
+pub use broadcast::*;
+pub use error::*;
+#[cfg(feature = "tokio")]
+pub use keepalive::*;
+#[cfg(feature = "tokio")]
+pub use multiplexed::*;
+pub use negotiate::*;
+#[cfg(feature = "tokio")]
+pub use reconnect::*;
+pub use udp::*;
+pub(crate) use xid::*;
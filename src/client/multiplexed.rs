@@ -0,0 +1,981 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt},
+    net::{TcpStream, ToSocketAddrs},
+    sync::{mpsc, oneshot, watch, OwnedSemaphorePermit, Semaphore},
+};
+
+#[cfg(feature = "tower")]
+use crate::client::service::Readiness;
+
+use crate::{
+    auth::AuthFlavor,
+    client::{
+        reply_result, ClientError, Keepalive, VerifierCheck, XidGenerator, DEFAULT_MAX_REPLY_LEN,
+    },
+    read_record_async, AuthError, CallBody, MessageType, RpcMessage,
+};
+
+/// The default maximum number of calls awaiting a reply on one connection.
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 128;
+
+/// Calls awaiting a reply, keyed by xid, or `None` once the connection has
+/// closed.
+type PendingCalls = Option<HashMap<u32, oneshot::Sender<Vec<u8>>>>;
+
+/// State shared between client handles and the connection I/O tasks.
+#[derive(Debug)]
+struct Shared {
+    xids: XidGenerator,
+    pending: Mutex<PendingCalls>,
+    in_flight: Arc<Semaphore>,
+    closed: watch::Sender<bool>,
+
+    /// The time the last reply was received, or the connection was
+    /// established.
+    last_reply: Mutex<Instant>,
+}
+
+impl Shared {
+    /// Register a pending call with `xid`, or allocate an unused xid if
+    /// `None`.
+    fn register(
+        &self,
+        xid: Option<u32>,
+        tx: oneshot::Sender<Vec<u8>>,
+    ) -> Result<PendingGuard<'_>, ClientError> {
+        let mut pending = self.pending.lock().unwrap();
+        let pending = pending.as_mut().ok_or(ClientError::Disconnected)?;
+
+        if let Some(xid) = xid {
+            return match pending.entry(xid) {
+                Entry::Vacant(v) => {
+                    v.insert(tx);
+                    Ok(PendingGuard { shared: self, xid })
+                }
+                Entry::Occupied(_) => Err(ClientError::Io(std::io::Error::new(
+                    std::io::ErrorKind::AlreadyExists,
+                    "xid already in use",
+                ))),
+            };
+        }
+
+        loop {
+            // Skip any xid still in use after the counter wraps.
+            if let Entry::Vacant(v) = pending.entry(self.xids.next()) {
+                let xid = *v.key();
+                v.insert(tx);
+                return Ok(PendingGuard { shared: self, xid });
+            }
+        }
+    }
+
+    /// Mark the connection as closed, failing all pending and queued calls.
+    fn disconnect(&self) {
+        // Dropping the senders wakes every waiting call.
+        self.pending.lock().unwrap().take();
+        self.in_flight.close();
+        self.closed.send_replace(true);
+    }
+}
+
+/// Removes a pending call when the call completes or is cancelled.
+#[derive(Debug)]
+struct PendingGuard<'a> {
+    shared: &'a Shared,
+    xid: u32,
+}
+
+impl Drop for PendingGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(pending) = self.shared.pending.lock().unwrap().as_mut() {
+            pending.remove(&self.xid);
+        }
+    }
+}
+
+/// Configuration for an [`AsyncClient`].
+#[derive(Debug, Clone)]
+pub struct AsyncClientBuilder {
+    credentials: AuthFlavor<Vec<u8>>,
+    max_in_flight: usize,
+    max_reply_len: usize,
+    verifier_check: Option<VerifierCheck>,
+    keepalive: Option<Keepalive>,
+}
+
+impl AsyncClientBuilder {
+    /// Send `credentials` with each call.
+    pub fn with_credentials(self, credentials: AuthFlavor<Vec<u8>>) -> Self {
+        Self {
+            credentials,
+            ..self
+        }
+    }
+
+    /// Allow at most `max_in_flight` calls to await a reply at any one time.
+    ///
+    /// Further calls wait until an in-flight call completes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_in_flight` is 0.
+    pub fn with_max_in_flight(self, max_in_flight: usize) -> Self {
+        assert!(max_in_flight > 0, "max_in_flight must be non-zero");
+        Self {
+            max_in_flight,
+            ..self
+        }
+    }
+
+    /// Reject replies longer than `max_reply_len` bytes.
+    ///
+    /// Receiving an oversized reply closes the connection, as the stream
+    /// cannot be resynchronised.
+    pub fn with_max_reply_len(self, max_reply_len: usize) -> Self {
+        Self {
+            max_reply_len,
+            ..self
+        }
+    }
+
+    /// Validate the auth verifier of each accepted reply with `check`.
+    ///
+    /// Calls whose reply verifier is rejected by `check` fail with
+    /// [`RpcError::VerifierRejected`](crate::RpcError::VerifierRejected),
+    /// regardless of the reply status.
+    pub fn with_verifier_check<F>(self, check: F) -> Self
+    where
+        F: Fn(&AuthFlavor<&[u8]>) -> Result<(), AuthError> + Send + Sync + 'static,
+    {
+        Self {
+            verifier_check: Some(VerifierCheck::new(check)),
+            ..self
+        }
+    }
+
+    /// Ping the server when the connection is idle, closing the connection if
+    /// the server stops responding.
+    ///
+    /// See [`Keepalive`] for details.
+    pub fn with_keepalive(self, keepalive: Keepalive) -> Self {
+        Self {
+            keepalive: Some(keepalive),
+            ..self
+        }
+    }
+
+    /// Connect to the RPC server at `addr` over TCP.
+    pub async fn connect(self, addr: impl ToSocketAddrs) -> Result<AsyncClient, std::io::Error> {
+        let stream = TcpStream::connect(addr).await?;
+        stream.set_nodelay(true)?;
+        Ok(self.build(stream))
+    }
+
+    /// Connect to the RPC server listening on the Unix domain socket at
+    /// `path`.
+    #[cfg(unix)]
+    pub async fn connect_unix(
+        self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<AsyncClient, std::io::Error> {
+        let stream = tokio::net::UnixStream::connect(path).await?;
+        Ok(self.build(stream))
+    }
+
+    /// Construct a client sending calls over the already-connected `stream`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime.
+    pub fn build<S>(self, stream: S) -> AsyncClient
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let (closed, closed_rx) = watch::channel(false);
+        let shared = Arc::new(Shared {
+            xids: XidGenerator::new(),
+            pending: Mutex::new(Some(HashMap::new())),
+            in_flight: Arc::new(Semaphore::new(self.max_in_flight)),
+            closed,
+            last_reply: Mutex::new(Instant::now()),
+        });
+
+        let (reader, writer) = tokio::io::split(stream);
+        let (tx, rx) = mpsc::channel(self.max_in_flight);
+
+        tokio::spawn(read_replies(
+            reader,
+            Arc::clone(&shared),
+            closed_rx.clone(),
+            self.max_reply_len,
+        ));
+        tokio::spawn(write_calls(
+            writer,
+            Arc::clone(&shared),
+            closed_rx.clone(),
+            rx,
+        ));
+
+        let client = AsyncClient {
+            #[cfg(feature = "tower")]
+            ready: Readiness::new(Arc::clone(&shared.in_flight)),
+            shared,
+            writer: tx,
+            credentials: self.credentials,
+            verifier_check: self.verifier_check,
+        };
+
+        if let Some(keepalive) = self.keepalive {
+            tokio::spawn(keep_alive(client.downgrade(), closed_rx, keepalive));
+        }
+
+        client
+    }
+}
+
+impl Default for AsyncClientBuilder {
+    fn default() -> Self {
+        Self {
+            credentials: AuthFlavor::AuthNone(None),
+            max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+            max_reply_len: DEFAULT_MAX_REPLY_LEN,
+            verifier_check: None,
+            keepalive: None,
+        }
+    }
+}
+
+/// An async RPC client multiplexing many concurrent calls over a single stream
+/// connection.
+///
+/// Calls are written to the connection as they are made, and replies are
+/// routed to the waiting call by transaction ID, allowing the server to reply
+/// in any order. Replies with an unknown transaction ID (such as the reply to a
+/// cancelled call) are logged and discarded.
+///
+/// At most [`AsyncClientBuilder::with_max_in_flight()`] calls await a reply at
+/// any one time, with further calls waiting for an in-flight call to complete.
+///
+/// Dropping a call future cancels the call, and any reply is discarded. If the
+/// connection is closed or fails, all pending calls (and any later calls)
+/// return [`ClientError::Disconnected`].
+///
+/// `AsyncClient` is cheap to clone, with all clones sharing the same
+/// connection. The connection is closed once all clones are dropped.
+///
+/// ```no_run
+/// use onc_rpc::client::AsyncClient;
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let client = AsyncClient::builder()
+///     .with_max_in_flight(16)
+///     .connect("127.0.0.1:2049")
+///     .await?;
+///
+/// // Call the NULL procedure of NFSv3 twice, concurrently.
+/// let (a, b) = tokio::join!(
+///     client.call(100003, 3, 0, &[]),
+///     client.call(100003, 3, 0, &[]),
+/// );
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct AsyncClient {
+    shared: Arc<Shared>,
+    writer: mpsc::Sender<Vec<u8>>,
+    credentials: AuthFlavor<Vec<u8>>,
+    verifier_check: Option<VerifierCheck>,
+
+    /// The in-flight permit acquired by `Service::poll_ready()`.
+    #[cfg(feature = "tower")]
+    pub(crate) ready: Readiness,
+}
+
+impl AsyncClient {
+    /// Configure a new client.
+    pub fn builder() -> AsyncClientBuilder {
+        AsyncClientBuilder::default()
+    }
+
+    /// Construct a client with the default configuration, sending calls over
+    /// the already-connected `stream`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime.
+    pub fn new<S>(stream: S) -> Self
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        AsyncClientBuilder::default().build(stream)
+    }
+
+    /// The credentials sent with each call made by this handle.
+    pub fn credentials(&self) -> &AuthFlavor<Vec<u8>> {
+        &self.credentials
+    }
+
+    /// Replace the credentials sent with subsequent calls made by this handle.
+    ///
+    /// Clones of this client are unaffected.
+    pub fn set_credentials(&mut self, credentials: AuthFlavor<Vec<u8>>) {
+        self.credentials = credentials;
+    }
+
+    /// Returns true if the connection has closed.
+    pub fn is_closed(&self) -> bool {
+        *self.shared.closed.borrow()
+    }
+
+    /// Call `procedure` of `program` / `program_version` with the serialised
+    /// `args`, returning the serialised result of a successful call.
+    ///
+    /// Dropping the returned future cancels the call.
+    pub async fn call(
+        &self,
+        program: u32,
+        program_version: u32,
+        procedure: u32,
+        args: &[u8],
+    ) -> Result<Vec<u8>, ClientError> {
+        self.call_with_xid(None, program, program_version, procedure, args, &mut false)
+            .await
+    }
+
+    /// Call the NULL procedure (procedure 0) of `program` / `program_version`,
+    /// returning the round-trip time.
+    pub async fn ping(&self, program: u32, program_version: u32) -> Result<Duration, ClientError> {
+        let start = Instant::now();
+        self.call(program, program_version, 0, &[]).await?;
+        Ok(start.elapsed())
+    }
+
+    /// Returns a handle that does not keep the connection open.
+    fn downgrade(&self) -> WeakClient {
+        WeakClient {
+            shared: Arc::downgrade(&self.shared),
+            writer: self.writer.downgrade(),
+            credentials: self.credentials.clone(),
+        }
+    }
+
+    /// Allocate an xid from the sequence used for calls on this connection.
+    pub(crate) fn next_xid(&self) -> u32 {
+        self.shared.xids.next()
+    }
+
+    /// Make a call with the given `xid`, or a newly allocated xid if `None`.
+    ///
+    /// `sent` is set once the call has been queued for sending, after which
+    /// the server may have received (and executed) it.
+    pub(crate) async fn call_with_xid(
+        &self,
+        xid: Option<u32>,
+        program: u32,
+        program_version: u32,
+        procedure: u32,
+        args: &[u8],
+        sent: &mut bool,
+    ) -> Result<Vec<u8>, ClientError> {
+        let body = CallBody::new(
+            program,
+            program_version,
+            procedure,
+            self.credentials.as_borrowed(),
+            AuthFlavor::AuthNone(None),
+            args,
+        );
+
+        let permit = Arc::clone(&self.shared.in_flight)
+            .acquire_owned()
+            .await
+            .map_err(|_| ClientError::Disconnected)?;
+
+        self.send_call(xid, body, sent, Some(permit)).await
+    }
+
+    /// Call the NULL procedure of `program` / `program_version` without
+    /// acquiring an in-flight permit.
+    ///
+    /// Keepalive pings must not wait behind calls occupying every permit, or a
+    /// busy connection with slow replies would be closed as unresponsive.
+    async fn keepalive_ping(&self, program: u32, program_version: u32) -> Result<(), ClientError> {
+        let body = CallBody::new(
+            program,
+            program_version,
+            0,
+            self.credentials.as_borrowed(),
+            AuthFlavor::AuthNone(None),
+            &[] as &[u8],
+        );
+
+        self.send_call(None, body, &mut false, None).await?;
+        Ok(())
+    }
+
+    /// Send `body` with the given `xid`, or a newly allocated xid if `None`,
+    /// returning the serialised result of a successful call.
+    ///
+    /// `sent` is set once the call has been queued for sending. The in-flight
+    /// `permit`, if any, is held until the call completes.
+    pub(crate) async fn send_call<T, P>(
+        &self,
+        xid: Option<u32>,
+        body: CallBody<T, P>,
+        sent: &mut bool,
+        permit: Option<OwnedSemaphorePermit>,
+    ) -> Result<Vec<u8>, ClientError>
+    where
+        T: AsRef<[u8]>,
+        P: AsRef<[u8]>,
+    {
+        let _permit = permit;
+
+        let (tx, rx) = oneshot::channel();
+        let pending = self.shared.register(xid, tx)?;
+
+        let mut buf = Vec::new();
+        RpcMessage::new(pending.xid, MessageType::Call(body)).serialise_into(&mut buf)?;
+
+        self.writer
+            .send(buf)
+            .await
+            .map_err(|_| ClientError::Disconnected)?;
+        *sent = true;
+
+        let reply = rx.await.map_err(|_| ClientError::Disconnected)?;
+        drop(pending);
+
+        let msg = RpcMessage::try_from(reply.as_slice()).map_err(ClientError::Rpc)?;
+        reply_result(msg, self.verifier_check.as_ref())
+    }
+}
+
+/// Read reply records from `reader`, routing them to the pending call with the
+/// matching xid.
+async fn read_replies<R>(
+    mut reader: R,
+    shared: Arc<Shared>,
+    mut closed: watch::Receiver<bool>,
+    max_reply_len: usize,
+) where
+    R: AsyncRead + Unpin + Send,
+{
+    let mut buf = Vec::new();
+    loop {
+        let res = tokio::select! {
+            res = read_record_async(&mut reader, &mut buf, max_reply_len) => res,
+            _ = closed.wait_for(|v| *v) => break,
+        };
+
+        if let Err(e) = res {
+            tracing::debug!(error=%e, "rpc client connection closed");
+            break;
+        }
+
+        let Some(xid) = buf
+            .get(4..8)
+            .map(|v| u32::from_be_bytes(v.try_into().unwrap()))
+        else {
+            tracing::warn!(len = buf.len(), "discarding short rpc reply");
+            continue;
+        };
+
+        *shared.last_reply.lock().unwrap() = Instant::now();
+
+        let tx = shared
+            .pending
+            .lock()
+            .unwrap()
+            .as_mut()
+            .and_then(|p| p.remove(&xid));
+
+        match tx {
+            Some(tx) => {
+                // The call may have been cancelled since the lookup, in which
+                // case the reply is discarded.
+                let _ = tx.send(std::mem::take(&mut buf));
+            }
+            None => tracing::debug!(xid, "discarding rpc reply with unknown xid"),
+        }
+    }
+
+    shared.disconnect();
+}
+
+/// A handle to an [`AsyncClient`] connection that does not keep it open.
+#[derive(Debug)]
+struct WeakClient {
+    shared: Weak<Shared>,
+    writer: mpsc::WeakSender<Vec<u8>>,
+    credentials: AuthFlavor<Vec<u8>>,
+}
+
+impl WeakClient {
+    /// Return a client for the connection, or `None` if all client handles
+    /// have been dropped.
+    fn upgrade(&self) -> Option<AsyncClient> {
+        let shared = self.shared.upgrade()?;
+        Some(AsyncClient {
+            #[cfg(feature = "tower")]
+            ready: Readiness::new(Arc::clone(&shared.in_flight)),
+            shared,
+            writer: self.writer.upgrade()?,
+            credentials: self.credentials.clone(),
+            verifier_check: None,
+        })
+    }
+}
+
+/// Ping the server once the connection has been idle for the keepalive
+/// interval, disconnecting after too many consecutive failures.
+async fn keep_alive(client: WeakClient, mut closed: watch::Receiver<bool>, keepalive: Keepalive) {
+    let mut failures = 0;
+    let mut last_ping = Instant::now();
+
+    loop {
+        let Some(shared) = client.shared.upgrade() else {
+            return;
+        };
+        let last_reply = *shared.last_reply.lock().unwrap();
+        drop(shared);
+
+        // Wait until the connection has been idle for the interval, and at
+        // least the interval has passed since the last failed ping.
+        let deadline = last_reply.max(last_ping) + keepalive.interval;
+        if deadline > Instant::now() {
+            tokio::select! {
+                _ = tokio::time::sleep_until(deadline.into()) => continue,
+                _ = closed.wait_for(|v| *v) => return,
+            }
+        }
+
+        // Do not keep the connection open once all other handles are dropped.
+        let Some(c) = client.upgrade() else {
+            return;
+        };
+
+        last_ping = Instant::now();
+        let res = tokio::time::timeout(
+            keepalive.timeout,
+            c.keepalive_ping(keepalive.program, keepalive.version),
+        )
+        .await;
+
+        match res {
+            Ok(Ok(_) | Err(ClientError::Reply(_))) => {
+                failures = 0;
+                continue;
+            }
+            Ok(Err(ClientError::Disconnected)) => return,
+            Ok(Err(e)) => tracing::debug!(error=%e, "rpc keepalive ping failed"),
+            Err(_) => tracing::debug!("rpc keepalive ping timed out"),
+        }
+
+        failures += 1;
+        if failures >= keepalive.max_failures {
+            tracing::warn!(failures, "rpc keepalive failed, closing connection");
+            c.shared.disconnect();
+            return;
+        }
+    }
+}
+
+/// Write serialised call records received from `rx` to `writer`.
+async fn write_calls<W>(
+    mut writer: W,
+    shared: Arc<Shared>,
+    mut closed: watch::Receiver<bool>,
+    mut rx: mpsc::Receiver<Vec<u8>>,
+) where
+    W: AsyncWrite + Unpin + Send,
+{
+    loop {
+        let buf = tokio::select! {
+            buf = rx.recv() => buf,
+            _ = closed.wait_for(|v| *v) => break,
+        };
+
+        // All client handles have been dropped.
+        let Some(buf) = buf else {
+            let _ = writer.shutdown().await;
+            break;
+        };
+
+        if let Err(e) = writer.write_all(&buf).await {
+            tracing::debug!(error=%e, "rpc client write failed");
+            break;
+        }
+    }
+
+    shared.disconnect();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use tokio::{
+        io::AsyncWriteExt,
+        net::TcpListener,
+        sync::mpsc::{unbounded_channel, UnboundedReceiver},
+    };
+
+    use super::*;
+    use crate::{AcceptedReply, AcceptedStatus, ReplyBody};
+
+    fn success(xid: u32, payload: &[u8]) -> Vec<u8> {
+        RpcMessage::new(
+            xid,
+            MessageType::Reply(ReplyBody::<&[u8], &[u8]>::Accepted(AcceptedReply::new(
+                AuthFlavor::AuthNone(None),
+                AcceptedStatus::Success(payload),
+            ))),
+        )
+        .serialise()
+        .unwrap()
+    }
+
+    /// Accept a single connection, passing each call (xid and payload) to the
+    /// returned channel, and writing the records received from the returned
+    /// sender.
+    async fn server() -> (
+        TcpStream,
+        UnboundedReceiver<(u32, Vec<u8>)>,
+        mpsc::UnboundedSender<Option<Vec<u8>>>,
+    ) {
+        let l = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(l.local_addr().unwrap()).await.unwrap();
+        let (conn, _) = l.accept().await.unwrap();
+        let (mut r, mut w) = conn.into_split();
+
+        let (calls_tx, calls_rx) = unbounded_channel();
+        tokio::spawn(async move {
+            let mut buf = Vec::new();
+            while read_record_async(&mut r, &mut buf, 1024).await.is_ok() {
+                let msg = RpcMessage::try_from(buf.as_slice()).unwrap();
+                let payload = msg.call_body().unwrap().payload().to_vec();
+                let _ = calls_tx.send((msg.xid(), payload));
+            }
+        });
+
+        // A `None` closes the connection.
+        let (replies_tx, mut replies_rx) = unbounded_channel::<Option<Vec<u8>>>();
+        tokio::spawn(async move {
+            while let Some(Some(r)) = replies_rx.recv().await {
+                w.write_all(&r).await.unwrap();
+            }
+        });
+
+        (client, calls_rx, replies_tx)
+    }
+
+    #[tokio::test]
+    async fn test_out_of_order_replies() {
+        let (conn, mut calls, replies) = server().await;
+        let client = AsyncClient::new(conn);
+
+        let server = async {
+            let mut got = Vec::new();
+            for _ in 0..3 {
+                got.push(calls.recv().await.unwrap());
+            }
+            // Reply in reverse order, preceded by a reply to an unknown xid.
+            replies
+                .send(Some(success(got[0].0 ^ 0xFFFF, b"unknown")))
+                .unwrap();
+            for (xid, payload) in got.into_iter().rev() {
+                replies.send(Some(success(xid, &payload))).unwrap();
+            }
+        };
+
+        let (a, b, c, ()) = tokio::join!(
+            client.call(1, 1, 1, b"a"),
+            client.call(1, 1, 1, b"b"),
+            client.call(1, 1, 1, b"c"),
+            server,
+        );
+        assert_eq!(a.unwrap(), b"a");
+        assert_eq!(b.unwrap(), b"b");
+        assert_eq!(c.unwrap(), b"c");
+    }
+
+    #[tokio::test]
+    async fn test_max_in_flight() {
+        let (conn, mut calls, replies) = server().await;
+        let client = AsyncClient::builder().with_max_in_flight(2).build(conn);
+
+        let mut handles = Vec::new();
+        for i in 0..3_u8 {
+            let client = client.clone();
+            handles.push(tokio::spawn(
+                async move { client.call(1, 1, 1, &[i]).await },
+            ));
+        }
+
+        // Only two calls are sent until one completes.
+        let (xid, payload) = calls.recv().await.unwrap();
+        calls.recv().await.unwrap();
+        assert!(
+            tokio::time::timeout(Duration::from_millis(50), calls.recv())
+                .await
+                .is_err()
+        );
+
+        replies.send(Some(success(xid, &payload))).unwrap();
+        let (xid, payload) = calls.recv().await.unwrap();
+        replies.send(Some(success(xid, &payload))).unwrap();
+
+        // Close the connection, failing the remaining call.
+        replies.send(None).unwrap();
+
+        let mut results = Vec::new();
+        for h in handles {
+            results.push(h.await.unwrap());
+        }
+        assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 2);
+        assert!(results
+            .iter()
+            .any(|r| matches!(r, Err(ClientError::Disconnected))));
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_fails_pending() {
+        let (conn, mut calls, replies) = server().await;
+        let client = AsyncClient::new(conn);
+
+        let call = tokio::spawn({
+            let client = client.clone();
+            async move { client.call(1, 1, 1, &[]).await }
+        });
+        calls.recv().await.unwrap();
+        replies.send(None).unwrap();
+
+        assert!(matches!(
+            call.await.unwrap(),
+            Err(ClientError::Disconnected)
+        ));
+        assert!(client.is_closed());
+        assert!(matches!(
+            client.call(1, 1, 1, &[]).await,
+            Err(ClientError::Disconnected)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_cancel() {
+        let (conn, mut calls, replies) = server().await;
+        let client = AsyncClient::builder().with_max_in_flight(1).build(conn);
+
+        // Time out (and drop) a call.
+        assert!(tokio::time::timeout(
+            Duration::from_millis(20),
+            client.call(1, 1, 1, b"cancelled")
+        )
+        .await
+        .is_err());
+        let (cancelled, _) = calls.recv().await.unwrap();
+        assert!(client
+            .shared
+            .pending
+            .lock()
+            .unwrap()
+            .as_ref()
+            .unwrap()
+            .is_empty());
+
+        // The in-flight permit is released, and the late reply is discarded.
+        let next = tokio::spawn({
+            let client = client.clone();
+            async move { client.call(1, 1, 1, b"next").await }
+        });
+        let (xid, payload) = calls.recv().await.unwrap();
+        replies.send(Some(success(cancelled, b"late"))).unwrap();
+        replies.send(Some(success(xid, &payload))).unwrap();
+
+        assert_eq!(next.await.unwrap().unwrap(), b"next");
+    }
+
+    #[tokio::test]
+    async fn test_max_reply_len_closes() {
+        let (conn, mut calls, replies) = server().await;
+        let client = AsyncClient::builder().with_max_reply_len(16).build(conn);
+
+        let call = tokio::spawn({
+            let client = client.clone();
+            async move { client.call(1, 1, 1, &[]).await }
+        });
+        let (xid, _) = calls.recv().await.unwrap();
+        replies.send(Some(success(xid, &[42; 64]))).unwrap();
+
+        assert!(matches!(
+            call.await.unwrap(),
+            Err(ClientError::Disconnected)
+        ));
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn test_unix_socket() {
+        let path =
+            std::env::temp_dir().join(format!("onc-rpc-async-client-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let l = tokio::net::UnixListener::bind(&path).unwrap();
+
+        let client = AsyncClient::builder().connect_unix(&path).await.unwrap();
+        let (mut conn, _) = l.accept().await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        tokio::spawn(async move {
+            let mut buf = Vec::new();
+            while read_record_async(&mut conn, &mut buf, 1024).await.is_ok() {
+                let msg = RpcMessage::try_from(buf.as_slice()).unwrap();
+                let reply = success(msg.xid(), msg.call_body().unwrap().payload());
+                conn.write_all(&reply).await.unwrap();
+            }
+        });
+
+        assert_eq!(client.call(1, 1, 1, b"ping").await.unwrap(), b"ping");
+    }
+
+    /// Reply to every call received from `calls` while `answer` is set.
+    fn responder(
+        mut calls: UnboundedReceiver<(u32, Vec<u8>)>,
+        replies: mpsc::UnboundedSender<Option<Vec<u8>>>,
+        answer: Arc<AtomicBool>,
+    ) -> Arc<AtomicUsize> {
+        let count = Arc::new(AtomicUsize::new(0));
+        tokio::spawn({
+            let count = Arc::clone(&count);
+            async move {
+                while let Some((xid, payload)) = calls.recv().await {
+                    count.fetch_add(1, Ordering::Relaxed);
+                    if answer.load(Ordering::Relaxed) {
+                        let _ = replies.send(Some(success(xid, &payload)));
+                    }
+                }
+            }
+        });
+        count
+    }
+
+    #[tokio::test]
+    async fn test_ping() {
+        let (conn, calls, replies) = server().await;
+        let answer = Arc::new(AtomicBool::new(true));
+        let count = responder(calls, replies, answer);
+        let client = AsyncClient::new(conn);
+
+        let rtt = client.ping(100003, 3).await.unwrap();
+        assert!(rtt < Duration::from_secs(5));
+        assert_eq!(count.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_keepalive() {
+        let (conn, calls, replies) = server().await;
+        let answer = Arc::new(AtomicBool::new(true));
+        let count = responder(calls, replies, Arc::clone(&answer));
+
+        let client = AsyncClient::builder()
+            .with_keepalive(
+                Keepalive::new(1, 1)
+                    .with_interval(Duration::from_millis(20))
+                    .with_timeout(Duration::from_millis(20))
+                    .with_max_failures(3),
+            )
+            .build(conn);
+
+        // The idle connection is pinged, and kept open while the server
+        // answers.
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(!client.is_closed());
+        let pings = count.load(Ordering::Relaxed);
+        assert!(pings >= 3, "pings={pings}");
+
+        // Once the server stops answering, the connection is closed after 3
+        // failed pings.
+        answer.store(false, Ordering::Relaxed);
+        let before = count.load(Ordering::Relaxed);
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !client.is_closed() {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        // A ping may have been answered before the flag was cleared.
+        let failed = count.load(Ordering::Relaxed) - before;
+        assert!((3..=4).contains(&failed), "failed={failed}");
+        assert!(matches!(
+            client.call(1, 1, 1, &[]).await,
+            Err(ClientError::Disconnected)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_keepalive_at_max_in_flight() {
+        let (conn, mut calls, replies) = server().await;
+
+        // Answer pings (with an empty payload) but never the other calls.
+        tokio::spawn(async move {
+            while let Some((xid, payload)) = calls.recv().await {
+                if payload.is_empty() {
+                    let _ = replies.send(Some(success(xid, &[])));
+                }
+            }
+        });
+
+        let client = AsyncClient::builder()
+            .with_max_in_flight(1)
+            .with_keepalive(
+                Keepalive::new(1, 1)
+                    .with_interval(Duration::from_millis(20))
+                    .with_timeout(Duration::from_millis(20))
+                    .with_max_failures(2),
+            )
+            .build(conn);
+
+        // Occupy the only in-flight permit.
+        let stuck = tokio::spawn({
+            let client = client.clone();
+            async move { client.call(1, 1, 1, b"stuck").await }
+        });
+
+        // The pings are sent and answered without waiting for the permit, so
+        // the connection stays open.
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(!client.is_closed());
+        assert!(!stuck.is_finished());
+        stuck.abort();
+    }
+
+    #[tokio::test]
+    async fn test_keepalive_not_sent_while_active() {
+        let (conn, calls, replies) = server().await;
+        let answer = Arc::new(AtomicBool::new(true));
+        let count = responder(calls, replies, answer);
+
+        let client = AsyncClient::builder()
+            .with_keepalive(Keepalive::new(1, 1).with_interval(Duration::from_millis(200)))
+            .build(conn);
+
+        for _ in 0..10 {
+            client.call(1, 1, 1, b"busy").await.unwrap();
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        // Only the calls were sent.
+        assert_eq!(count.load(Ordering::Relaxed), 10);
+    }
+}
@@ -0,0 +1,401 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    net::SocketAddr,
+    ops::RangeInclusive,
+    sync::{Mutex, PoisonError},
+};
+
+use crate::{client::ClientError, RpcError};
+
+/// Negotiates the highest program version supported by both the caller and a
+/// server, caching the result per server and program.
+///
+/// The server is probed with NULL (procedure 0) calls, starting at the highest
+/// version the caller supports. When the server replies with `PROG_MISMATCH`,
+/// the next probe uses the highest version within both the caller range and
+/// the range the server reported, until a probe succeeds or no common version
+/// remains.
+///
+/// Servers are identified by a key of type `K` - the server [`SocketAddr`] by
+/// default.
+///
+/// ```no_run
+/// use std::net::TcpStream;
+///
+/// use onc_rpc::client::{Client, VersionNegotiator};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// const MOUNT_PROGRAM: u32 = 100005;
+///
+/// let negotiator = VersionNegotiator::new();
+///
+/// let mut client = Client::<TcpStream>::connect("127.0.0.1:635")?;
+/// let addr = client.get_ref().peer_addr()?;
+///
+/// // Prefer MOUNT v3, falling back to v1.
+/// let version = negotiator.negotiate(addr, MOUNT_PROGRAM, 1..=3, |vers| {
+///     client.call(MOUNT_PROGRAM, vers, 0, &[])
+/// })?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct VersionNegotiator<K = SocketAddr> {
+    cache: Mutex<HashMap<(K, u32), u32>>,
+}
+
+impl<K> VersionNegotiator<K>
+where
+    K: Eq + Hash,
+{
+    /// Construct a negotiator with an empty cache.
+    pub fn new() -> Self {
+        Self {
+            cache: Mutex::default(),
+        }
+    }
+
+    /// Return the cached version of `program` negotiated with `server`, if
+    /// any.
+    pub fn cached(&self, server: &K, program: u32) -> Option<u32>
+    where
+        K: Clone,
+    {
+        self.lock().get(&(server.clone(), program)).copied()
+    }
+
+    /// Remove the cached version of `program` negotiated with `server`,
+    /// causing the next negotiation to probe the server again.
+    ///
+    /// This should be called if the server may have changed the versions it
+    /// supports, such as after a restart.
+    pub fn forget(&self, server: &K, program: u32)
+    where
+        K: Clone,
+    {
+        self.lock().remove(&(server.clone(), program));
+    }
+
+    /// Return the highest version of `program` within `versions` supported by
+    /// `server`, calling `ping` with each version to probe.
+    ///
+    /// `ping` must make a NULL call to `program` at the given version. If a
+    /// version within `versions` was previously negotiated with `server`, it
+    /// is returned without probing. A cached version outside of `versions` is
+    /// only used to skip probing versions above it.
+    ///
+    /// If there is no version supported by both sides,
+    /// [`RpcError::ProgramMismatch`] is returned with the range reported by the
+    /// server. Any other error returned by `ping` is returned as-is, and
+    /// nothing is cached.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `versions` is empty.
+    pub fn negotiate<F>(
+        &self,
+        server: K,
+        program: u32,
+        versions: RangeInclusive<u32>,
+        mut ping: F,
+    ) -> Result<u32, ClientError>
+    where
+        F: FnMut(u32) -> Result<Vec<u8>, ClientError>,
+    {
+        assert!(!versions.is_empty(), "empty version range");
+
+        let key = (server, program);
+        let cached = self.lock().get(&key).copied();
+        if let Some(v) = cached.filter(|v| versions.contains(v)) {
+            return Ok(v);
+        }
+
+        let mut candidate = first_candidate(cached, &versions);
+        loop {
+            match ping(candidate) {
+                Ok(_) => break,
+                Err(e) => candidate = next_candidate(candidate, &versions, e)?,
+            }
+        }
+
+        self.record(key, candidate);
+        Ok(candidate)
+    }
+
+    /// Return the highest version of `program` within `versions` supported by
+    /// `server`, awaiting `ping` with each version to probe.
+    ///
+    /// This is the async equivalent of [`VersionNegotiator::negotiate()`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `versions` is empty.
+    #[cfg(feature = "tokio")]
+    pub async fn negotiate_async<F, Fut>(
+        &self,
+        server: K,
+        program: u32,
+        versions: RangeInclusive<u32>,
+        mut ping: F,
+    ) -> Result<u32, ClientError>
+    where
+        F: FnMut(u32) -> Fut,
+        Fut: std::future::Future<Output = Result<Vec<u8>, ClientError>>,
+    {
+        assert!(!versions.is_empty(), "empty version range");
+
+        let key = (server, program);
+        let cached = self.lock().get(&key).copied();
+        if let Some(v) = cached.filter(|v| versions.contains(v)) {
+            return Ok(v);
+        }
+
+        let mut candidate = first_candidate(cached, &versions);
+        loop {
+            match ping(candidate).await {
+                Ok(_) => break,
+                Err(e) => candidate = next_candidate(candidate, &versions, e)?,
+            }
+        }
+
+        self.record(key, candidate);
+        Ok(candidate)
+    }
+
+    /// Cache `version` as negotiated for `key`, unless a higher version was
+    /// already negotiated (and this negotiation was limited to a narrower
+    /// range of versions).
+    fn record(&self, key: (K, u32), version: u32) {
+        let mut cache = self.lock();
+        let v = cache.entry(key).or_insert(version);
+        *v = (*v).max(version);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<(K, u32), u32>> {
+        // The map is always left in a consistent state.
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<K> Default for VersionNegotiator<K>
+where
+    K: Eq + Hash,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Return the first version to probe, skipping the versions in `versions` above
+/// the `cached` version previously negotiated with the server.
+fn first_candidate(cached: Option<u32>, versions: &RangeInclusive<u32>) -> u32 {
+    match cached {
+        Some(v) => v.clamp(*versions.start(), *versions.end()),
+        None => *versions.end(),
+    }
+}
+
+/// Return the next version to probe after `candidate` failed with `err`.
+///
+/// The next candidate is always lower than `candidate`, ensuring negotiation
+/// terminates even if the server reports a range that includes `candidate`.
+fn next_candidate(
+    candidate: u32,
+    versions: &RangeInclusive<u32>,
+    err: ClientError,
+) -> Result<u32, ClientError> {
+    let ClientError::Reply(RpcError::ProgramMismatch { low, high }) = err else {
+        return Err(err);
+    };
+
+    let next = candidate.saturating_sub(1).min(high);
+    if candidate == 0 || next < low || next < *versions.start() {
+        return Err(err);
+    }
+
+    Ok(next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Return a NULL procedure handler for a server supporting `supported`,
+    /// reporting the range of the lowest and highest supported version in
+    /// mismatch replies.
+    ///
+    /// Probed versions are appended to `probes`.
+    fn server<'a>(
+        supported: &'a [u32],
+        probes: &'a mut Vec<u32>,
+    ) -> impl FnMut(u32) -> Result<Vec<u8>, ClientError> + 'a {
+        move |vers| {
+            probes.push(vers);
+            if supported.contains(&vers) {
+                return Ok(vec![]);
+            }
+            Err(ClientError::Reply(RpcError::ProgramMismatch {
+                low: *supported.iter().min().unwrap(),
+                high: *supported.iter().max().unwrap(),
+            }))
+        }
+    }
+
+    #[test]
+    fn test_negotiate_highest_common() {
+        let n = VersionNegotiator::<&str>::new();
+        let mut probes = vec![];
+
+        // MOUNT v3 is preferred over v1.
+        let got = n.negotiate("a", 100005, 1..=3, server(&[1, 2, 3], &mut probes));
+        assert_eq!(got.unwrap(), 3);
+        assert_eq!(probes, [3]);
+
+        // The server only supports NLM v1 to v4, the caller up to v5.
+        probes.clear();
+        let got = n.negotiate("a", 100021, 1..=5, server(&[1, 3, 4], &mut probes));
+        assert_eq!(got.unwrap(), 4);
+        assert_eq!(probes, [5, 4]);
+    }
+
+    #[test]
+    fn test_negotiate_gap_in_server_range() {
+        let n = VersionNegotiator::<&str>::new();
+        let mut probes = vec![];
+
+        // The server reports 1 to 4, but does not support 2 or 3.
+        let got = n.negotiate("a", 1, 2..=3, server(&[1, 4], &mut probes));
+        assert!(matches!(
+            got,
+            Err(ClientError::Reply(RpcError::ProgramMismatch {
+                low: 1,
+                high: 4
+            }))
+        ));
+        assert_eq!(probes, [3, 2]);
+
+        probes.clear();
+        let got = n.negotiate("a", 1, 1..=3, server(&[1, 4], &mut probes));
+        assert_eq!(got.unwrap(), 1);
+        assert_eq!(probes, [3, 2, 1]);
+    }
+
+    #[test]
+    fn test_negotiate_no_common_version() {
+        let n = VersionNegotiator::<&str>::new();
+        let mut probes = vec![];
+
+        let got = n.negotiate("a", 1, 5..=6, server(&[1, 2, 3], &mut probes));
+        assert!(matches!(
+            got,
+            Err(ClientError::Reply(RpcError::ProgramMismatch {
+                low: 1,
+                high: 3
+            }))
+        ));
+        assert_eq!(probes, [6]);
+        assert_eq!(n.cached(&"a", 1), None);
+    }
+
+    #[test]
+    fn test_negotiate_other_error() {
+        let n = VersionNegotiator::<&str>::new();
+
+        let got = n.negotiate("a", 1, 1..=3, |_| {
+            Err(ClientError::Reply(RpcError::ProgramUnavailable))
+        });
+        assert!(matches!(
+            got,
+            Err(ClientError::Reply(RpcError::ProgramUnavailable))
+        ));
+        assert_eq!(n.cached(&"a", 1), None);
+    }
+
+    #[test]
+    fn test_negotiate_cached() {
+        let n = VersionNegotiator::<&str>::new();
+        let mut probes = vec![];
+
+        let got = n.negotiate("a", 1, 1..=3, server(&[1, 2], &mut probes));
+        assert_eq!(got.unwrap(), 2);
+        assert_eq!(n.cached(&"a", 1), Some(2));
+
+        // The cached version is returned without probing.
+        let got = n.negotiate("a", 1, 1..=3, |_| unreachable!());
+        assert_eq!(got.unwrap(), 2);
+
+        // Other servers and programs are negotiated separately.
+        assert_eq!(n.cached(&"b", 1), None);
+        assert_eq!(n.cached(&"a", 2), None);
+
+        n.forget(&"a", 1);
+        probes.clear();
+        let got = n.negotiate("a", 1, 1..=3, server(&[1], &mut probes));
+        assert_eq!(got.unwrap(), 1);
+        assert_eq!(probes, [3, 1]);
+    }
+
+    #[test]
+    fn test_negotiate_cached_outside_range() {
+        let n = VersionNegotiator::<&str>::new();
+        let mut probes = vec![];
+
+        let got = n.negotiate("a", 1, 1..=3, server(&[1, 2, 3], &mut probes));
+        assert_eq!(got.unwrap(), 3);
+
+        // A narrower range after a cache hit probes again, within the range.
+        probes.clear();
+        let got = n.negotiate("a", 1, 1..=2, server(&[1, 2, 3], &mut probes));
+        assert_eq!(got.unwrap(), 2);
+        assert_eq!(probes, [2]);
+
+        // The higher version remains cached for wider ranges.
+        assert_eq!(n.cached(&"a", 1), Some(3));
+        let got = n.negotiate("a", 1, 1..=3, |_| unreachable!());
+        assert_eq!(got.unwrap(), 3);
+
+        // Probing starts at the cached version when the range extends above
+        // it.
+        let n = VersionNegotiator::<&str>::new();
+        n.negotiate("a", 1, 1..=2, server(&[1, 2], &mut probes))
+            .unwrap();
+        probes.clear();
+        let got = n.negotiate("a", 1, 3..=5, server(&[1, 2], &mut probes));
+        assert!(matches!(
+            got,
+            Err(ClientError::Reply(RpcError::ProgramMismatch {
+                low: 1,
+                high: 2
+            }))
+        ));
+        assert_eq!(probes, [3]);
+        assert_eq!(n.cached(&"a", 1), Some(2));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_negotiate_async() {
+        let n = VersionNegotiator::<&str>::new();
+        let mut probes = vec![];
+
+        let got = n
+            .negotiate_async("a", 1, 1..=4, |vers| {
+                probes.push(vers);
+                async move {
+                    tokio::task::yield_now().await;
+                    match vers {
+                        1 | 2 => Ok(vec![]),
+                        _ => Err(ClientError::Reply(RpcError::ProgramMismatch {
+                            low: 1,
+                            high: 2,
+                        })),
+                    }
+                }
+            })
+            .await;
+
+        assert_eq!(got.unwrap(), 2);
+        assert_eq!(probes, [4, 2]);
+        assert_eq!(n.cached(&"a", 1), Some(2));
+    }
+}
@@ -0,0 +1,325 @@
+use std::future::Future;
+
+use tokio::sync::Mutex;
+
+use crate::client::{AsyncClient, Backoff, ClientError};
+
+/// An [`AsyncClient`] wrapper reconnecting to the server when the connection
+/// fails, and retrying calls where it is safe to do so.
+///
+/// Connections are established by calling the `connect` function given to
+/// [`ReconnectingClient::new()`]. When the connection breaks, the next call
+/// reconnects, retrying according to the [`Backoff`] schedule if the server is
+/// unreachable.
+///
+/// Calls that had not been sent when the connection broke are always retried
+/// on the new connection. Calls that may have been received by the server are
+/// only retried if made with [`ReconnectingClient::call_idempotent()`], and are
+/// sent with the same transaction ID so that a server duplicate request cache
+/// can recognise the retransmission. Calls made with
+/// [`ReconnectingClient::call()`] instead fail with
+/// [`ClientError::OutcomeUnknown`].
+///
+/// ```no_run
+/// use onc_rpc::client::{AsyncClient, ReconnectingClient};
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let client = ReconnectingClient::new(|| AsyncClient::builder().connect("127.0.0.1:2049"));
+///
+/// // NFSv3 GETATTR is idempotent.
+/// let reply = client.call_idempotent(100003, 3, 1, &[]).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct ReconnectingClient<F> {
+    connect: F,
+    backoff: Backoff,
+    conn: Mutex<Option<AsyncClient>>,
+}
+
+impl<F, Fut> ReconnectingClient<F>
+where
+    F: Fn() -> Fut + Send + Sync,
+    Fut: Future<Output = Result<AsyncClient, std::io::Error>> + Send,
+{
+    /// Construct a client establishing connections with `connect`, using the
+    /// default [`Backoff`] schedule.
+    ///
+    /// No connection is made until the first call.
+    pub fn new(connect: F) -> Self {
+        Self {
+            connect,
+            backoff: Backoff::default(),
+            conn: Mutex::default(),
+        }
+    }
+
+    /// Retry connection attempts, and calls interrupted by a broken
+    /// connection, according to `backoff`.
+    pub fn with_backoff(self, backoff: Backoff) -> Self {
+        Self { backoff, ..self }
+    }
+
+    /// Call `procedure` of `program` / `program_version` with the serialised
+    /// `args`, returning the serialised result of a successful call.
+    ///
+    /// If the connection breaks after the call is sent,
+    /// [`ClientError::OutcomeUnknown`] is returned.
+    pub async fn call(
+        &self,
+        program: u32,
+        program_version: u32,
+        procedure: u32,
+        args: &[u8],
+    ) -> Result<Vec<u8>, ClientError> {
+        self.call_with_retry(false, program, program_version, procedure, args)
+            .await
+    }
+
+    /// Call `procedure` of `program` / `program_version` with the serialised
+    /// `args`, retrying with the same transaction ID on a new connection if the
+    /// connection breaks.
+    ///
+    /// The call must be safe for the server to execute more than once.
+    pub async fn call_idempotent(
+        &self,
+        program: u32,
+        program_version: u32,
+        procedure: u32,
+        args: &[u8],
+    ) -> Result<Vec<u8>, ClientError> {
+        self.call_with_retry(true, program, program_version, procedure, args)
+            .await
+    }
+
+    async fn call_with_retry(
+        &self,
+        idempotent: bool,
+        program: u32,
+        program_version: u32,
+        procedure: u32,
+        args: &[u8],
+    ) -> Result<Vec<u8>, ClientError> {
+        let mut call_xid = None;
+        let mut waits = self.backoff.intervals().take(self.backoff.retries());
+
+        loop {
+            let conn = self.connection().await?;
+
+            // Allocate the xid from the connection, so it cannot collide with
+            // the connection's keepalive pings, and reuse it for any retries.
+            let xid = *call_xid.get_or_insert_with(|| conn.next_xid());
+
+            let mut sent = false;
+            let res = conn
+                .call_with_xid(
+                    Some(xid),
+                    program,
+                    program_version,
+                    procedure,
+                    args,
+                    &mut sent,
+                )
+                .await;
+
+            match res {
+                Err(ClientError::Disconnected) if sent && !idempotent => {
+                    return Err(ClientError::OutcomeUnknown)
+                }
+                Err(ClientError::Disconnected) => {
+                    let Some(wait) = waits.next() else {
+                        return Err(ClientError::Disconnected);
+                    };
+                    tracing::debug!(xid, sent, ?wait, "retrying call after connection loss");
+                    tokio::time::sleep(wait).await;
+                }
+                v => return v,
+            }
+        }
+    }
+
+    /// Return the current connection, reconnecting if it has closed.
+    async fn connection(&self) -> Result<AsyncClient, ClientError> {
+        let mut conn = self.conn.lock().await;
+        if let Some(c) = conn.as_ref().filter(|c| !c.is_closed()) {
+            return Ok(c.clone());
+        }
+
+        let mut waits = self.backoff.intervals().take(self.backoff.retries());
+        loop {
+            match (self.connect)().await {
+                Ok(c) => {
+                    *conn = Some(c.clone());
+                    return Ok(c);
+                }
+                Err(e) => {
+                    let Some(wait) = waits.next() else {
+                        return Err(e.into());
+                    };
+                    tracing::debug!(error=%e, ?wait, "rpc client reconnect failed");
+                    tokio::time::sleep(wait).await;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::{
+        io::AsyncWriteExt,
+        net::TcpListener,
+        sync::mpsc::{unbounded_channel, UnboundedReceiver},
+    };
+
+    use super::*;
+    use crate::{
+        auth::AuthFlavor, read_record_async, AcceptedReply, AcceptedStatus, MessageType, ReplyBody,
+        RpcMessage,
+    };
+
+    /// Serve on `l`, reading a single call and then killing the server,
+    /// before restarting it to echo all further calls.
+    ///
+    /// The xid of every call received is sent to the returned channel.
+    async fn kill_and_restart(l: TcpListener) -> UnboundedReceiver<u32> {
+        let addr = l.local_addr().unwrap();
+        let (tx, rx) = unbounded_channel();
+
+        tokio::spawn(async move {
+            let (mut conn, _) = l.accept().await.unwrap();
+            let mut buf = Vec::new();
+            read_record_async(&mut conn, &mut buf, 1024).await.unwrap();
+            tx.send(RpcMessage::try_from(buf.as_slice()).unwrap().xid())
+                .unwrap();
+
+            // Kill the server mid-call, and restart it shortly after.
+            drop(conn);
+            drop(l);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+
+            let l = TcpListener::bind(addr).await.unwrap();
+            loop {
+                let (mut conn, _) = l.accept().await.unwrap();
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    let mut buf = Vec::new();
+                    while read_record_async(&mut conn, &mut buf, 1024).await.is_ok() {
+                        let msg = RpcMessage::try_from(buf.as_slice()).unwrap();
+                        let _ = tx.send(msg.xid());
+                        let reply = RpcMessage::new(
+                            msg.xid(),
+                            MessageType::Reply(ReplyBody::<&[u8], &[u8]>::Accepted(
+                                AcceptedReply::new(
+                                    AuthFlavor::AuthNone(None),
+                                    AcceptedStatus::Success(msg.call_body().unwrap().payload()),
+                                ),
+                            )),
+                        );
+                        conn.write_all(&reply.serialise().unwrap()).await.unwrap();
+                    }
+                });
+            }
+        });
+
+        rx
+    }
+
+    fn backoff() -> Backoff {
+        Backoff::new(Duration::from_millis(10))
+            .with_factor(1)
+            .with_retries(20)
+    }
+
+    #[tokio::test]
+    async fn test_idempotent_call_replayed() {
+        let l = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = l.local_addr().unwrap();
+        let mut xids = kill_and_restart(l).await;
+        let client = ReconnectingClient::new(move || AsyncClient::builder().connect(addr))
+            .with_backoff(backoff());
+
+        let got = client.call_idempotent(1, 1, 1, b"hello").await.unwrap();
+        assert_eq!(got, b"hello");
+
+        // The call was replayed to the restarted server with the same xid.
+        let first = xids.recv().await.unwrap();
+        assert_eq!(xids.recv().await.unwrap(), first);
+    }
+
+    #[tokio::test]
+    async fn test_non_idempotent_outcome_unknown() {
+        let l = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = l.local_addr().unwrap();
+        let mut xids = kill_and_restart(l).await;
+        let client = ReconnectingClient::new(move || AsyncClient::builder().connect(addr))
+            .with_backoff(backoff());
+
+        assert!(matches!(
+            client.call(1, 1, 1, b"hello").await,
+            Err(ClientError::OutcomeUnknown)
+        ));
+        let first = xids.recv().await.unwrap();
+
+        // The client reconnects for the next call, which is not a replay.
+        assert_eq!(client.call(1, 1, 1, b"again").await.unwrap(), b"again");
+        assert_ne!(xids.recv().await.unwrap(), first);
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_gives_up() {
+        // Find a port with nothing listening on it.
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let client = ReconnectingClient::new(move || AsyncClient::builder().connect(addr))
+            .with_backoff(Backoff::new(Duration::from_millis(1)).with_retries(2));
+
+        assert!(matches!(
+            client.call_idempotent(1, 1, 1, &[]).await,
+            Err(ClientError::Io(e)) if e.kind() == std::io::ErrorKind::ConnectionRefused
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_retries_paced_by_backoff() {
+        // A server that drops every connection as soon as it is accepted.
+        let l = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = l.local_addr().unwrap();
+        let (tx, mut accepted) = unbounded_channel();
+        tokio::spawn(async move {
+            loop {
+                let (conn, _) = l.accept().await.unwrap();
+                drop(conn);
+                let _ = tx.send(());
+            }
+        });
+
+        let client = ReconnectingClient::new(move || AsyncClient::builder().connect(addr))
+            .with_backoff(
+                Backoff::new(Duration::from_millis(30))
+                    .with_factor(1)
+                    .with_retries(3),
+            );
+
+        let start = std::time::Instant::now();
+        assert!(matches!(
+            client.call_idempotent(1, 1, 1, &[]).await,
+            Err(ClientError::Disconnected)
+        ));
+
+        // Each of the 3 retries waited for the backoff interval.
+        assert!(start.elapsed() >= Duration::from_millis(90));
+
+        let mut n = 0;
+        while accepted.try_recv().is_ok() {
+            n += 1;
+        }
+        assert!(n <= 4, "{n} connections for 4 attempts");
+    }
+}
@@ -0,0 +1,258 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{ready, Context, Poll},
+};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio_util::sync::PollSemaphore;
+
+use crate::{
+    client::{AsyncClient, ClientError},
+    CallBody,
+};
+
+/// The in-flight permit reserved for the next call by
+/// `Service::poll_ready()`.
+///
+/// Each clone of a client reserves its own permit.
+#[derive(Debug)]
+pub(crate) struct Readiness {
+    semaphore: PollSemaphore,
+    permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Readiness {
+    pub(crate) fn new(semaphore: Arc<Semaphore>) -> Self {
+        Self {
+            semaphore: PollSemaphore::new(semaphore),
+            permit: None,
+        }
+    }
+}
+
+impl Clone for Readiness {
+    fn clone(&self) -> Self {
+        Self {
+            semaphore: self.semaphore.clone(),
+            permit: None,
+        }
+    }
+}
+
+/// Sends each [`CallBody`] request as a call, resolving to the serialised
+/// result of a successful call.
+///
+/// The call is sent with the credentials and verifier of the request, rather
+/// than the client credentials, and a newly allocated xid. The service is
+/// ready once a call can be sent without exceeding
+/// [`AsyncClientBuilder::with_max_in_flight()`], and fails with
+/// [`ClientError::Disconnected`] once the connection closes.
+///
+/// [`AsyncClientBuilder::with_max_in_flight()`]:
+///     crate::client::AsyncClientBuilder::with_max_in_flight
+///
+/// As with [`AsyncClient::call()`], the reply is validated by the client -
+/// unsuccessful replies resolve to [`ClientError::Reply`] - and only the
+/// serialised procedure result remains, for the caller to decode with the
+/// program's own types.
+///
+/// This allows tower middleware such as timeouts, retries and concurrency
+/// limits to wrap the client.
+///
+/// ```no_run
+/// use onc_rpc::{auth::AuthFlavor, client::AsyncClient, CallBody};
+/// use tower_service::Service;
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let mut client = AsyncClient::builder().connect("127.0.0.1:2049").await?;
+///
+/// let call = CallBody::new(
+///     100003,
+///     3,
+///     0,
+///     AuthFlavor::<Vec<u8>>::AuthNone(None),
+///     AuthFlavor::AuthNone(None),
+///     Vec::new(),
+/// );
+///
+/// // The inherent AsyncClient::call() method takes precedence over the trait
+/// // method.
+/// let result = Service::call(&mut client, call).await?;
+/// # Ok(())
+/// # }
+/// ```
+impl<T, P> tower_service::Service<CallBody<T, P>> for AsyncClient
+where
+    T: AsRef<[u8]> + Send + 'static,
+    P: AsRef<[u8]> + Send + 'static,
+{
+    type Response = Vec<u8>;
+    type Error = ClientError;
+    type Future = Pin<Box<dyn Future<Output = Result<Vec<u8>, ClientError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if self.is_closed() {
+            return Poll::Ready(Err(ClientError::Disconnected));
+        }
+        if self.ready.permit.is_none() {
+            // The semaphore is closed when the connection closes.
+            let permit = ready!(self.ready.semaphore.poll_acquire(cx));
+            self.ready.permit = Some(permit.ok_or(ClientError::Disconnected)?);
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: CallBody<T, P>) -> Self::Future {
+        let permit = self
+            .ready
+            .permit
+            .take()
+            .expect("poll_ready() must be called before call()");
+
+        let client = self.clone();
+        Box::pin(async move { client.send_call(None, req, &mut false, Some(permit)).await })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::net::TcpListener;
+    use tower::{timeout::TimeoutLayer, Service, ServiceBuilder, ServiceExt};
+
+    use super::*;
+    use crate::{
+        auth::{AuthFlavor, AuthUnixParams},
+        server::{AsyncServer, CallContext, Dispatcher, Procedure},
+        RpcError,
+    };
+
+    const PROGRAM: u32 = 100099;
+
+    type Call = CallBody<Vec<u8>, Vec<u8>>;
+
+    fn call(procedure: u32, creds: AuthFlavor<Vec<u8>>, args: &[u8]) -> Call {
+        CallBody::new(
+            PROGRAM,
+            1,
+            procedure,
+            creds,
+            AuthFlavor::AuthNone(None),
+            args.to_vec(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_service() {
+        let dispatcher = Dispatcher::new()
+            .with_procedure(
+                PROGRAM,
+                1,
+                1,
+                Procedure::sync(|ctx: CallContext, _args| {
+                    // Reply with the machine name of the AUTH_UNIX credentials.
+                    match ctx.credentials() {
+                        AuthFlavor::AuthUnix(v) => Ok(v.machine_name().to_vec()),
+                        _ => Ok(vec![]),
+                    }
+                }),
+            )
+            .with_procedure(
+                PROGRAM,
+                1,
+                2,
+                |_ctx: CallContext, args: Vec<u8>| async move {
+                    tokio::time::sleep(Duration::from_secs(10)).await;
+                    Ok(args)
+                },
+            );
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = AsyncServer::new(dispatcher);
+        tokio::spawn(async move { server.serve(listener).await });
+
+        let client = AsyncClient::builder().connect(addr).await.unwrap();
+        let service = ServiceBuilder::new()
+            .layer(TimeoutLayer::new(Duration::from_millis(50)))
+            .service(client.clone());
+
+        // The request credentials are sent.
+        let creds = AuthFlavor::AuthUnix(AuthUnixParams::new(0, b"host".to_vec(), 1000, 1000, []));
+        let got = service.clone().oneshot(call(1, creds, &[])).await.unwrap();
+        assert_eq!(got, b"host");
+
+        // Errors from the client and middleware are surfaced.
+        let err = service
+            .clone()
+            .oneshot(call(9, AuthFlavor::AuthNone(None), &[]))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ClientError>(),
+            Some(ClientError::Reply(RpcError::ProcedureUnavailable))
+        ));
+
+        let err = service
+            .oneshot(call(2, AuthFlavor::AuthNone(None), &[]))
+            .await
+            .unwrap_err();
+        assert!(err.is::<tower::timeout::error::Elapsed>());
+    }
+
+    #[tokio::test]
+    async fn test_poll_ready_in_flight_limit() {
+        let (unblock_tx, unblock_rx) = tokio::sync::oneshot::channel::<()>();
+        let unblock_rx = std::sync::Mutex::new(Some(unblock_rx));
+        let dispatcher = Dispatcher::new().with_procedure(
+            PROGRAM,
+            1,
+            1,
+            move |_ctx: CallContext, args: Vec<u8>| {
+                let rx = unblock_rx.lock().unwrap().take();
+                async move {
+                    if let Some(rx) = rx {
+                        rx.await.unwrap();
+                    }
+                    Ok(args)
+                }
+            },
+        );
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = AsyncServer::new(dispatcher);
+        tokio::spawn(async move { server.serve(listener).await });
+
+        let mut first = AsyncClient::builder()
+            .with_max_in_flight(1)
+            .connect(addr)
+            .await
+            .unwrap();
+        let mut second = first.clone();
+
+        // The first call holds the only in-flight permit, reserved by
+        // poll_ready().
+        ServiceExt::<Call>::ready(&mut first).await.unwrap();
+        let slow = Service::call(&mut first, call(1, AuthFlavor::AuthNone(None), b"slow"));
+
+        let ready = tokio::time::timeout(
+            Duration::from_millis(50),
+            ServiceExt::<Call>::ready(&mut second),
+        )
+        .await;
+        assert!(ready.is_err(), "service ready beyond the in-flight limit");
+
+        // Once the call completes, the permit is released.
+        unblock_tx.send(()).unwrap();
+        assert_eq!(slow.await.unwrap(), b"slow");
+
+        ServiceExt::<Call>::ready(&mut second).await.unwrap();
+        let got = Service::call(&mut second, call(1, AuthFlavor::AuthNone(None), b"fast"))
+            .await
+            .unwrap();
+        assert_eq!(got, b"fast");
+    }
+}
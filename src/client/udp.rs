@@ -0,0 +1,380 @@
+use std::{
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    auth::AuthFlavor,
+    client::{reply_result, Backoff, ClientError, VerifierCheck, XidGenerator},
+    AuthError, CallBody, MessageType, RpcMessage,
+};
+
+/// The default maximum length of a reply datagram.
+pub const DEFAULT_MAX_DATAGRAM_LEN: usize = 64 * 1024;
+
+/// A synchronous RPC client for UDP.
+///
+/// Each call is sent as a single datagram without record marking. If no reply
+/// is received within the current [`Backoff`] interval the call is
+/// retransmitted with the same transaction ID, allowing the server to detect
+/// the duplicate. Once the schedule is exhausted, [`ClientError::Timeout`] is
+/// returned.
+///
+/// Replies to other transaction IDs, such as late replies to a previous call
+/// or duplicate replies to retransmitted calls, are discarded - as are any
+/// datagrams that cannot be parsed.
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use onc_rpc::client::{Backoff, UdpClient};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let mut client = UdpClient::connect("127.0.0.1:111")?
+///     .with_backoff(Backoff::new(Duration::from_millis(500)).with_retries(3));
+///
+/// // Call the portmapper NULL procedure.
+/// client.call(100000, 2, 0, &[])?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct UdpClient {
+    socket: UdpSocket,
+    xids: XidGenerator,
+    credentials: AuthFlavor<Vec<u8>>,
+    backoff: Backoff,
+    max_reply_len: usize,
+    verifier_check: Option<VerifierCheck>,
+}
+
+impl UdpClient {
+    /// Bind an ephemeral local port and send calls to the server at `addr`.
+    pub fn connect(addr: impl ToSocketAddrs) -> Result<Self, std::io::Error> {
+        let mut last_err = None;
+        for addr in addr.to_socket_addrs()? {
+            let local: SocketAddr = match addr {
+                SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+                SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+            };
+            match UdpSocket::bind(local).and_then(|s| s.connect(addr).map(|_| s)) {
+                Ok(s) => return Ok(Self::new(s)),
+                Err(e) => last_err = Some(e),
+            }
+        }
+
+        Err(last_err.unwrap_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "could not resolve to any addresses",
+            )
+        }))
+    }
+
+    /// Construct a client sending calls over `socket`, which must be connected
+    /// to the server with [`UdpSocket::connect()`].
+    ///
+    /// Calls are sent with [`AuthFlavor::AuthNone`] credentials, retransmitted
+    /// using the default [`Backoff`] schedule, and accept replies of at most
+    /// [`DEFAULT_MAX_DATAGRAM_LEN`] bytes.
+    pub fn new(socket: UdpSocket) -> Self {
+        Self {
+            socket,
+            xids: XidGenerator::new(),
+            credentials: AuthFlavor::AuthNone(None),
+            backoff: Backoff::default(),
+            max_reply_len: DEFAULT_MAX_DATAGRAM_LEN,
+            verifier_check: None,
+        }
+    }
+
+    /// Send `credentials` with each call.
+    pub fn with_credentials(self, credentials: AuthFlavor<Vec<u8>>) -> Self {
+        Self {
+            credentials,
+            ..self
+        }
+    }
+
+    /// Retransmit calls according to `backoff`.
+    pub fn with_backoff(self, backoff: Backoff) -> Self {
+        Self { backoff, ..self }
+    }
+
+    /// Discard reply datagrams longer than `max_reply_len` bytes.
+    pub fn with_max_reply_len(self, max_reply_len: usize) -> Self {
+        Self {
+            max_reply_len,
+            ..self
+        }
+    }
+
+    /// Validate the auth verifier of each accepted reply with `check`.
+    ///
+    /// Calls whose reply verifier is rejected by `check` fail with
+    /// [`RpcError::VerifierRejected`](crate::RpcError::VerifierRejected),
+    /// regardless of the reply status.
+    pub fn with_verifier_check<F>(self, check: F) -> Self
+    where
+        F: Fn(&AuthFlavor<&[u8]>) -> Result<(), AuthError> + Send + Sync + 'static,
+    {
+        Self {
+            verifier_check: Some(VerifierCheck::new(check)),
+            ..self
+        }
+    }
+
+    /// The credentials sent with each call.
+    pub fn credentials(&self) -> &AuthFlavor<Vec<u8>> {
+        &self.credentials
+    }
+
+    /// Replace the credentials sent with subsequent calls.
+    pub fn set_credentials(&mut self, credentials: AuthFlavor<Vec<u8>>) {
+        self.credentials = credentials;
+    }
+
+    /// Call the NULL procedure (procedure 0) of `program` / `program_version`,
+    /// returning the round-trip time.
+    pub fn ping(&mut self, program: u32, program_version: u32) -> Result<Duration, ClientError> {
+        let start = Instant::now();
+        self.call(program, program_version, 0, &[])?;
+        Ok(start.elapsed())
+    }
+
+    /// Call `procedure` of `program` / `program_version` with the serialised
+    /// `args`, returning the serialised result of a successful call.
+    pub fn call(
+        &mut self,
+        program: u32,
+        program_version: u32,
+        procedure: u32,
+        args: &[u8],
+    ) -> Result<Vec<u8>, ClientError> {
+        let xid = self.xids.next();
+        let call = RpcMessage::new(
+            xid,
+            MessageType::Call(CallBody::new(
+                program,
+                program_version,
+                procedure,
+                self.credentials.as_borrowed(),
+                AuthFlavor::AuthNone(None),
+                args,
+            )),
+        )
+        .serialise_datagram()?;
+
+        // Allow one extra byte to detect (and discard) oversized datagrams,
+        // which are otherwise silently truncated.
+        let mut buf = vec![0; self.max_reply_len + 1];
+
+        for wait in self.backoff.intervals() {
+            self.socket.send(&call)?;
+
+            let deadline = Instant::now() + wait;
+            while let Some(n) = self.recv_until(&mut buf, deadline)? {
+                if n > self.max_reply_len {
+                    continue;
+                }
+
+                match RpcMessage::from_datagram(&buf[..n]) {
+                    Ok(msg) if msg.xid() == xid => {
+                        return reply_result(msg, self.verifier_check.as_ref())
+                    }
+                    _ => continue,
+                }
+            }
+        }
+
+        Err(ClientError::Timeout)
+    }
+
+    /// Receive a datagram into `buf`, returning `None` if no datagram arrives
+    /// before `deadline`.
+    fn recv_until(
+        &self,
+        buf: &mut [u8],
+        deadline: Instant,
+    ) -> Result<Option<usize>, std::io::Error> {
+        let remaining = match deadline.checked_duration_since(Instant::now()) {
+            Some(d) if !d.is_zero() => d,
+            _ => return Ok(None),
+        };
+
+        self.socket.set_read_timeout(Some(remaining))?;
+        match self.socket.recv(buf) {
+            Ok(n) => Ok(Some(n)),
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                ) =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns a reference to the underlying socket.
+    pub fn get_ref(&self) -> &UdpSocket {
+        &self.socket
+    }
+
+    /// Consume this client, returning the underlying socket.
+    pub fn into_inner(self) -> UdpSocket {
+        self.socket
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::mpsc::{channel, Receiver},
+        thread,
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::{AcceptedReply, AcceptedStatus, ReplyBody};
+
+    /// The retransmission interval used by tests, short enough to keep them
+    /// fast.
+    const TEST_INTERVAL: Duration = Duration::from_millis(20);
+
+    fn success(xid: u32, payload: &[u8]) -> Vec<u8> {
+        RpcMessage::new(
+            xid,
+            MessageType::Reply(ReplyBody::<&[u8], &[u8]>::Accepted(AcceptedReply::new(
+                AuthFlavor::AuthNone(None),
+                AcceptedStatus::Success(payload),
+            ))),
+        )
+        .serialise_datagram()
+        .unwrap()
+    }
+
+    /// Spawn a UDP responder echoing the call payload, passing the xid of every
+    /// received datagram to `reply` to decide which (if any) datagrams to send
+    /// back.
+    ///
+    /// The xid of every received call is sent to the returned channel.
+    fn responder<F>(mut reply: F) -> (SocketAddr, Receiver<u32>)
+    where
+        F: FnMut(u32, &[u8]) -> Vec<Vec<u8>> + Send + 'static,
+    {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        let (tx, rx) = channel();
+
+        thread::spawn(move || {
+            let mut buf = vec![0; 1024];
+            loop {
+                let (n, from) = socket.recv_from(&mut buf).unwrap();
+                let msg = RpcMessage::from_datagram(&buf[..n]).unwrap();
+                let payload = msg.call_body().unwrap().payload();
+
+                if tx.send(msg.xid()).is_err() {
+                    return;
+                }
+                for r in reply(msg.xid(), payload) {
+                    socket.send_to(&r, from).unwrap();
+                }
+            }
+        });
+
+        (addr, rx)
+    }
+
+    fn client(addr: SocketAddr) -> UdpClient {
+        UdpClient::connect(addr)
+            .unwrap()
+            .with_backoff(Backoff::new(TEST_INTERVAL).with_factor(1).with_retries(3))
+    }
+
+    #[test]
+    fn test_call() {
+        let (addr, rx) = responder(|xid, payload| vec![success(xid, payload)]);
+        let mut client = client(addr);
+
+        assert_eq!(client.call(100000, 2, 0, b"ping").unwrap(), b"ping");
+        assert_eq!(client.call(100000, 2, 0, b"pong").unwrap(), b"pong");
+
+        let a = rx.recv().unwrap();
+        assert_eq!(rx.recv().unwrap(), a.wrapping_add(1));
+    }
+
+    #[test]
+    fn test_ping() {
+        let (addr, _rx) = responder(|xid, _payload| vec![success(xid, &[])]);
+        let mut client = client(addr);
+
+        assert!(client.ping(100000, 2).unwrap() < TEST_INTERVAL * 4);
+    }
+
+    #[test]
+    fn test_retransmit_dropped() {
+        // Drop the first two calls.
+        let mut n = 0;
+        let (addr, rx) = responder(move |xid, payload| {
+            n += 1;
+            if n <= 2 {
+                return vec![];
+            }
+            vec![success(xid, payload)]
+        });
+        let mut client = client(addr);
+
+        assert_eq!(client.call(1, 1, 1, b"hello").unwrap(), b"hello");
+
+        // All three transmissions carry the same xid.
+        let xids = rx.try_iter().collect::<Vec<_>>();
+        assert_eq!(xids.len(), 3);
+        assert!(xids.iter().all(|v| *v == xids[0]));
+    }
+
+    #[test]
+    fn test_stale_and_duplicate_replies_ignored() {
+        let (addr, _rx) = responder(|xid, payload| {
+            vec![
+                success(xid.wrapping_sub(1), b"stale"),
+                b"garbage".to_vec(),
+                success(xid, payload),
+                success(xid, b"duplicate"),
+            ]
+        });
+        let mut client = client(addr);
+
+        assert_eq!(client.call(1, 1, 1, b"first").unwrap(), b"first");
+
+        // The duplicate reply to the first call is discarded.
+        assert_eq!(client.call(1, 1, 1, b"second").unwrap(), b"second");
+    }
+
+    #[test]
+    fn test_timeout() {
+        let (addr, rx) = responder(|_xid, _payload| vec![]);
+        let mut client = client(addr);
+
+        let start = Instant::now();
+        assert!(matches!(
+            client.call(1, 1, 1, &[]),
+            Err(ClientError::Timeout)
+        ));
+        assert!(start.elapsed() >= TEST_INTERVAL * 4);
+
+        // The call was sent once, and retried 3 times.
+        assert_eq!(rx.try_iter().count(), 4);
+    }
+
+    #[test]
+    fn test_oversized_reply_discarded() {
+        let (addr, _rx) = responder(|xid, _payload| vec![success(xid, &[42; 128])]);
+        let mut client = client(addr).with_max_reply_len(64);
+
+        assert!(matches!(
+            client.call(1, 1, 1, &[]),
+            Err(ClientError::Timeout)
+        ));
+    }
+}
@@ -0,0 +1,45 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::BuildHasher,
+    sync::atomic::{AtomicU32, Ordering},
+    time::SystemTime,
+};
+
+/// A source of transaction IDs (xids) for outgoing calls.
+///
+/// IDs are allocated sequentially from a random starting point, so that calls
+/// from a restarted client are unlikely to be mistaken for retransmissions of
+/// calls from the previous instance by a server duplicate request cache.
+#[derive(Debug)]
+pub(crate) struct XidGenerator(AtomicU32);
+
+impl XidGenerator {
+    pub(crate) fn new() -> Self {
+        let seed = RandomState::new().hash_one((SystemTime::now(), std::process::id()));
+        Self(AtomicU32::new(seed as u32))
+    }
+
+    /// Allocate the next xid.
+    pub(crate) fn next(&self) -> u32 {
+        self.0.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+impl Default for XidGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sequential() {
+        let x = XidGenerator::new();
+        let a = x.next();
+        assert_eq!(x.next(), a.wrapping_add(1));
+        assert_eq!(x.next(), a.wrapping_add(2));
+    }
+}
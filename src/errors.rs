@@ -32,6 +32,17 @@ pub enum Error {
     #[error("RPC message is fragmented")]
     Fragmented,
 
+    /// A record read from a stream transport exceeds the configured maximum
+    /// length.
+    #[error("record length {len} exceeds maximum of {max} bytes")]
+    RecordTooLarge {
+        /// The length of the record, as read from the fragment headers so far.
+        len: usize,
+
+        /// The maximum record length permitted.
+        max: usize,
+    },
+
     /// The message type in the RPC request is neither [`MessageType::Call`]
     /// or [`MessageType::Reply`].
     ///
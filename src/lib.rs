@@ -25,13 +25,24 @@ pub(crate) use opaque::*;
 mod rpc_message;
 pub use rpc_message::*;
 
+mod partial_header;
+pub use partial_header::*;
+
 mod call_body;
 pub use call_body::*;
 
 mod reply;
 pub use reply::*;
 
+mod record;
+pub use record::*;
+
 pub mod auth;
+pub mod client;
+pub mod server;
+
+#[cfg(feature = "tls")]
+pub mod tls;
 
 #[cfg(feature = "bytes")]
 mod bytes_ext;
@@ -43,3 +54,9 @@ pub use bytes::*;
 // Unused crate lint workaround for dev dependency.
 #[cfg(test)]
 use criterion as _;
+#[cfg(all(test, not(feature = "tls")))]
+use rcgen as _;
+#[cfg(all(test, not(feature = "tokio")))]
+use tokio as _;
+#[cfg(all(test, not(feature = "tower")))]
+use tower as _;
@@ -0,0 +1,211 @@
+use crate::rpc_message::{MESSAGE_TYPE_CALL, MSG_HEADER_LEN};
+
+/// The fixed-size fields at the start of an RPC message, decoded as far as
+/// possible without parsing the credentials, verifier or payload.
+///
+/// Parsing an [`RpcMessage`](crate::RpcMessage) fails as a whole if any part
+/// of the message is invalid, discarding the fields decoded before the error.
+/// A server can decode a `PartialHeader` from a message that failed to parse
+/// to recover the xid and call details needed to send the error reply required
+/// by RFC 5531, such as `RPC_MISMATCH` for an unsupported RPC version.
+///
+/// Decoding never fails - each field is `None` if the buffer ends before it.
+/// The call fields are only decoded for call messages.
+///
+/// ```
+/// use onc_rpc::PartialHeader;
+///
+/// // A call datagram using RPC version 3, truncated after the program number.
+/// let buf = [
+///     0, 0, 0, 42, // xid
+///     0, 0, 0, 0, // CALL
+///     0, 0, 0, 3, // rpcvers
+///     0, 1, 134, 163, // program
+/// ];
+///
+/// let header = PartialHeader::from_datagram(&buf);
+/// assert_eq!(header.xid(), Some(42));
+/// assert!(header.is_call());
+/// assert_eq!(header.rpc_version(), Some(3));
+/// assert_eq!(header.program(), Some(100003));
+/// assert_eq!(header.program_version(), None);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PartialHeader {
+    xid: Option<u32>,
+    message_type: Option<u32>,
+    rpc_version: Option<u32>,
+    program: Option<u32>,
+    program_version: Option<u32>,
+    procedure: Option<u32>,
+}
+
+impl PartialHeader {
+    /// Decode the header fields of the record-marked message in `buf`.
+    ///
+    /// The record marking header is skipped without validation.
+    pub fn from_record(buf: &[u8]) -> Self {
+        buf.get(MSG_HEADER_LEN..)
+            .map(Self::from_datagram)
+            .unwrap_or_default()
+    }
+
+    /// Decode the header fields of the message datagram in `buf`, which has
+    /// no record marking header.
+    pub fn from_datagram(buf: &[u8]) -> Self {
+        let mut words = buf
+            .chunks_exact(4)
+            .map(|v| u32::from_be_bytes(v.try_into().unwrap()));
+
+        let xid = words.next();
+        let message_type = words.next();
+        if message_type != Some(MESSAGE_TYPE_CALL) {
+            return Self {
+                xid,
+                message_type,
+                ..Default::default()
+            };
+        }
+
+        Self {
+            xid,
+            message_type,
+            rpc_version: words.next(),
+            program: words.next(),
+            program_version: words.next(),
+            procedure: words.next(),
+        }
+    }
+
+    /// The transaction ID of the message.
+    pub fn xid(&self) -> Option<u32> {
+        self.xid
+    }
+
+    /// The raw message type discriminant - 0 for a call, 1 for a reply, or
+    /// any other value for an invalid message.
+    pub fn message_type(&self) -> Option<u32> {
+        self.message_type
+    }
+
+    /// Returns true if the message type was decoded and is a call.
+    pub fn is_call(&self) -> bool {
+        self.message_type == Some(MESSAGE_TYPE_CALL)
+    }
+
+    /// The RPC protocol version of the call.
+    pub fn rpc_version(&self) -> Option<u32> {
+        self.rpc_version
+    }
+
+    /// The program number of the call.
+    pub fn program(&self) -> Option<u32> {
+        self.program
+    }
+
+    /// The program version of the call.
+    pub fn program_version(&self) -> Option<u32> {
+        self.program_version
+    }
+
+    /// The procedure number of the call.
+    pub fn procedure(&self) -> Option<u32> {
+        self.procedure
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        auth::AuthFlavor, AcceptedReply, AcceptedStatus, CallBody, Error, MessageType, ReplyBody,
+        RpcMessage,
+    };
+
+    fn call() -> RpcMessage<&'static [u8], &'static [u8]> {
+        RpcMessage::new(
+            42,
+            MessageType::Call(CallBody::new(
+                100003,
+                3,
+                7,
+                AuthFlavor::AuthNone(None),
+                AuthFlavor::AuthNone(None),
+                b"args",
+            )),
+        )
+    }
+
+    #[test]
+    fn test_call() {
+        let want = PartialHeader {
+            xid: Some(42),
+            message_type: Some(0),
+            rpc_version: Some(2),
+            program: Some(100003),
+            program_version: Some(3),
+            procedure: Some(7),
+        };
+
+        let record = call().serialise().unwrap();
+        assert_eq!(PartialHeader::from_record(&record), want);
+
+        let datagram = call().serialise_datagram().unwrap();
+        assert_eq!(PartialHeader::from_datagram(&datagram), want);
+        assert!(want.is_call());
+    }
+
+    #[test]
+    fn test_invalid_rpc_version() {
+        let mut record = call().serialise().unwrap();
+        record[12..16].copy_from_slice(&3_u32.to_be_bytes());
+
+        // The message cannot be parsed, but the header is intact.
+        assert_eq!(
+            RpcMessage::try_from(record.as_slice()),
+            Err(Error::InvalidRpcVersion(3))
+        );
+
+        let header = PartialHeader::from_record(&record);
+        assert_eq!(header.xid(), Some(42));
+        assert_eq!(header.rpc_version(), Some(3));
+        assert_eq!(header.procedure(), Some(7));
+    }
+
+    #[test]
+    fn test_truncated() {
+        let datagram = call().serialise_datagram().unwrap();
+
+        // Each field is decoded only once all of its bytes are available.
+        let header = PartialHeader::from_datagram(&datagram[..15]);
+        assert_eq!(header.xid(), Some(42));
+        assert_eq!(header.rpc_version(), Some(2));
+        assert_eq!(header.program(), None);
+        assert_eq!(header.procedure(), None);
+
+        assert_eq!(
+            PartialHeader::from_datagram(&datagram[..3]),
+            Default::default()
+        );
+        assert_eq!(PartialHeader::from_record(&[]), Default::default());
+    }
+
+    #[test]
+    fn test_reply() {
+        let msg = RpcMessage::<&[u8], &[u8]>::new(
+            42,
+            MessageType::Reply(ReplyBody::Accepted(AcceptedReply::new(
+                AuthFlavor::AuthNone(None),
+                AcceptedStatus::Success(b"result"),
+            ))),
+        );
+
+        // The call fields are not decoded from replies.
+        let header = PartialHeader::from_datagram(&msg.serialise_datagram().unwrap());
+        assert_eq!(header.xid(), Some(42));
+        assert_eq!(header.message_type(), Some(1));
+        assert!(!header.is_call());
+        assert_eq!(header.rpc_version(), None);
+        assert_eq!(header.program(), None);
+    }
+}
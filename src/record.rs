@@ -0,0 +1,182 @@
+//! Record marking for stream transports, as described in [RFC 5531 section
+//! 11].
+//!
+//! [RFC 5531 section 11]: https://tools.ietf.org/html/rfc5531#section-11
+
+use std::io::Read;
+
+use crate::{Error, LAST_FRAGMENT_BIT, MSG_HEADER_LEN};
+
+/// Read a single record from `r` into `buf`, reassembling it if it was sent as
+/// more than one fragment.
+///
+/// Once this call returns, `buf` contains exactly one record prefixed with a
+/// single "last fragment" header, suitable for passing to the
+/// [`RpcMessage`](crate::RpcMessage) `TryFrom` implementations. Any existing
+/// contents of `buf` are discarded, but the allocation is reused.
+///
+/// If the reassembled record would exceed `max_len` bytes (excluding the
+/// header) [`Error::RecordTooLarge`] is returned before the oversized fragment
+/// is read. The stream should be considered unusable after any error.
+///
+/// If the stream is closed before a full record is read,
+/// [`Error::IOError`] with a kind of
+/// [`UnexpectedEof`](std::io::ErrorKind::UnexpectedEof) is returned.
+pub fn read_record<R: Read>(mut r: R, buf: &mut Vec<u8>, max_len: usize) -> Result<(), Error> {
+    buf.clear();
+
+    // Reserve space for the header, written once the length is known.
+    buf.extend_from_slice(&[0; MSG_HEADER_LEN]);
+
+    loop {
+        let mut header = [0; MSG_HEADER_LEN];
+        r.read_exact(&mut header)?;
+        let header = u32::from_be_bytes(header);
+
+        let fragment_len = (header & !LAST_FRAGMENT_BIT) as usize;
+        let record_len = buf.len() - MSG_HEADER_LEN + fragment_len;
+        if record_len > max_len {
+            return Err(Error::RecordTooLarge {
+                len: record_len,
+                max: max_len,
+            });
+        }
+
+        let start = buf.len();
+        buf.resize(start + fragment_len, 0);
+        r.read_exact(&mut buf[start..])?;
+
+        if header & LAST_FRAGMENT_BIT != 0 {
+            break;
+        }
+    }
+
+    let header = (buf.len() - MSG_HEADER_LEN) as u32 | LAST_FRAGMENT_BIT;
+    buf[..MSG_HEADER_LEN].copy_from_slice(&header.to_be_bytes());
+
+    Ok(())
+}
+
+/// Read a single record from `r` into `buf`, reassembling it if it was sent as
+/// more than one fragment.
+///
+/// This is the async equivalent of [`read_record()`], with the same
+/// behaviour.
+///
+/// This function is not cancellation safe - if the returned future is dropped
+/// before completion, part of a record may have been consumed from `r`.
+#[cfg(feature = "tokio")]
+pub async fn read_record_async<R>(r: &mut R, buf: &mut Vec<u8>, max_len: usize) -> Result<(), Error>
+where
+    R: tokio::io::AsyncRead + Unpin + ?Sized,
+{
+    use tokio::io::AsyncReadExt;
+
+    buf.clear();
+    buf.extend_from_slice(&[0; MSG_HEADER_LEN]);
+
+    loop {
+        let header = r.read_u32().await?;
+
+        let fragment_len = (header & !LAST_FRAGMENT_BIT) as usize;
+        let record_len = buf.len() - MSG_HEADER_LEN + fragment_len;
+        if record_len > max_len {
+            return Err(Error::RecordTooLarge {
+                len: record_len,
+                max: max_len,
+            });
+        }
+
+        let start = buf.len();
+        buf.resize(start + fragment_len, 0);
+        r.read_exact(&mut buf[start..]).await?;
+
+        if header & LAST_FRAGMENT_BIT != 0 {
+            break;
+        }
+    }
+
+    let header = (buf.len() - MSG_HEADER_LEN) as u32 | LAST_FRAGMENT_BIT;
+    buf[..MSG_HEADER_LEN].copy_from_slice(&header.to_be_bytes());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use hex_literal::hex;
+
+    use super::*;
+
+    #[test]
+    fn test_read_single_fragment() {
+        let raw = hex!("80000004 01020304 80000000");
+        let mut r = Cursor::new(raw.as_ref());
+        let mut buf = Vec::new();
+
+        read_record(&mut r, &mut buf, 1024).expect("read record");
+        assert_eq!(buf, hex!("80000004 01020304"));
+
+        // An empty record is valid.
+        read_record(&mut r, &mut buf, 1024).expect("read record");
+        assert_eq!(buf, hex!("80000000"));
+    }
+
+    #[test]
+    fn test_read_reassembles_fragments() {
+        let raw = hex!("00000002 0102 00000001 03 80000003 040506");
+        let mut buf = Vec::new();
+
+        read_record(raw.as_ref(), &mut buf, 1024).expect("read record");
+        assert_eq!(buf, hex!("80000006 010203040506"));
+    }
+
+    #[test]
+    fn test_read_max_len() {
+        let raw = hex!("00000002 0102 80000002 0304");
+        let mut buf = Vec::new();
+
+        read_record(raw.as_ref(), &mut buf, 4).expect("read record");
+        assert_eq!(buf, hex!("80000004 01020304"));
+
+        let err = read_record(raw.as_ref(), &mut buf, 3).expect_err("should exceed max");
+        assert_eq!(err, Error::RecordTooLarge { len: 4, max: 3 });
+    }
+
+    #[test]
+    fn test_read_truncated() {
+        let raw = hex!("80000004 0102");
+        let mut buf = Vec::new();
+
+        let err = read_record(raw.as_ref(), &mut buf, 1024).expect_err("should be truncated");
+        assert!(matches!(
+            err,
+            Error::IOError(std::io::ErrorKind::UnexpectedEof, _)
+        ));
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "tokio")]
+    async fn test_read_async_reassembles_fragments() {
+        let raw = hex!("00000002 0102 80000001 03 80000000");
+        let mut r = raw.as_ref();
+        let mut buf = Vec::new();
+
+        read_record_async(&mut r, &mut buf, 1024)
+            .await
+            .expect("read record");
+        assert_eq!(buf, hex!("80000003 010203"));
+
+        read_record_async(&mut r, &mut buf, 1024)
+            .await
+            .expect("read record");
+        assert_eq!(buf, hex!("80000000"));
+
+        let err = read_record_async(&mut hex!("80000004 0102").as_ref(), &mut buf, 3)
+            .await
+            .expect_err("should exceed max");
+        assert_eq!(err, Error::RecordTooLarge { len: 4, max: 3 });
+    }
+}
@@ -74,6 +74,11 @@ where
     pub fn status(&self) -> &AcceptedStatus<P> {
         &self.status
     }
+
+    /// Decompose this reply into the auth verifier and status.
+    pub(crate) fn into_parts(self) -> (AuthFlavor<T>, AcceptedStatus<P>) {
+        (self.auth_verifier, self.status)
+    }
 }
 
 impl<'a> TryFrom<&'a [u8]> for AcceptedReply<&'a [u8], &'a [u8]> {
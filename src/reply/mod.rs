@@ -6,3 +6,6 @@ pub use accepted_reply::*;
 
 mod rejected_reply;
 pub use rejected_reply::*;
+
+mod rpc_error;
+pub use rpc_error::*;
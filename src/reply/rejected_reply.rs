@@ -95,6 +95,12 @@ impl RejectedReply {
     }
 }
 
+impl From<AuthError> for RejectedReply {
+    fn from(v: AuthError) -> Self {
+        Self::AuthError(v)
+    }
+}
+
 impl TryFrom<&[u8]> for RejectedReply {
     type Error = Error;
 
@@ -126,14 +132,18 @@ impl TryFrom<crate::Bytes> for RejectedReply {
 
 /// `AuthError` describes the reason the request authentication credentials were
 /// rejected.
-#[derive(Debug, PartialEq, Clone)]
+///
+/// The [`Display`](std::fmt::Display) output is the name used in the spec.
+#[derive(Debug, PartialEq, Clone, thiserror::Error)]
 pub enum AuthError {
     /// This is `AUTH_OK` in the spec.
+    #[error("AUTH_OK")]
     Success,
 
     /// The credentials were rejected.
     ///
     /// This is `AUTH_BADCRED` in the spec.
+    #[error("AUTH_BADCRED")]
     BadCredentials,
 
     /// The session has been invalidated.
@@ -144,31 +154,37 @@ pub enum AuthError {
     /// This is `AUTH_REJECTEDCRED` in the spec.
     ///
     /// [`AUTH_SHORT`]: crate::auth::AuthFlavor::AuthShort
+    #[error("AUTH_REJECTEDCRED")]
     RejectedCredentials,
 
     /// The verifier was not acceptable.
     ///
     /// This is `AUTH_BADVERF` in the spec.
+    #[error("AUTH_BADVERF")]
     BadVerifier,
 
     /// The verifier was rejected/expired.
     ///
     /// This is `AUTH_REJECTEDVERF` in the spec.
+    #[error("AUTH_REJECTEDVERF")]
     RejectedVerifier,
 
     /// The authentication scheme was rejected for security reasons.
     ///
     /// This is `AUTH_TOOWEAK` in the spec.
+    #[error("AUTH_TOOWEAK")]
     TooWeak,
 
     /// The response verifier is invalid.
     ///
     /// This is `AUTH_INVALIDRESP` in the spec.
+    #[error("AUTH_INVALIDRESP")]
     InvalidResponseVerifier,
 
     /// An unknown failure occured.
     ///
     /// This is `AUTH_FAILED` in the spec.
+    #[error("AUTH_FAILED")]
     Failed,
 }
 
@@ -5,8 +5,8 @@ use std::{
 
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 
-use super::{AcceptedReply, RejectedReply};
-use crate::Error;
+use super::{AcceptedReply, AcceptedStatus, RejectedReply};
+use crate::{auth::AuthFlavor, Error};
 
 const REPLY_ACCEPTED: u32 = 0;
 const REPLY_DENIED: u32 = 1;
@@ -40,6 +40,15 @@ where
     T: AsRef<[u8]>,
     P: AsRef<[u8]>,
 {
+    /// Construct an accepted reply with `status`, using `verifier` or
+    /// `AUTH_NONE` if `None`.
+    pub(crate) fn accepted(verifier: Option<AuthFlavor<T>>, status: AcceptedStatus<P>) -> Self {
+        Self::Accepted(AcceptedReply::new(
+            verifier.unwrap_or(AuthFlavor::AuthNone(None)),
+            status,
+        ))
+    }
+
     /// Serialises this `ReplyBody` into `buf`, advancing the cursor position by
     /// [`ReplyBody::serialised_len()`] bytes.
     pub fn serialise_into<W: Write>(&self, mut buf: W) -> Result<(), std::io::Error> {
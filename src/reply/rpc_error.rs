@@ -0,0 +1,228 @@
+use thiserror::Error;
+
+use crate::{auth::AuthFlavor, AcceptedStatus, AuthError, RejectedReply, ReplyBody};
+
+/// The reason an RPC call was not successful, as described by the reply from
+/// the server.
+///
+/// The [`Display`](std::fmt::Display) output is the name used in the spec.
+#[derive(Debug, Clone, PartialEq, Error)]
+#[non_exhaustive]
+pub enum RpcError {
+    /// The server does not support the requested program.
+    #[error("PROG_UNAVAIL")]
+    ProgramUnavailable,
+
+    /// The server does not support the requested program version.
+    #[error("PROG_MISMATCH (supported versions {low} to {high})")]
+    ProgramMismatch {
+        /// The lowest supported program version.
+        low: u32,
+
+        /// The highest supported program version.
+        high: u32,
+    },
+
+    /// The server does not support the requested procedure.
+    #[error("PROC_UNAVAIL")]
+    ProcedureUnavailable,
+
+    /// The server could not decode the call arguments.
+    #[error("GARBAGE_ARGS")]
+    GarbageArgs,
+
+    /// The server experienced an internal error.
+    #[error("SYSTEM_ERR")]
+    SystemError,
+
+    /// The server does not support RPC version 2.
+    #[error("RPC_MISMATCH (supported versions {low} to {high})")]
+    RpcVersionMismatch {
+        /// The lowest supported RPC version.
+        low: u32,
+
+        /// The highest supported RPC version.
+        high: u32,
+    },
+
+    /// The server rejected the call credentials or verifier.
+    #[error("AUTH_ERROR: {0}")]
+    AuthError(AuthError),
+
+    /// The verifier in the reply was rejected by the caller.
+    ///
+    /// See [`ReplyBody::into_result_with_verifier()`].
+    #[error("reply verifier rejected: {0}")]
+    VerifierRejected(AuthError),
+}
+
+impl From<RejectedReply> for RpcError {
+    fn from(v: RejectedReply) -> Self {
+        match v {
+            RejectedReply::RpcVersionMismatch { low, high } => {
+                Self::RpcVersionMismatch { low, high }
+            }
+            RejectedReply::AuthError(e) => Self::AuthError(e),
+        }
+    }
+}
+
+impl<P> AcceptedStatus<P>
+where
+    P: AsRef<[u8]>,
+{
+    /// Convert this status into the payload of a successful call, or the
+    /// [`RpcError`] describing why the call failed.
+    pub fn into_result(self) -> Result<P, RpcError> {
+        match self {
+            Self::Success(p) => Ok(p),
+            Self::ProgramUnavailable => Err(RpcError::ProgramUnavailable),
+            Self::ProgramMismatch { low, high } => Err(RpcError::ProgramMismatch { low, high }),
+            Self::ProcedureUnavailable => Err(RpcError::ProcedureUnavailable),
+            Self::GarbageArgs => Err(RpcError::GarbageArgs),
+            Self::SystemError => Err(RpcError::SystemError),
+        }
+    }
+}
+
+impl<T, P> ReplyBody<T, P>
+where
+    T: AsRef<[u8]>,
+    P: AsRef<[u8]>,
+{
+    /// Convert this reply into the payload of a successful call, or the
+    /// [`RpcError`] describing why the call failed.
+    ///
+    /// The auth verifier sent by the server is not checked - use
+    /// [`ReplyBody::into_result_with_verifier()`] to validate it.
+    pub fn into_result(self) -> Result<P, RpcError> {
+        self.into_result_with_verifier(|_| Ok(()))
+    }
+
+    /// Convert this reply into the payload of a successful call, or the
+    /// [`RpcError`] describing why the call failed, passing the auth verifier
+    /// of an accepted reply to `check`.
+    ///
+    /// If `check` returns an error, [`RpcError::VerifierRejected`] is returned
+    /// regardless of the reply status.
+    ///
+    /// ```
+    /// use onc_rpc::{auth::AuthFlavor, AcceptedReply, AcceptedStatus, AuthError, ReplyBody, RpcError};
+    ///
+    /// let reply = ReplyBody::<&[u8], &[u8]>::Accepted(AcceptedReply::new(
+    ///     AuthFlavor::AuthNone(None),
+    ///     AcceptedStatus::Success(b"payload"),
+    /// ));
+    ///
+    /// // Require the server to send an AUTH_SHORT verifier.
+    /// let got = reply.into_result_with_verifier(|v| match v {
+    ///     AuthFlavor::AuthShort(_) => Ok(()),
+    ///     _ => Err(AuthError::InvalidResponseVerifier),
+    /// });
+    ///
+    /// assert_eq!(
+    ///     got,
+    ///     Err(RpcError::VerifierRejected(AuthError::InvalidResponseVerifier))
+    /// );
+    /// ```
+    pub fn into_result_with_verifier<F>(self, check: F) -> Result<P, RpcError>
+    where
+        F: FnOnce(&AuthFlavor<T>) -> Result<(), AuthError>,
+    {
+        match self {
+            Self::Accepted(r) => {
+                let (verifier, status) = r.into_parts();
+                check(&verifier).map_err(RpcError::VerifierRejected)?;
+                status.into_result()
+            }
+            Self::Denied(r) => Err(r.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AcceptedReply;
+
+    fn accepted(status: AcceptedStatus<&[u8]>) -> ReplyBody<&[u8], &[u8]> {
+        ReplyBody::Accepted(AcceptedReply::new(AuthFlavor::AuthNone(None), status))
+    }
+
+    #[test]
+    fn test_into_result() {
+        assert_eq!(
+            accepted(AcceptedStatus::Success(b"ok")).into_result(),
+            Ok(b"ok".as_slice())
+        );
+
+        let cases = [
+            (
+                accepted(AcceptedStatus::ProgramUnavailable),
+                RpcError::ProgramUnavailable,
+            ),
+            (
+                accepted(AcceptedStatus::ProgramMismatch { low: 2, high: 4 }),
+                RpcError::ProgramMismatch { low: 2, high: 4 },
+            ),
+            (
+                accepted(AcceptedStatus::ProcedureUnavailable),
+                RpcError::ProcedureUnavailable,
+            ),
+            (accepted(AcceptedStatus::GarbageArgs), RpcError::GarbageArgs),
+            (accepted(AcceptedStatus::SystemError), RpcError::SystemError),
+            (
+                ReplyBody::Denied(RejectedReply::RpcVersionMismatch { low: 3, high: 3 }),
+                RpcError::RpcVersionMismatch { low: 3, high: 3 },
+            ),
+            (
+                ReplyBody::Denied(RejectedReply::AuthError(AuthError::RejectedCredentials)),
+                RpcError::AuthError(AuthError::RejectedCredentials),
+            ),
+        ];
+
+        for (reply, want) in cases {
+            assert_eq!(reply.into_result(), Err(want));
+        }
+    }
+
+    #[test]
+    fn test_verifier_check() {
+        let reply = ReplyBody::<&[u8], &[u8]>::Accepted(AcceptedReply::new(
+            AuthFlavor::AuthShort(b"handle"),
+            AcceptedStatus::Success(b"ok"),
+        ));
+        let got = reply.into_result_with_verifier(|v| {
+            assert_eq!(*v, AuthFlavor::AuthShort(b"handle".as_slice()));
+            Ok(())
+        });
+        assert_eq!(got, Ok(b"ok".as_slice()));
+
+        // A rejected verifier takes precedence over the status.
+        let got = accepted(AcceptedStatus::SystemError)
+            .into_result_with_verifier(|_| Err(AuthError::BadVerifier));
+        assert_eq!(got, Err(RpcError::VerifierRejected(AuthError::BadVerifier)));
+
+        // Denied replies carry no verifier to check.
+        let got = ReplyBody::<&[u8], &[u8]>::Denied(RejectedReply::AuthError(AuthError::TooWeak))
+            .into_result_with_verifier(|_| unreachable!());
+        assert_eq!(got, Err(RpcError::AuthError(AuthError::TooWeak)));
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(RpcError::ProgramUnavailable.to_string(), "PROG_UNAVAIL");
+        assert_eq!(
+            RpcError::ProgramMismatch { low: 2, high: 4 }.to_string(),
+            "PROG_MISMATCH (supported versions 2 to 4)"
+        );
+        assert_eq!(
+            RpcError::AuthError(AuthError::TooWeak).to_string(),
+            "AUTH_ERROR: AUTH_TOOWEAK"
+        );
+        assert_eq!(
+            AuthError::InvalidResponseVerifier.to_string(),
+            "AUTH_INVALIDRESP"
+        );
+    }
+}
@@ -8,12 +8,17 @@ use std::{
 
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 
-use crate::{reply::ReplyBody, CallBody, Error};
+use crate::{
+    auth::AuthFlavor,
+    call_body::RPC_VERSION,
+    reply::{AcceptedStatus, AuthError, RejectedReply, ReplyBody},
+    CallBody, Error,
+};
 
-const MSG_HEADER_LEN: usize = 4;
-const LAST_FRAGMENT_BIT: u32 = 1 << 31;
+pub(crate) const MSG_HEADER_LEN: usize = 4;
+pub(crate) const LAST_FRAGMENT_BIT: u32 = 1 << 31;
 
-const MESSAGE_TYPE_CALL: u32 = 0;
+pub(crate) const MESSAGE_TYPE_CALL: u32 = 0;
 const MESSAGE_TYPE_REPLY: u32 = 1;
 
 // TODO: serialise_ioslice() -> IoSliceBuffer
@@ -196,6 +201,30 @@ where
         Ok(buf.into_inner())
     }
 
+    /// Write this `RpcMessage` into `buf` without the record marking header,
+    /// for sending as a single datagram over a connectionless transport such
+    /// as UDP.
+    ///
+    /// The datagram is [`RpcMessage::serialised_datagram_len()`] bytes long.
+    pub fn serialise_datagram_into<W: Write>(&self, mut buf: W) -> Result<(), std::io::Error> {
+        buf.write_u32::<BigEndian>(self.xid)?;
+        self.message_type.serialise_into(buf)
+    }
+
+    /// Serialise this `RpcMessage` into a new [`Vec`] without the record
+    /// marking header, for sending as a single datagram.
+    pub fn serialise_datagram(&self) -> Result<Vec<u8>, std::io::Error> {
+        let mut buf = Vec::with_capacity(self.serialised_datagram_len() as usize);
+        self.serialise_datagram_into(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Returns the length of this message once serialised as a datagram,
+    /// excluding the record marking header.
+    pub fn serialised_datagram_len(&self) -> u32 {
+        self.serialised_len() - MSG_HEADER_LEN as u32
+    }
+
     /// Returns the on-wire length of this message once serialised, including
     /// the message header.
     pub fn serialised_len(&self) -> u32 {
@@ -230,6 +259,180 @@ where
             _ => None,
         }
     }
+
+    /// Consumes this message, returning the [`ReplyBody`], or `None` if this
+    /// message is not a RPC response.
+    pub fn into_reply_body(self) -> Option<ReplyBody<T, P>> {
+        match self.message_type {
+            MessageType::Reply(b) => Some(b),
+            _ => None,
+        }
+    }
+}
+
+/// Helpers building the reply to a received call, copying the xid of the call
+/// into the reply.
+///
+/// The accepted replies include `verifier`, such as the one returned by an
+/// [`Authenticator`](crate::auth::Authenticator), or an `AUTH_NONE` verifier
+/// if `None`. See the equivalent [`CallBody`] methods to build a
+/// [`ReplyBody`] alone.
+///
+/// ```
+/// use onc_rpc::{auth::AuthFlavor, CallBody, MessageType, RpcMessage};
+///
+/// # let buf = RpcMessage::new(
+/// #     4242,
+/// #     MessageType::Call(CallBody::new(
+/// #         100003,
+/// #         3,
+/// #         1,
+/// #         AuthFlavor::<&[u8]>::AuthNone(None),
+/// #         AuthFlavor::AuthNone(None),
+/// #         &[][..],
+/// #     )),
+/// # )
+/// # .serialise()
+/// # .unwrap();
+/// let call = RpcMessage::try_from(buf.as_slice())?;
+///
+/// let reply: RpcMessage<Vec<u8>, Vec<u8>> = call.success(None, vec![42]);
+/// assert_eq!(reply.xid(), call.xid());
+/// # Ok::<(), onc_rpc::Error>(())
+/// ```
+impl<T, P> RpcMessage<T, P>
+where
+    T: AsRef<[u8]>,
+    P: AsRef<[u8]>,
+{
+    /// Reply with the serialised procedure `result` (`SUCCESS`).
+    pub fn success<V, R>(&self, verifier: Option<AuthFlavor<V>>, result: R) -> RpcMessage<V, R>
+    where
+        V: AsRef<[u8]>,
+        R: AsRef<[u8]>,
+    {
+        self.reply(ReplyBody::accepted(
+            verifier,
+            AcceptedStatus::Success(result),
+        ))
+    }
+
+    /// Reply that the program is not available (`PROG_UNAVAIL`).
+    pub fn prog_unavail<V, R>(&self, verifier: Option<AuthFlavor<V>>) -> RpcMessage<V, R>
+    where
+        V: AsRef<[u8]>,
+        R: AsRef<[u8]>,
+    {
+        self.reply(ReplyBody::accepted(
+            verifier,
+            AcceptedStatus::ProgramUnavailable,
+        ))
+    }
+
+    /// Reply that the program version is not supported, and that versions
+    /// `low` to `high` are (`PROG_MISMATCH`).
+    pub fn prog_mismatch<V, R>(
+        &self,
+        verifier: Option<AuthFlavor<V>>,
+        low: u32,
+        high: u32,
+    ) -> RpcMessage<V, R>
+    where
+        V: AsRef<[u8]>,
+        R: AsRef<[u8]>,
+    {
+        self.reply(ReplyBody::accepted(
+            verifier,
+            AcceptedStatus::ProgramMismatch { low, high },
+        ))
+    }
+
+    /// Reply that the procedure is not available (`PROC_UNAVAIL`).
+    pub fn proc_unavail<V, R>(&self, verifier: Option<AuthFlavor<V>>) -> RpcMessage<V, R>
+    where
+        V: AsRef<[u8]>,
+        R: AsRef<[u8]>,
+    {
+        self.reply(ReplyBody::accepted(
+            verifier,
+            AcceptedStatus::ProcedureUnavailable,
+        ))
+    }
+
+    /// Reply that the call arguments could not be decoded (`GARBAGE_ARGS`).
+    pub fn garbage_args<V, R>(&self, verifier: Option<AuthFlavor<V>>) -> RpcMessage<V, R>
+    where
+        V: AsRef<[u8]>,
+        R: AsRef<[u8]>,
+    {
+        self.reply(ReplyBody::accepted(verifier, AcceptedStatus::GarbageArgs))
+    }
+
+    /// Reply that the server experienced an internal error (`SYSTEM_ERR`).
+    pub fn system_err<V, R>(&self, verifier: Option<AuthFlavor<V>>) -> RpcMessage<V, R>
+    where
+        V: AsRef<[u8]>,
+        R: AsRef<[u8]>,
+    {
+        self.reply(ReplyBody::accepted(verifier, AcceptedStatus::SystemError))
+    }
+
+    /// Reject the call as using an unsupported RPC version (`RPC_MISMATCH`),
+    /// reporting version 2 as the only supported version.
+    pub fn rpc_mismatch<V, R>(&self) -> RpcMessage<V, R>
+    where
+        V: AsRef<[u8]>,
+        R: AsRef<[u8]>,
+    {
+        self.reply(ReplyBody::Denied(RejectedReply::RpcVersionMismatch {
+            low: RPC_VERSION,
+            high: RPC_VERSION,
+        }))
+    }
+
+    /// Reject the call as failing authentication (`AUTH_ERROR`).
+    pub fn auth_error<V, R>(&self, err: AuthError) -> RpcMessage<V, R>
+    where
+        V: AsRef<[u8]>,
+        R: AsRef<[u8]>,
+    {
+        self.reply(ReplyBody::Denied(RejectedReply::AuthError(err)))
+    }
+
+    fn reply<V, R>(&self, body: ReplyBody<V, R>) -> RpcMessage<V, R>
+    where
+        V: AsRef<[u8]>,
+        R: AsRef<[u8]>,
+    {
+        RpcMessage::new(self.xid, MessageType::Reply(body))
+    }
+}
+
+impl<'a> RpcMessage<&'a [u8], &'a [u8]> {
+    /// Deserialises a new [`RpcMessage`] from a datagram received over a
+    /// connectionless transport such as UDP, which has no record marking
+    /// header.
+    ///
+    /// `buf` must contain exactly 1 message - if `buf` contains an incomplete
+    /// message, or `buf` contains trailing bytes after the message
+    /// [`Error::IncompleteMessage`] is returned.
+    pub fn from_datagram(buf: &'a [u8]) -> Result<Self, Error> {
+        let mut r = Cursor::new(buf);
+
+        let xid = r.read_u32::<BigEndian>()?;
+        let message_type = MessageType::from_cursor(&mut r)?;
+
+        let msg = RpcMessage { xid, message_type };
+
+        if msg.serialised_datagram_len() as usize != buf.len() {
+            return Err(Error::IncompleteMessage {
+                buffer_len: buf.len(),
+                expected: msg.serialised_datagram_len() as usize,
+            });
+        }
+
+        Ok(msg)
+    }
 }
 
 impl<'a> TryFrom<&'a [u8]> for RpcMessage<&'a [u8], &'a [u8]> {
@@ -573,7 +776,7 @@ mod tests {
 			9322e3136382e312e3138382e3233382e32333500000000000002"
         );
 
-        assert_eq!(body.payload().as_ref(), payload.as_ref());
+        assert_eq!(*body.payload(), payload.as_ref());
 
         let serialised = msg.serialise().expect("failed to serialise");
         assert_eq!(serialised.as_slice(), RAW.as_ref());
@@ -719,7 +922,7 @@ mod tests {
     }
 
     #[test]
-    fn test_rpcmessage_auth_unix_empty<'a>() {
+    fn test_rpcmessage_auth_unix_empty() {
         // Remote Procedure Call, Type:Call XID:0x265ec106
         //     Fragment header: Last fragment, 152 bytes
         //         1... .... .... .... .... .... .... .... = Last Fragment: Yes
@@ -828,7 +1031,7 @@ mod tests {
     }
 
     #[test]
-    fn test_rpcmessage_reply<'a>() {
+    fn test_rpcmessage_reply() {
         // Remote Procedure Call, Type:Reply XID:0x265ec0fd
         //     Fragment header: Last fragment, 72 bytes
         //         1... .... .... .... .... .... .... .... = Last Fragment: Yes
@@ -880,7 +1083,7 @@ mod tests {
 
     #[test]
     #[cfg(feature = "bytes")]
-    fn test_rpcmessage_reply_bytes<'a>() {
+    fn test_rpcmessage_reply_bytes() {
         // Remote Procedure Call, Type:Reply XID:0x265ec0fd
         //     Fragment header: Last fragment, 72 bytes
         //         1... .... .... .... .... .... .... .... = Last Fragment: Yes
@@ -933,7 +1136,7 @@ mod tests {
     }
 
     #[test]
-    fn test_fuzz_message_too_long_for_type<'a>() {
+    fn test_fuzz_message_too_long_for_type() {
         const RAW: [u8; 39] = hex!(
             "800000232323232300000001000000000000000000000000000000010302
             232323232300232300"
@@ -954,7 +1157,7 @@ mod tests {
 
     #[test]
     #[cfg(feature = "bytes")]
-    fn test_fuzz_message_too_long_for_type_bytes<'a>() {
+    fn test_fuzz_message_too_long_for_type_bytes() {
         const RAW: [u8; 39] = hex!(
             "800000232323232300000001000000000000000000000000000000010302
             232323232300232300"
@@ -973,6 +1176,136 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_reply_helpers() {
+        let call = RpcMessage::<&[u8], &[u8]>::new(
+            4242,
+            MessageType::Call(CallBody::new(
+                100003,
+                3,
+                1,
+                AuthFlavor::AuthNone(None),
+                AuthFlavor::AuthNone(None),
+                &[][..],
+            )),
+        );
+
+        let verifier = || Some(AuthFlavor::AuthNone(Some(&[1, 2, 3, 4][..])));
+        let accepted = |status| {
+            Some(ReplyBody::Accepted(AcceptedReply::new(
+                AuthFlavor::AuthNone(Some(&[1, 2, 3, 4][..])),
+                status,
+            )))
+        };
+
+        type Reply<'a> = RpcMessage<&'a [u8], &'a [u8]>;
+
+        let reply: Reply<'_> = call.success(verifier(), &b"result"[..]);
+        assert_eq!(reply.xid(), 4242);
+        assert_eq!(
+            reply.into_reply_body(),
+            accepted(AcceptedStatus::Success(&b"result"[..]))
+        );
+
+        let reply: Reply<'_> = call.prog_unavail(verifier());
+        assert_eq!(
+            reply.into_reply_body(),
+            accepted(AcceptedStatus::ProgramUnavailable)
+        );
+
+        let reply: Reply<'_> = call.prog_mismatch(verifier(), 1, 2);
+        assert_eq!(
+            reply.into_reply_body(),
+            accepted(AcceptedStatus::ProgramMismatch { low: 1, high: 2 })
+        );
+
+        let reply: Reply<'_> = call.proc_unavail(verifier());
+        assert_eq!(
+            reply.into_reply_body(),
+            accepted(AcceptedStatus::ProcedureUnavailable)
+        );
+
+        let reply: Reply<'_> = call.garbage_args(verifier());
+        assert_eq!(
+            reply.into_reply_body(),
+            accepted(AcceptedStatus::GarbageArgs)
+        );
+
+        let reply: Reply<'_> = call.system_err(verifier());
+        assert_eq!(
+            reply.into_reply_body(),
+            accepted(AcceptedStatus::SystemError)
+        );
+
+        let reply: Reply<'_> = call.rpc_mismatch();
+        assert_eq!(
+            reply.into_reply_body(),
+            Some(ReplyBody::Denied(RejectedReply::RpcVersionMismatch {
+                low: 2,
+                high: 2
+            }))
+        );
+
+        let reply: Reply<'_> = call.auth_error(AuthError::TooWeak);
+        assert_eq!(
+            reply.into_reply_body(),
+            Some(ReplyBody::Denied(RejectedReply::AuthError(
+                AuthError::TooWeak
+            )))
+        );
+
+        // Accepted replies default to an AUTH_NONE verifier.
+        let reply: Reply<'_> = call.success(None, &b"result"[..]);
+        assert_eq!(
+            reply.into_reply_body(),
+            Some(ReplyBody::Accepted(AcceptedReply::new(
+                AuthFlavor::AuthNone(None),
+                AcceptedStatus::Success(&b"result"[..]),
+            )))
+        );
+    }
+
+    #[test]
+    fn test_datagram_round_trip() {
+        let msg = RpcMessage::<&[u8], &[u8]>::new(
+            4242,
+            MessageType::Call(CallBody::new(
+                100000,
+                2,
+                3,
+                AuthFlavor::AuthNone(None),
+                AuthFlavor::AuthNone(None),
+                &[1, 2, 3, 4],
+            )),
+        );
+
+        let stream = msg.serialise().unwrap();
+        let datagram = msg.serialise_datagram().unwrap();
+        assert_eq!(datagram, stream[MSG_HEADER_LEN..]);
+        assert_eq!(datagram.len(), msg.serialised_datagram_len() as usize);
+
+        let got = RpcMessage::from_datagram(&datagram).expect("parse datagram");
+        assert_eq!(got, msg);
+
+        // Truncated datagrams are rejected.
+        assert!(RpcMessage::from_datagram(&datagram[..datagram.len() - 8]).is_err());
+
+        // As are trailing bytes after a fixed length message.
+        let mut long = RpcMessage::<&[u8], &[u8]>::new(
+            4242,
+            MessageType::Reply(ReplyBody::Denied(RejectedReply::AuthError(
+                AuthError::TooWeak,
+            ))),
+        )
+        .serialise_datagram()
+        .unwrap();
+        long.push(0);
+        assert!(matches!(
+            RpcMessage::from_datagram(&long),
+            Err(Error::IncompleteMessage { .. })
+        ));
+    }
+
     #[test]
     fn test_ioslice_payload() {
         use std::io::IoSlice;
@@ -1023,15 +1356,15 @@ mod tests {
     fn arbitrary_auth_flavor() -> impl Strategy<Value = AuthFlavor<Vec<u8>>> {
         prop_oneof![
             // AuthNone
-            of(arbitrary_bytes(0..=200))
-                .prop_map(|opt_data| AuthFlavor::AuthNone(opt_data.map(|data| data))),
+            of(arbitrary_bytes(0..=200)).prop_map(AuthFlavor::AuthNone),
             // AuthUnix
             arbitrary_unix_auth_params().prop_map(AuthFlavor::AuthUnix),
             // AuthShort
-            arbitrary_bytes(0..=200).prop_map(|data| AuthFlavor::AuthShort(data)),
+            arbitrary_bytes(0..=200).prop_map(AuthFlavor::AuthShort),
+            Just(AuthFlavor::AuthTls),
             // Unknown
             (any::<u32>(), arbitrary_bytes(0..=200))
-                .prop_map(|(id, data)| AuthFlavor::Unknown { id, data: data })
+                .prop_map(|(id, data)| AuthFlavor::Unknown { id, data })
         ]
     }
 
@@ -0,0 +1,603 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    io::{BufReader, ErrorKind, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream},
+    panic::{self, AssertUnwindSafe},
+    pin::pin,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc, Arc, Mutex, MutexGuard, PoisonError,
+    },
+    task::{Context, Poll, Wake, Waker},
+    thread::{self, Thread},
+    time::Duration,
+};
+
+use crate::{
+    auth::Peer,
+    read_record,
+    server::{Dispatcher, Reply},
+    AcceptedStatus, Error, MessageType, PartialHeader, ReplyBody, RpcMessage,
+};
+
+/// The default maximum length of a call record.
+pub const DEFAULT_MAX_RECORD_LEN: usize = 4 * 1024 * 1024;
+
+/// The default number of worker threads serving connections in a
+/// [`BlockingServer`].
+pub const DEFAULT_WORKER_THREADS: usize = 16;
+
+/// Configuration for a [`BlockingServer`].
+#[derive(Debug, Clone)]
+pub struct BlockingServerBuilder {
+    worker_threads: usize,
+    max_record_len: usize,
+    idle_timeout: Option<Duration>,
+}
+
+impl BlockingServerBuilder {
+    /// Serve connections with a pool of `worker_threads` threads, each serving
+    /// one connection at a time.
+    ///
+    /// Once every worker is busy, new connections are left in the listen
+    /// backlog until a connection closes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `worker_threads` is 0.
+    pub fn with_worker_threads(self, worker_threads: usize) -> Self {
+        assert!(worker_threads > 0, "worker_threads must be non-zero");
+        Self {
+            worker_threads,
+            ..self
+        }
+    }
+
+    /// Reject call records longer than `max_record_len` bytes.
+    ///
+    /// Receiving an oversized record closes the connection, as the stream
+    /// cannot be resynchronised.
+    pub fn with_max_record_len(self, max_record_len: usize) -> Self {
+        Self {
+            max_record_len,
+            ..self
+        }
+    }
+
+    /// Close connections once no data has been received for `idle_timeout`,
+    /// freeing the worker serving them.
+    ///
+    /// The timeout applies to each read from the connection rather than to
+    /// each call, so a peer sending a call slowly - even a byte at a time -
+    /// keeps its worker for as long as each read completes within
+    /// `idle_timeout`.
+    ///
+    /// By default, idle connections are never closed.
+    pub fn with_idle_timeout(self, idle_timeout: Duration) -> Self {
+        Self {
+            idle_timeout: Some(idle_timeout),
+            ..self
+        }
+    }
+
+    /// Construct a server handling calls with `dispatcher`.
+    pub fn build(self, dispatcher: impl Into<Arc<Dispatcher>>) -> BlockingServer {
+        BlockingServer {
+            dispatcher: dispatcher.into(),
+            config: self,
+            shared: Arc::default(),
+        }
+    }
+}
+
+impl Default for BlockingServerBuilder {
+    fn default() -> Self {
+        Self {
+            worker_threads: DEFAULT_WORKER_THREADS,
+            max_record_len: DEFAULT_MAX_RECORD_LEN,
+            idle_timeout: None,
+        }
+    }
+}
+
+/// A synchronous RPC server for TCP, built only on the standard library.
+///
+/// Connections are served by a fixed-size pool of worker threads (see
+/// [`BlockingServerBuilder::with_worker_threads()`]), each reading call
+/// records from one connection, executing them in order and writing the
+/// replies back, reusing the connection's read and write buffers for every
+/// call.
+///
+/// The [`Procedure`](crate::server::Procedure) handlers of the [`Dispatcher`]
+/// are executed on the worker thread, polling the returned future to
+/// completion without an async runtime - handlers must not rely on runtime
+/// facilities such as tokio timers or sockets. A handler that panics is
+/// answered with `SYSTEM_ERR`, leaving the worker to continue serving the
+/// connection.
+///
+/// [`BlockingServer::shutdown()`] stops the server, allowing calls already
+/// executing to reply. `BlockingServer` is cheap to clone, with all clones
+/// sharing the same dispatcher and shutdown state.
+///
+/// ```no_run
+/// use std::net::TcpListener;
+///
+/// use onc_rpc::server::{BlockingServer, Dispatcher, Procedure};
+/// # fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let dispatcher = Dispatcher::new().with_procedure(
+///     100099,
+///     1,
+///     1,
+///     Procedure::sync(|_ctx, args| Ok(args)),
+/// );
+///
+/// let server = BlockingServer::builder()
+///     .with_worker_threads(4)
+///     .build(dispatcher);
+///
+/// let handle = {
+///     let server = server.clone();
+///     let listener = TcpListener::bind("127.0.0.1:4242")?;
+///     std::thread::spawn(move || server.serve(listener))
+/// };
+///
+/// // Later, stop the server and wait for it to finish.
+/// server.shutdown();
+/// handle.join().unwrap()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct BlockingServer {
+    dispatcher: Arc<Dispatcher>,
+    config: BlockingServerBuilder,
+    shared: Arc<Shared>,
+}
+
+impl BlockingServer {
+    /// Configure a new server.
+    pub fn builder() -> BlockingServerBuilder {
+        BlockingServerBuilder::default()
+    }
+
+    /// Construct a server with the default configuration, handling calls with
+    /// `dispatcher`.
+    pub fn new(dispatcher: impl Into<Arc<Dispatcher>>) -> Self {
+        BlockingServerBuilder::default().build(dispatcher)
+    }
+
+    /// The dispatcher handling calls received by this server.
+    pub fn dispatcher(&self) -> &Arc<Dispatcher> {
+        &self.dispatcher
+    }
+
+    /// Accept and serve TCP connections from `listener`, blocking the calling
+    /// thread until the server is shut down.
+    ///
+    /// Returns once [`BlockingServer::shutdown()`] has been called and every
+    /// connection accepted by `listener` has closed. Errors accepting a
+    /// connection do not stop the server.
+    pub fn serve(&self, listener: TcpListener) -> Result<(), std::io::Error> {
+        let addr = listener.local_addr()?;
+        let _listener = self.shared.register_listener(addr);
+        if self.shared.is_stopped() {
+            return Ok(());
+        }
+
+        // Connections are handed to a free worker, blocking the accept loop
+        // until one is available.
+        let (tx, rx) = mpsc::sync_channel::<TcpStream>(0);
+        let rx = Mutex::new(rx);
+
+        thread::scope(|s| {
+            for _ in 0..self.config.worker_threads {
+                s.spawn(|| self.worker(&rx));
+            }
+
+            for res in listener.incoming() {
+                if self.shared.is_stopped() {
+                    break;
+                }
+
+                match res {
+                    Ok(stream) => {
+                        if tx.send(stream).is_err() {
+                            break;
+                        }
+                    }
+                    // Back off to avoid spinning on persistent errors, such
+                    // as running out of file descriptors.
+                    Err(_) => thread::sleep(Duration::from_millis(100)),
+                }
+            }
+
+            // Stop the workers once they finish their connections.
+            drop(tx);
+        });
+
+        Ok(())
+    }
+
+    /// Serve calls received over the already-connected `stream` on the calling
+    /// thread, returning once the connection is closed.
+    ///
+    /// Returns `Ok` if the peer closed the connection, or it was closed for
+    /// being idle or by [`BlockingServer::shutdown()`], and an error if the
+    /// connection failed or the peer sent an invalid record.
+    pub fn serve_connection(&self, stream: TcpStream) -> Result<(), Error> {
+        let peer = Peer::Inet(stream.peer_addr()?);
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(self.config.idle_timeout)?;
+
+        let Some(_conn) = self.shared.register_connection(&stream)? else {
+            return Ok(());
+        };
+
+        let mut reader = BufReader::new(&stream);
+        let mut writer = &stream;
+        let mut buf = Vec::new();
+        let mut out = Vec::new();
+
+        loop {
+            match read_record(&mut reader, &mut buf, self.config.max_record_len) {
+                Ok(()) => {}
+                Err(Error::IOError(
+                    ErrorKind::UnexpectedEof | ErrorKind::WouldBlock | ErrorKind::TimedOut,
+                    _,
+                )) => return Ok(()),
+                Err(e) => return Err(e),
+            }
+
+            // Catch panicking handlers to keep the worker pool at its fixed
+            // size.
+            let dispatched = panic::catch_unwind(AssertUnwindSafe(|| {
+                block_on(self.dispatcher.dispatch_record(&peer, &buf, &mut out))
+            }));
+
+            match dispatched {
+                Ok(res) => {
+                    if res? {
+                        writer.write_all(&out)?;
+                    }
+                }
+                Err(_) => {
+                    // The record parsed as a call for the handler to run, so
+                    // the xid is always present.
+                    let xid = PartialHeader::from_record(&buf).xid().unwrap();
+                    let err: Reply = RpcMessage::new(
+                        xid,
+                        MessageType::Reply(ReplyBody::accepted(None, AcceptedStatus::SystemError)),
+                    );
+                    out.clear();
+                    err.serialise_into(&mut out)?;
+                    writer.write_all(&out)?;
+                }
+            }
+        }
+    }
+
+    /// Stop the server.
+    ///
+    /// Every [`BlockingServer::serve()`] call stops accepting connections, and
+    /// every connection is closed once the call it is executing (if any) has
+    /// been replied to. Connections accepted but not yet served are closed
+    /// without reading from them.
+    ///
+    /// This does not wait for the server to stop, and a server cannot be
+    /// restarted once shut down.
+    pub fn shutdown(&self) {
+        self.shared.stopped.store(true, Ordering::SeqCst);
+
+        // Wake the accept loops with a connection of their own.
+        for addr in lock(&self.shared.listeners).values() {
+            let _ = TcpStream::connect_timeout(&wake_addr(*addr), Duration::from_secs(1));
+        }
+
+        // Unblock workers waiting for the next call, leaving replies to the
+        // calls they are executing to be written.
+        for stream in lock(&self.shared.connections).values() {
+            let _ = stream.shutdown(Shutdown::Read);
+        }
+    }
+
+    /// Serve the connections received from `rx` until it is closed.
+    fn worker(&self, rx: &Mutex<mpsc::Receiver<TcpStream>>) {
+        loop {
+            let Ok(stream) = lock(rx).recv() else {
+                return;
+            };
+
+            // Errors close the connection, leaving the client to reconnect.
+            let _ = self.serve_connection(stream);
+        }
+    }
+}
+
+/// Shutdown state shared between the clones of a [`BlockingServer`].
+#[derive(Debug, Default)]
+struct Shared {
+    stopped: AtomicBool,
+    next_id: AtomicU64,
+
+    /// The addresses of the listeners being served, to wake on shutdown.
+    listeners: Mutex<HashMap<u64, SocketAddr>>,
+
+    /// Handles to the connections being served, to close on shutdown.
+    connections: Mutex<HashMap<u64, TcpStream>>,
+}
+
+impl Shared {
+    fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+    }
+
+    fn register_listener(&self, addr: SocketAddr) -> Registration<'_, SocketAddr> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        lock(&self.listeners).insert(id, addr);
+        Registration {
+            map: &self.listeners,
+            id,
+        }
+    }
+
+    /// Register `stream` to be closed on shutdown, returning `None` if the
+    /// server is already stopped.
+    fn register_connection(
+        &self,
+        stream: &TcpStream,
+    ) -> Result<Option<Registration<'_, TcpStream>>, std::io::Error> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        lock(&self.connections).insert(id, stream.try_clone()?);
+        let conn = Registration {
+            map: &self.connections,
+            id,
+        };
+
+        // Checked after registering, so a concurrent shutdown either observes
+        // this connection or is observed here.
+        Ok((!self.is_stopped()).then_some(conn))
+    }
+}
+
+/// Removes an entry from a map in [`Shared`] when dropped.
+struct Registration<'a, T> {
+    map: &'a Mutex<HashMap<u64, T>>,
+    id: u64,
+}
+
+impl<T> Drop for Registration<'_, T> {
+    fn drop(&mut self) {
+        lock(self.map).remove(&self.id);
+    }
+}
+
+fn lock<T>(v: &Mutex<T>) -> MutexGuard<'_, T> {
+    // The guarded state is always left consistent.
+    v.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// The address to connect to in order to reach a listener bound to `addr`.
+fn wake_addr(addr: SocketAddr) -> SocketAddr {
+    match addr.ip() {
+        IpAddr::V4(v) if v.is_unspecified() => (Ipv4Addr::LOCALHOST, addr.port()).into(),
+        IpAddr::V6(v) if v.is_unspecified() => (Ipv6Addr::LOCALHOST, addr.port()).into(),
+        _ => addr,
+    }
+}
+
+/// Unparks the thread polling a future when it is woken.
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Poll `fut` to completion on the calling thread, parking it while the
+/// future is pending.
+fn block_on<F: Future>(fut: F) -> F::Output {
+    let mut fut = pin!(fut);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+
+    loop {
+        if let Poll::Ready(v) = fut.as_mut().poll(&mut cx) {
+            return v;
+        }
+        thread::park();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::{
+        client::{Client, ClientError},
+        server::{CallContext, Procedure},
+        RpcError,
+    };
+
+    const PROGRAM: u32 = 100099;
+
+    /// Serve `server` on a new loopback listener in a new thread, returning
+    /// its address and the thread handle.
+    fn serve(
+        server: &BlockingServer,
+    ) -> (SocketAddr, thread::JoinHandle<Result<(), std::io::Error>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = server.clone();
+        (addr, thread::spawn(move || server.serve(listener)))
+    }
+
+    fn echo() -> Dispatcher {
+        Dispatcher::new().with_procedure(PROGRAM, 1, 1, Procedure::sync(|_ctx, args| Ok(args)))
+    }
+
+    #[test]
+    fn test_serve() {
+        let dispatcher = echo().with_procedure(
+            PROGRAM,
+            1,
+            2,
+            |_ctx: CallContext, args: Vec<u8>| async move {
+                // Complete the handler future from another thread.
+                let (tx, rx) = tokio::sync::oneshot::channel();
+                thread::spawn(move || {
+                    thread::sleep(Duration::from_millis(10));
+                    tx.send(args).unwrap();
+                });
+                Ok(rx.await.unwrap())
+            },
+        );
+
+        let server = BlockingServer::new(dispatcher);
+        let (addr, handle) = serve(&server);
+        let mut client = Client::<TcpStream>::connect(addr).unwrap();
+
+        assert_eq!(client.call(PROGRAM, 1, 1, b"hello").unwrap(), b"hello");
+        assert_eq!(client.call(PROGRAM, 1, 2, b"later").unwrap(), b"later");
+        client.ping(PROGRAM, 1).unwrap();
+
+        let err = client.call(PROGRAM, 2, 1, &[]).unwrap_err();
+        assert!(matches!(
+            err,
+            ClientError::Reply(RpcError::ProgramMismatch { low: 1, high: 1 })
+        ));
+
+        // Large, fragmented records reuse the connection buffers.
+        let args = vec![42; 100_000];
+        assert_eq!(client.call(PROGRAM, 1, 1, &args).unwrap(), args);
+
+        server.shutdown();
+        handle.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_worker_pool() {
+        let server = BlockingServer::builder()
+            .with_worker_threads(1)
+            .build(echo());
+        let (addr, handle) = serve(&server);
+
+        let mut first = Client::<TcpStream>::connect(addr).unwrap();
+        first.call(PROGRAM, 1, 1, &[]).unwrap();
+
+        // The second connection waits for the only worker.
+        let second = thread::spawn(move || {
+            let start = Instant::now();
+            let mut client = Client::<TcpStream>::connect(addr).unwrap();
+            client.call(PROGRAM, 1, 1, &[]).unwrap();
+            start.elapsed()
+        });
+
+        thread::sleep(Duration::from_millis(100));
+        drop(first);
+        assert!(second.join().unwrap() >= Duration::from_millis(100));
+
+        server.shutdown();
+        handle.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_handler_panic() {
+        let dispatcher = echo().with_procedure(
+            PROGRAM,
+            1,
+            2,
+            Procedure::sync(|_ctx, _args| -> Result<Vec<u8>, _> { panic!("handler panic") }),
+        );
+        let server = BlockingServer::builder()
+            .with_worker_threads(1)
+            .build(dispatcher);
+        let (addr, handle) = serve(&server);
+
+        // The panic is answered with SYSTEM_ERR, and the connection and the
+        // only worker remain usable.
+        let mut client = Client::<TcpStream>::connect(addr).unwrap();
+        for _ in 0..3 {
+            assert!(matches!(
+                client.call(PROGRAM, 1, 2, &[]),
+                Err(ClientError::Reply(RpcError::SystemError))
+            ));
+            assert_eq!(client.call(PROGRAM, 1, 1, b"ok").unwrap(), b"ok");
+        }
+        drop(client);
+
+        let mut client = Client::<TcpStream>::connect(addr).unwrap();
+        assert_eq!(client.call(PROGRAM, 1, 1, b"ok").unwrap(), b"ok");
+
+        server.shutdown();
+        handle.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_idle_timeout() {
+        let server = BlockingServer::builder()
+            .with_worker_threads(1)
+            .with_idle_timeout(Duration::from_millis(50))
+            .build(echo());
+        let (addr, handle) = serve(&server);
+
+        let mut idle = Client::<TcpStream>::connect(addr).unwrap();
+        idle.call(PROGRAM, 1, 1, &[]).unwrap();
+
+        // The idle connection is closed, freeing the worker.
+        thread::sleep(Duration::from_millis(100));
+        let mut client = Client::<TcpStream>::connect(addr).unwrap();
+        client.call(PROGRAM, 1, 1, &[]).unwrap();
+        assert!(idle.call(PROGRAM, 1, 1, &[]).is_err());
+
+        server.shutdown();
+        handle.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_shutdown() {
+        let (started_tx, started_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let release_rx = Mutex::new(release_rx);
+        let dispatcher = echo().with_procedure(
+            PROGRAM,
+            1,
+            2,
+            Procedure::sync(move |_ctx, args| {
+                started_tx.send(()).unwrap();
+                release_rx.lock().unwrap().recv().unwrap();
+                Ok(args)
+            }),
+        );
+
+        let server = BlockingServer::new(dispatcher);
+        let (addr, handle) = serve(&server);
+
+        let mut idle = Client::<TcpStream>::connect(addr).unwrap();
+        idle.ping(PROGRAM, 1).unwrap();
+
+        let busy = thread::spawn(move || {
+            let mut client = Client::<TcpStream>::connect(addr).unwrap();
+            client.call(PROGRAM, 1, 2, b"in flight")
+        });
+        started_rx.recv().unwrap();
+
+        // The executing call is replied to once it completes.
+        server.shutdown();
+        release_tx.send(()).unwrap();
+        assert_eq!(busy.join().unwrap().unwrap(), b"in flight");
+
+        handle.join().unwrap().unwrap();
+        assert!(idle.ping(PROGRAM, 1).is_err());
+
+        // A stopped server cannot be restarted.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        server.serve(listener).unwrap();
+    }
+}
@@ -0,0 +1,880 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::Debug,
+    sync::Arc,
+};
+
+use crate::{
+    auth::{AuthFlavor, AuthRequest, Authenticator, Peer},
+    call_body::RPC_VERSION,
+    rpc_message::LAST_FRAGMENT_BIT,
+    server::{
+        CacheLookup, CallContext, DuplicateRequestCache, Procedure, ProcedureError, RequestKey,
+    },
+    AcceptedStatus, CallBody, Error, MessageType, PartialHeader, RejectedReply, ReplyBody,
+    RpcMessage,
+};
+
+/// The procedure number of the NULL procedure.
+const NULL_PROCEDURE: u32 = 0;
+
+/// A reply message produced by a [`Dispatcher`].
+pub type Reply = RpcMessage<Vec<u8>, Vec<u8>>;
+
+/// Routes calls to the [`Procedure`] registered for the call program, version
+/// and procedure number, producing the reply to send to the caller.
+///
+/// Calls that cannot be routed are answered as the spec requires:
+///
+/// * `PROG_UNAVAIL` if no procedures are registered for the program.
+/// * `PROG_MISMATCH` if no procedures are registered for the program version,
+///   with the lowest and highest registered versions.
+/// * `PROC_UNAVAIL` if the procedure is not registered.
+/// * `RPC_MISMATCH` if the call is not RPC version 2.
+///
+/// The NULL procedure (procedure 0) of every registered program version is
+/// answered with an empty successful reply, unless a procedure is registered
+/// for it.
+///
+/// If configured with an [`Authenticator`], every call is authenticated before
+/// being routed, and rejected calls are answered with `AUTH_ERROR`. The
+/// verifier returned by the authenticator is sent in the reply - without an
+/// authenticator, all calls are accepted and `AUTH_NONE` is sent.
+///
+/// ```
+/// use onc_rpc::server::{Dispatcher, Procedure, ProcedureError};
+///
+/// let dispatcher = Dispatcher::new()
+///     .with_procedure(100099, 1, 1, |_ctx, args: Vec<u8>| async move {
+///         // Echo the arguments.
+///         Ok::<_, ProcedureError>(args)
+///     })
+///     .with_procedure(
+///         100099,
+///         2,
+///         1,
+///         Procedure::sync(|_ctx, args: Vec<u8>| Ok(args.into_iter().rev().collect())),
+///     );
+/// ```
+#[derive(Default)]
+pub struct Dispatcher {
+    procedures: HashMap<(u32, u32, u32), Procedure>,
+
+    /// The registered versions of each program.
+    versions: HashMap<u32, BTreeSet<u32>>,
+    authenticator: Option<Arc<dyn Authenticator>>,
+    reply_cache: Option<Arc<DuplicateRequestCache>>,
+}
+
+impl Dispatcher {
+    /// Construct a dispatcher with no registered procedures, accepting all
+    /// calls without authentication.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Authenticate each call with `authenticator`.
+    pub fn with_authenticator<A>(self, authenticator: A) -> Self
+    where
+        A: Authenticator + 'static,
+    {
+        Self {
+            authenticator: Some(Arc::new(authenticator)),
+            ..self
+        }
+    }
+
+    /// Answer retransmitted calls to procedures registered with
+    /// [`Procedure::with_reply_cache()`] from `cache`.
+    ///
+    /// The cache is used by [`Dispatcher::dispatch_record()`] and
+    /// [`Dispatcher::dispatch_datagram()`], and may be shared between
+    /// dispatchers.
+    pub fn with_duplicate_request_cache(
+        self,
+        cache: impl Into<Arc<DuplicateRequestCache>>,
+    ) -> Self {
+        Self {
+            reply_cache: Some(cache.into()),
+            ..self
+        }
+    }
+
+    /// Register `handler` as `procedure` of `program` / `program_version`,
+    /// replacing any existing registration.
+    pub fn with_procedure(
+        mut self,
+        program: u32,
+        program_version: u32,
+        procedure: u32,
+        handler: impl Into<Procedure>,
+    ) -> Self {
+        self.procedures
+            .insert((program, program_version, procedure), handler.into());
+        self.versions
+            .entry(program)
+            .or_default()
+            .insert(program_version);
+        self
+    }
+
+    /// The lowest and highest registered version of `program`, or `None` if
+    /// the program is not registered.
+    pub fn versions(&self, program: u32) -> Option<(u32, u32)> {
+        let v = self.versions.get(&program)?;
+        Some((*v.first()?, *v.last()?))
+    }
+
+    /// Handle the call `msg` received from `peer`, returning the reply to send.
+    ///
+    /// Returns `None` if `msg` is not a call, or if the reply is suppressed
+    /// (see [`Procedure::with_suppressed_reply()`]).
+    ///
+    /// This never consults the [`DuplicateRequestCache`] - a retransmitted call
+    /// is executed again, even for procedures registered with
+    /// [`Procedure::with_reply_cache()`]. Use [`Dispatcher::dispatch_record()`]
+    /// or [`Dispatcher::dispatch_datagram()`] to answer retransmissions from
+    /// the cache.
+    pub async fn dispatch<T, P>(&self, peer: &Peer, msg: &RpcMessage<T, P>) -> Option<Reply>
+    where
+        T: AsRef<[u8]> + Sync,
+        P: AsRef<[u8]> + Sync,
+    {
+        let call = msg.call_body()?;
+
+        if call.rpc_version() != RPC_VERSION {
+            return Some(msg.rpc_mismatch());
+        }
+
+        // Authenticate the caller before routing the call.
+        let (principal, verifier) = match &self.authenticator {
+            Some(a) => match a.authenticate(&AuthRequest::new(peer, call)) {
+                Ok(v) => {
+                    let (principal, verifier) = v.into_parts();
+                    (Some(principal), Some(verifier))
+                }
+                Err(e) => return Some(msg.auth_error(e)),
+            },
+            None => (None, None),
+        };
+
+        let key = (call.program(), call.program_version(), call.procedure());
+        let Some(procedure) = self.procedures.get(&key) else {
+            return Some(self.unrouted_reply(msg, call, verifier));
+        };
+
+        let ctx = CallContext::new(
+            msg.xid(),
+            call.program(),
+            call.program_version(),
+            call.procedure(),
+            peer.clone(),
+            call.auth_credentials().as_borrowed().into_owned(),
+            principal,
+        );
+
+        let reply = match procedure.call(ctx, call.payload().as_ref().to_vec()).await {
+            Ok(_) if procedure.suppresses_reply() => return None,
+            Ok(v) => msg.success(verifier, v),
+            Err(ProcedureError::GarbageArgs) => msg.garbage_args(verifier),
+            Err(ProcedureError::SystemError) => msg.system_err(verifier),
+            Err(ProcedureError::Auth(e)) => msg.auth_error(e),
+        };
+
+        Some(reply)
+    }
+
+    /// Parse and handle the record-marked call in `buf` received from `peer`,
+    /// writing the serialised reply record to send into `out`.
+    ///
+    /// Returns `false`, leaving `out` empty, if there is no reply to send - if
+    /// `buf` is not a call, cannot be parsed well enough to reply, or is a
+    /// retransmission of a call still being executed.
+    ///
+    /// Retransmitted calls to procedures registered with
+    /// [`Procedure::with_reply_cache()`] are answered from the
+    /// [`DuplicateRequestCache`], if configured.
+    pub async fn dispatch_record(
+        &self,
+        peer: &Peer,
+        buf: &[u8],
+        out: &mut Vec<u8>,
+    ) -> Result<bool, std::io::Error> {
+        out.clear();
+
+        let reply = match RpcMessage::try_from(buf) {
+            Ok(msg) => match self.dispatch_cached(peer, &msg).await {
+                Dispatched::Reply(v) => *v,
+                Dispatched::Cached(v) => {
+                    // Frame the cached datagram as a single fragment record.
+                    let header = u32::try_from(v.len()).unwrap() | LAST_FRAGMENT_BIT;
+                    out.extend_from_slice(&header.to_be_bytes());
+                    out.extend_from_slice(&v);
+                    return Ok(true);
+                }
+                Dispatched::None => return Ok(false),
+            },
+            Err(e) => match parse_error_reply(&e, PartialHeader::from_record(buf)) {
+                Some(v) => v,
+                None => return Ok(false),
+            },
+        };
+
+        reply.serialise_into(&mut *out)?;
+        Ok(true)
+    }
+
+    /// Parse and handle the call datagram in `buf` received from `peer`,
+    /// writing the serialised reply datagram to send into `out`.
+    ///
+    /// Returns `false`, leaving `out` empty, if there is no reply to send (see
+    /// [`Dispatcher::dispatch_record()`]).
+    pub async fn dispatch_datagram(
+        &self,
+        peer: &Peer,
+        buf: &[u8],
+        out: &mut Vec<u8>,
+    ) -> Result<bool, std::io::Error> {
+        out.clear();
+
+        let reply = match RpcMessage::from_datagram(buf) {
+            Ok(msg) => match self.dispatch_cached(peer, &msg).await {
+                Dispatched::Reply(v) => *v,
+                Dispatched::Cached(v) => {
+                    out.extend_from_slice(&v);
+                    return Ok(true);
+                }
+                Dispatched::None => return Ok(false),
+            },
+            Err(e) => match parse_error_reply(&e, PartialHeader::from_datagram(buf)) {
+                Some(v) => v,
+                None => return Ok(false),
+            },
+        };
+
+        reply.serialise_datagram_into(&mut *out)?;
+        Ok(true)
+    }
+
+    /// Handle the call `msg` received from `peer` as
+    /// [`Dispatcher::dispatch()`] does, answering retransmissions from the
+    /// duplicate request cache if enabled for the procedure.
+    ///
+    /// Returns `None` if there is no reply to send, including for a
+    /// retransmission of a call still being executed.
+    #[cfg(feature = "tower")]
+    pub(crate) async fn dispatch_message<T, P>(
+        &self,
+        peer: &Peer,
+        msg: &RpcMessage<T, P>,
+    ) -> Option<Reply>
+    where
+        T: AsRef<[u8]> + Sync,
+        P: AsRef<[u8]> + Sync,
+    {
+        match self.dispatch_cached(peer, msg).await {
+            Dispatched::Reply(v) => Some(*v),
+            Dispatched::Cached(v) => Some(cached_reply(&v)),
+            Dispatched::None => None,
+        }
+    }
+
+    /// Handle the call `msg` received from `peer`, answering retransmissions
+    /// from the duplicate request cache if enabled for the procedure.
+    async fn dispatch_cached<T, P>(&self, peer: &Peer, msg: &RpcMessage<T, P>) -> Dispatched
+    where
+        T: AsRef<[u8]> + Sync,
+        P: AsRef<[u8]> + Sync,
+    {
+        let cache = match (&self.reply_cache, msg.call_body()) {
+            (Some(cache), Some(call)) if self.caches_reply(call) => {
+                Some((cache, RequestKey::new(peer, msg.xid(), call)))
+            }
+            _ => None,
+        };
+
+        let Some((cache, key)) = cache else {
+            return self.dispatch(peer, msg).await.into();
+        };
+
+        match cache.begin(&key) {
+            CacheLookup::New => {}
+            CacheLookup::InProgress | CacheLookup::Completed(None) => return Dispatched::None,
+            CacheLookup::Completed(Some(v)) => return Dispatched::Cached(v),
+        }
+
+        // Forget the call if this future is dropped before it completes.
+        let guard = PendingCall {
+            cache,
+            key,
+            completed: false,
+        };
+
+        let reply = self.dispatch(peer, msg).await;
+        let datagram = match &reply {
+            Some(v) => match v.serialise_datagram() {
+                Ok(v) => Some(Arc::from(v)),
+                // Not cached, so the retransmission fails the same way.
+                Err(_) => return reply.into(),
+            },
+            None => None,
+        };
+
+        guard.complete(datagram);
+        reply.into()
+    }
+
+    /// Returns true if replies to `call` should be recorded in the duplicate
+    /// request cache.
+    fn caches_reply<T, P>(&self, call: &CallBody<T, P>) -> bool
+    where
+        T: AsRef<[u8]>,
+        P: AsRef<[u8]>,
+    {
+        let key = (call.program(), call.program_version(), call.procedure());
+        self.procedures
+            .get(&key)
+            .is_some_and(Procedure::caches_reply)
+    }
+
+    /// The reply to the call `msg` with no registered procedure.
+    fn unrouted_reply<T, P>(
+        &self,
+        msg: &RpcMessage<T, P>,
+        call: &CallBody<T, P>,
+        verifier: Option<AuthFlavor<Vec<u8>>>,
+    ) -> Reply
+    where
+        T: AsRef<[u8]>,
+        P: AsRef<[u8]>,
+    {
+        let Some(versions) = self.versions.get(&call.program()) else {
+            return msg.prog_unavail(verifier);
+        };
+
+        if !versions.contains(&call.program_version()) {
+            let low = *versions.first().unwrap();
+            let high = *versions.last().unwrap();
+            return msg.prog_mismatch(verifier, low, high);
+        }
+
+        if call.procedure() == NULL_PROCEDURE {
+            return msg.success(verifier, vec![]);
+        }
+
+        msg.proc_unavail(verifier)
+    }
+}
+
+impl Debug for Dispatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Dispatcher")
+            .field("procedures", &self.procedures)
+            .field("authenticator", &self.authenticator.is_some())
+            .field("reply_cache", &self.reply_cache)
+            .finish()
+    }
+}
+
+/// The outcome of dispatching a call.
+enum Dispatched {
+    Reply(Box<Reply>),
+
+    /// The serialised reply datagram replayed from the duplicate request cache.
+    Cached(Arc<[u8]>),
+    None,
+}
+
+impl From<Option<Reply>> for Dispatched {
+    fn from(v: Option<Reply>) -> Self {
+        v.map_or(Self::None, |v| Self::Reply(Box::new(v)))
+    }
+}
+
+/// Parse the serialised reply `datagram` replayed from the duplicate request
+/// cache.
+#[cfg(feature = "tower")]
+fn cached_reply(datagram: &[u8]) -> Reply {
+    // Only replies serialised by the dispatcher are cached.
+    let msg = RpcMessage::from_datagram(datagram).expect("cached reply is valid");
+    let xid = msg.xid();
+
+    let body = match msg.into_reply_body().expect("cached message is a reply") {
+        ReplyBody::Accepted(r) => {
+            let (verifier, status) = r.into_parts();
+            let status = match status {
+                AcceptedStatus::Success(v) => AcceptedStatus::Success(v.to_vec()),
+                AcceptedStatus::ProgramUnavailable => AcceptedStatus::ProgramUnavailable,
+                AcceptedStatus::ProgramMismatch { low, high } => {
+                    AcceptedStatus::ProgramMismatch { low, high }
+                }
+                AcceptedStatus::ProcedureUnavailable => AcceptedStatus::ProcedureUnavailable,
+                AcceptedStatus::GarbageArgs => AcceptedStatus::GarbageArgs,
+                AcceptedStatus::SystemError => AcceptedStatus::SystemError,
+            };
+            ReplyBody::Accepted(crate::AcceptedReply::new(verifier.into_owned(), status))
+        }
+        ReplyBody::Denied(r) => ReplyBody::Denied(r),
+    };
+
+    RpcMessage::new(xid, MessageType::Reply(body))
+}
+
+/// A call recorded as in progress in a [`DuplicateRequestCache`], abandoned if
+/// dropped before it is completed.
+struct PendingCall<'a> {
+    cache: &'a DuplicateRequestCache,
+    key: RequestKey,
+    completed: bool,
+}
+
+impl PendingCall<'_> {
+    fn complete(mut self, reply: Option<Arc<[u8]>>) {
+        self.cache.complete(&self.key, reply);
+        self.completed = true;
+    }
+}
+
+impl Drop for PendingCall<'_> {
+    fn drop(&mut self) {
+        if !self.completed {
+            self.cache.abandon(&self.key);
+        }
+    }
+}
+
+/// Build the reply to a call that failed to parse with `err`, if any.
+///
+/// Calls with an unsupported RPC version are rejected with `RPC_MISMATCH`, and
+/// calls with an intact header but malformed credentials, verifier or
+/// arguments are replied to with `GARBAGE_ARGS`.
+fn parse_error_reply(err: &Error, header: PartialHeader) -> Option<Reply> {
+    let xid = header.xid()?;
+    if !header.is_call() || matches!(err, Error::Fragmented) {
+        return None;
+    }
+
+    let body = if header.rpc_version()? != RPC_VERSION {
+        ReplyBody::Denied(RejectedReply::RpcVersionMismatch {
+            low: RPC_VERSION,
+            high: RPC_VERSION,
+        })
+    } else {
+        // The call header is intact, so the credentials, verifier or
+        // arguments that follow it are malformed.
+        header.procedure()?;
+        ReplyBody::accepted(None, AcceptedStatus::GarbageArgs)
+    };
+
+    Some(RpcMessage::new(xid, MessageType::Reply(body)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::{
+        auth::{NoneAuthenticator, Principal},
+        AcceptedReply, AuthError,
+    };
+
+    const PROGRAM: u32 = 100099;
+
+    fn peer() -> Peer {
+        Peer::Inet("127.0.0.1:1234".parse().unwrap())
+    }
+
+    fn call<'a>(
+        vers: u32,
+        procedure: u32,
+        creds: AuthFlavor<&'a [u8]>,
+        payload: &'a [u8],
+    ) -> RpcMessage<&'a [u8], &'a [u8]> {
+        call_program(PROGRAM, vers, procedure, creds, payload)
+    }
+
+    fn call_program<'a>(
+        program: u32,
+        vers: u32,
+        procedure: u32,
+        creds: AuthFlavor<&'a [u8]>,
+        payload: &'a [u8],
+    ) -> RpcMessage<&'a [u8], &'a [u8]> {
+        RpcMessage::new(
+            42,
+            MessageType::Call(CallBody::new(
+                program,
+                vers,
+                procedure,
+                creds,
+                AuthFlavor::AuthNone(None),
+                payload,
+            )),
+        )
+    }
+
+    fn status<T, P>(reply: &RpcMessage<T, P>) -> &AcceptedStatus<P>
+    where
+        T: AsRef<[u8]> + Debug,
+        P: AsRef<[u8]> + Debug,
+    {
+        assert_eq!(reply.xid(), 42);
+        match reply.reply_body().unwrap() {
+            ReplyBody::Accepted(r) => r.status(),
+            v => panic!("unexpected reply {v:?}"),
+        }
+    }
+
+    fn dispatcher() -> Dispatcher {
+        Dispatcher::new()
+            .with_procedure(PROGRAM, 1, 1, |_ctx, args: Vec<u8>| async move { Ok(args) })
+            .with_procedure(
+                PROGRAM,
+                3,
+                1,
+                Procedure::sync(|ctx: CallContext, _args| {
+                    assert_eq!(ctx.xid(), 42);
+                    assert_eq!(ctx.program(), PROGRAM);
+                    assert_eq!(ctx.program_version(), 3);
+                    assert_eq!(ctx.procedure(), 1);
+                    assert_eq!(*ctx.peer(), peer());
+                    Ok(vec![3])
+                }),
+            )
+            .with_procedure(
+                PROGRAM,
+                3,
+                2,
+                Procedure::sync(|_ctx, args: Vec<u8>| {
+                    // Decode failures become GARBAGE_ARGS.
+                    AuthFlavor::try_from(args.as_slice())?;
+                    Ok(vec![])
+                }),
+            )
+            .with_procedure(
+                PROGRAM,
+                3,
+                3,
+                Procedure::sync(|_ctx, _args| Err(ProcedureError::SystemError)),
+            )
+            .with_procedure(
+                PROGRAM,
+                3,
+                4,
+                Procedure::sync(|_ctx, _args| Err(AuthError::TooWeak.into())),
+            )
+    }
+
+    #[tokio::test]
+    async fn test_routing() {
+        let d = dispatcher();
+        let none = || AuthFlavor::AuthNone(None);
+
+        let reply = d
+            .dispatch(&peer(), &call(1, 1, none(), b"hi"))
+            .await
+            .unwrap();
+        assert_eq!(*status(&reply), AcceptedStatus::Success(b"hi".to_vec()));
+
+        let reply = d.dispatch(&peer(), &call(3, 1, none(), &[])).await.unwrap();
+        assert_eq!(*status(&reply), AcceptedStatus::Success(vec![3]));
+
+        let reply = d.dispatch(&peer(), &call(3, 9, none(), &[])).await.unwrap();
+        assert_eq!(*status(&reply), AcceptedStatus::ProcedureUnavailable);
+
+        // The mismatch range spans the registered versions.
+        let reply = d.dispatch(&peer(), &call(2, 1, none(), &[])).await.unwrap();
+        assert_eq!(
+            *status(&reply),
+            AcceptedStatus::ProgramMismatch { low: 1, high: 3 }
+        );
+        assert_eq!(d.versions(PROGRAM), Some((1, 3)));
+
+        let reply = d
+            .dispatch(&peer(), &call_program(7, 1, 1, none(), &[]))
+            .await
+            .unwrap();
+        assert_eq!(*status(&reply), AcceptedStatus::ProgramUnavailable);
+        assert_eq!(d.versions(7), None);
+    }
+
+    #[tokio::test]
+    async fn test_null_procedure() {
+        let d = dispatcher();
+
+        for vers in [1, 3] {
+            let reply = d
+                .dispatch(&peer(), &call(vers, 0, AuthFlavor::AuthNone(None), &[]))
+                .await
+                .unwrap();
+            assert_eq!(*status(&reply), AcceptedStatus::Success(vec![]));
+        }
+
+        // An explicitly registered NULL procedure is called.
+        let d = d.with_procedure(PROGRAM, 1, 0, Procedure::sync(|_ctx, _args| Ok(vec![1])));
+        let reply = d
+            .dispatch(&peer(), &call(1, 0, AuthFlavor::AuthNone(None), &[]))
+            .await
+            .unwrap();
+        assert_eq!(*status(&reply), AcceptedStatus::Success(vec![1]));
+    }
+
+    #[tokio::test]
+    async fn test_procedure_errors() {
+        let d = dispatcher();
+        let none = || AuthFlavor::AuthNone(None);
+
+        let reply = d
+            .dispatch(&peer(), &call(3, 2, none(), b"bad"))
+            .await
+            .unwrap();
+        assert_eq!(*status(&reply), AcceptedStatus::GarbageArgs);
+
+        let reply = d.dispatch(&peer(), &call(3, 3, none(), &[])).await.unwrap();
+        assert_eq!(*status(&reply), AcceptedStatus::SystemError);
+
+        let reply = d.dispatch(&peer(), &call(3, 4, none(), &[])).await.unwrap();
+        assert_eq!(
+            reply.reply_body(),
+            Some(&ReplyBody::Denied(RejectedReply::AuthError(
+                AuthError::TooWeak
+            )))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rpc_version_mismatch() {
+        let d = dispatcher();
+        let msg = call(1, 1, AuthFlavor::AuthNone(None), &[]);
+
+        let want = Some(&ReplyBody::Denied(RejectedReply::RpcVersionMismatch {
+            low: 2,
+            high: 2,
+        }));
+
+        // Patch the RPC version following the header, xid and message type.
+        let mut record = msg.serialise().unwrap();
+        record[12..16].copy_from_slice(&3_u32.to_be_bytes());
+        let mut out = Vec::new();
+        assert!(d.dispatch_record(&peer(), &record, &mut out).await.unwrap());
+        let reply = RpcMessage::try_from(out.as_slice()).unwrap();
+        assert_eq!(reply.xid(), 42);
+        assert_eq!(reply.reply_body(), want);
+
+        let mut datagram = msg.serialise_datagram().unwrap();
+        datagram[8..12].copy_from_slice(&3_u32.to_be_bytes());
+        assert!(d
+            .dispatch_datagram(&peer(), &datagram, &mut out)
+            .await
+            .unwrap());
+        let reply = RpcMessage::from_datagram(&out).unwrap();
+        assert_eq!(reply.xid(), 42);
+        assert_eq!(reply.reply_body(), want);
+
+        // Other parse errors are dropped.
+        assert!(!d
+            .dispatch_datagram(&peer(), &datagram[..10], &mut out)
+            .await
+            .unwrap());
+        assert!(out.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_malformed_credentials() {
+        let d = dispatcher();
+        let msg = call(1, 1, AuthFlavor::AuthNone(None), &[]);
+
+        // Patch the credential length following the call header.
+        let mut record = msg.serialise().unwrap();
+        record[32..36].copy_from_slice(&1000_u32.to_be_bytes());
+        let mut out = Vec::new();
+        assert!(d.dispatch_record(&peer(), &record, &mut out).await.unwrap());
+        let reply = RpcMessage::try_from(out.as_slice()).unwrap();
+        assert_eq!(*status(&reply), AcceptedStatus::GarbageArgs);
+
+        let mut datagram = msg.serialise_datagram().unwrap();
+        datagram[28..32].copy_from_slice(&1000_u32.to_be_bytes());
+        assert!(d
+            .dispatch_datagram(&peer(), &datagram, &mut out)
+            .await
+            .unwrap());
+        let reply = RpcMessage::from_datagram(&out).unwrap();
+        assert_eq!(*status(&reply), AcceptedStatus::GarbageArgs);
+
+        // Fragmented records must be reassembled before dispatching.
+        record[0] &= 0x7f;
+        assert!(!d.dispatch_record(&peer(), &record, &mut out).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_request_cache() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let cache = Arc::new(DuplicateRequestCache::new());
+        let d = Dispatcher::new()
+            .with_duplicate_request_cache(Arc::clone(&cache))
+            .with_procedure(PROGRAM, 1, 1, {
+                let calls = Arc::clone(&calls);
+                Procedure::sync(move |_ctx, _args| {
+                    let n = calls.fetch_add(1, Ordering::Relaxed) as u8;
+                    Ok(vec![n])
+                })
+                .with_reply_cache()
+            })
+            .with_procedure(PROGRAM, 1, 2, {
+                let calls = Arc::clone(&calls);
+                Procedure::sync(move |_ctx, _args| {
+                    let n = calls.fetch_add(1, Ordering::Relaxed) as u8;
+                    Ok(vec![n])
+                })
+            });
+
+        let none = || AuthFlavor::AuthNone(None);
+        let datagram = call(1, 1, none(), b"remove").serialise_datagram().unwrap();
+        let record = call(1, 1, none(), b"remove").serialise().unwrap();
+
+        // Retransmissions are answered with the original reply, over either
+        // transport.
+        let mut first = Vec::new();
+        assert!(d
+            .dispatch_datagram(&peer(), &datagram, &mut first)
+            .await
+            .unwrap());
+        let reply = RpcMessage::from_datagram(&first).unwrap();
+        assert_eq!(*status(&reply), AcceptedStatus::Success(&[0][..]));
+
+        let mut out = Vec::new();
+        assert!(d
+            .dispatch_datagram(&peer(), &datagram, &mut out)
+            .await
+            .unwrap());
+        assert_eq!(out, first);
+
+        assert!(d.dispatch_record(&peer(), &record, &mut out).await.unwrap());
+        let mut want = (first.len() as u32 | LAST_FRAGMENT_BIT)
+            .to_be_bytes()
+            .to_vec();
+        want.extend_from_slice(&first);
+        assert_eq!(out, want);
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+
+        // A different peer, payload or uncached procedure is executed.
+        let other = Peer::Inet("127.0.0.2:4321".parse().unwrap());
+        d.dispatch_datagram(&other, &datagram, &mut out)
+            .await
+            .unwrap();
+        let changed = call(1, 1, none(), b"rename").serialise_datagram().unwrap();
+        d.dispatch_datagram(&peer(), &changed, &mut out)
+            .await
+            .unwrap();
+        let uncached = call(1, 2, none(), b"remove").serialise_datagram().unwrap();
+        for _ in 0..2 {
+            d.dispatch_datagram(&peer(), &uncached, &mut out)
+                .await
+                .unwrap();
+        }
+        assert_eq!(calls.load(Ordering::Relaxed), 5);
+
+        let stats = cache.stats();
+        assert_eq!(stats.hits(), 2);
+        assert_eq!(stats.misses(), 3);
+
+        // A duplicate of a call still executing is dropped.
+        let key = RequestKey::new(&peer(), 7, call(1, 1, none(), &[]).call_body().unwrap());
+        assert_eq!(cache.begin(&key), CacheLookup::New);
+        let mut msg = call(1, 1, none(), &[]).serialise_datagram().unwrap();
+        msg[..4].copy_from_slice(&7_u32.to_be_bytes());
+        assert!(!d.dispatch_datagram(&peer(), &msg, &mut out).await.unwrap());
+        assert_eq!(cache.stats().in_progress(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_authenticator() {
+        let d = Dispatcher::new()
+            .with_authenticator(NoneAuthenticator)
+            .with_procedure(
+                PROGRAM,
+                1,
+                1,
+                Procedure::sync(|ctx, _args| {
+                    assert_eq!(ctx.principal(), Some(&Principal::Anonymous));
+                    Ok(vec![])
+                }),
+            );
+
+        let reply = d
+            .dispatch(&peer(), &call(1, 1, AuthFlavor::AuthNone(None), &[]))
+            .await
+            .unwrap();
+        assert_eq!(*status(&reply), AcceptedStatus::Success(vec![]));
+
+        // Rejected before routing, even to an unknown procedure.
+        let creds = AuthFlavor::AuthShort(b"handle".as_slice());
+        for procedure in [1, 9] {
+            let reply = d
+                .dispatch(&peer(), &call(1, procedure, creds.clone(), &[]))
+                .await
+                .unwrap();
+            assert_eq!(
+                reply.reply_body(),
+                Some(&ReplyBody::Denied(RejectedReply::AuthError(
+                    AuthError::BadCredentials
+                )))
+            );
+        }
+
+        // Without an authenticator, there is no principal.
+        let d = Dispatcher::new().with_procedure(
+            PROGRAM,
+            1,
+            1,
+            Procedure::sync(|ctx, _args| {
+                assert_eq!(ctx.principal(), None);
+                assert!(matches!(ctx.credentials(), AuthFlavor::AuthShort(_)));
+                Ok(vec![])
+            }),
+        );
+        let reply = d.dispatch(&peer(), &call(1, 1, creds, &[])).await.unwrap();
+        assert_eq!(*status(&reply), AcceptedStatus::Success(vec![]));
+    }
+
+    #[tokio::test]
+    async fn test_suppressed_reply() {
+        let d = Dispatcher::new().with_procedure(
+            PROGRAM,
+            1,
+            1,
+            Procedure::sync(|_ctx, args: Vec<u8>| match args.as_slice() {
+                b"bad" => Err(ProcedureError::GarbageArgs),
+                _ => Ok(args),
+            })
+            .with_suppressed_reply(),
+        );
+
+        let none = || AuthFlavor::AuthNone(None);
+        assert!(d
+            .dispatch(&peer(), &call(1, 1, none(), b"ok"))
+            .await
+            .is_none());
+
+        // Errors are still replied to.
+        let reply = d
+            .dispatch(&peer(), &call(1, 1, none(), b"bad"))
+            .await
+            .unwrap();
+        assert_eq!(*status(&reply), AcceptedStatus::GarbageArgs);
+    }
+
+    #[tokio::test]
+    async fn test_reply_ignored() {
+        let d = dispatcher();
+        let msg = RpcMessage::new(
+            42,
+            MessageType::Reply(ReplyBody::<&[u8], &[u8]>::Accepted(AcceptedReply::new(
+                AuthFlavor::AuthNone(None),
+                AcceptedStatus::Success(&[]),
+            ))),
+        );
+        assert!(d.dispatch(&peer(), &msg).await.is_none());
+    }
+}
@@ -0,0 +1,497 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::{DefaultHasher, Hash, Hasher},
+    net::SocketAddr,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use crate::{auth::Peer, CallBody};
+
+/// The default maximum number of requests remembered by a
+/// [`DuplicateRequestCache`].
+pub const DEFAULT_DRC_CAPACITY: usize = 1024;
+
+/// The default time a completed request is remembered by a
+/// [`DuplicateRequestCache`].
+pub const DEFAULT_DRC_TTL: Duration = Duration::from_secs(120);
+
+/// Identifies a call for duplicate detection.
+///
+/// Two calls are duplicates if they are received from the same [`Peer`], with
+/// the same xid, program, version and procedure, and the same payload (as
+/// compared by checksum).
+///
+/// The source port of [`Peer::Inet`] callers is ignored, so a call replayed by
+/// a client over a new TCP connection is detected as a duplicate.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RequestKey {
+    peer: Peer,
+    xid: u32,
+    program: u32,
+    program_version: u32,
+    procedure: u32,
+    checksum: u64,
+}
+
+impl RequestKey {
+    /// Construct the key of the call with `xid` and body `call`, received
+    /// from `peer`.
+    pub fn new<T, P>(peer: &Peer, xid: u32, call: &CallBody<T, P>) -> Self
+    where
+        T: AsRef<[u8]>,
+        P: AsRef<[u8]>,
+    {
+        let mut hasher = DefaultHasher::new();
+        call.payload().as_ref().hash(&mut hasher);
+
+        // Clients reconnect from a new source port.
+        let peer = match peer {
+            Peer::Inet(addr) => Peer::Inet(SocketAddr::new(addr.ip(), 0)),
+            v => v.clone(),
+        };
+
+        Self {
+            peer,
+            xid,
+            program: call.program(),
+            program_version: call.program_version(),
+            procedure: call.procedure(),
+            checksum: hasher.finish(),
+        }
+    }
+}
+
+/// The result of looking up a call in a [`DuplicateRequestCache`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CacheLookup {
+    /// The call has not been seen before, and is now recorded as in progress.
+    ///
+    /// The caller must execute the call, and then call
+    /// [`DuplicateRequestCache::complete()`] or
+    /// [`DuplicateRequestCache::abandon()`].
+    New,
+
+    /// The call is a duplicate of a call still being executed, and should be
+    /// dropped.
+    InProgress,
+
+    /// The call is a duplicate of a completed call, and should be answered
+    /// with the serialised reply datagram of the original call, if any.
+    Completed(Option<Arc<[u8]>>),
+}
+
+/// Counters describing the effectiveness of a [`DuplicateRequestCache`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    hits: u64,
+    in_progress: u64,
+    misses: u64,
+}
+
+impl CacheStats {
+    /// The number of duplicates of completed calls, answered with the cached
+    /// reply.
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// The number of duplicates of calls still being executed, which were
+    /// dropped.
+    pub fn in_progress(&self) -> u64 {
+        self.in_progress
+    }
+
+    /// The number of calls that were not duplicates.
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+}
+
+/// A server-side cache of recent calls and their replies, preventing
+/// retransmitted calls from being executed more than once.
+///
+/// Clients retransmit calls over UDP when a reply is lost or slow, and replay
+/// pending calls over a new connection after a TCP connection fails. For
+/// non-idempotent procedures (such as removing or renaming a file) executing
+/// the call a second time returns the wrong result - the cache instead
+/// answers duplicates with the reply to the original call, and drops
+/// duplicates of calls still executing.
+///
+/// The cache remembers at most [`DuplicateRequestCache::with_capacity()`]
+/// calls, evicting the oldest when full, and forgets completed calls after
+/// [`DuplicateRequestCache::with_ttl()`].
+///
+/// A cache is used by a [`Dispatcher`](crate::server::Dispatcher) for the
+/// procedures registered with
+/// [`Procedure::with_reply_cache()`](crate::server::Procedure::with_reply_cache).
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use onc_rpc::server::{Dispatcher, DuplicateRequestCache, Procedure};
+///
+/// let dispatcher = Dispatcher::new()
+///     .with_duplicate_request_cache(
+///         DuplicateRequestCache::new()
+///             .with_capacity(4096)
+///             .with_ttl(Duration::from_secs(60)),
+///     )
+///     .with_procedure(
+///         100099,
+///         1,
+///         1,
+///         Procedure::sync(|_ctx, args| Ok(args)).with_reply_cache(),
+///     );
+/// ```
+#[derive(Debug)]
+pub struct DuplicateRequestCache {
+    capacity: usize,
+    ttl: Duration,
+    state: Mutex<State>,
+}
+
+impl DuplicateRequestCache {
+    /// Construct an empty cache with the default capacity and TTL.
+    pub fn new() -> Self {
+        Self {
+            capacity: DEFAULT_DRC_CAPACITY,
+            ttl: DEFAULT_DRC_TTL,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Remember at most `capacity` calls, evicting the oldest when full.
+    ///
+    /// Calls still executing are never evicted, so the cache may briefly hold
+    /// more than `capacity` calls when more are executing at once.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn with_capacity(self, capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity must be non-zero");
+        Self { capacity, ..self }
+    }
+
+    /// Forget completed calls `ttl` after they complete.
+    ///
+    /// This should exceed the longest time a client may retransmit a call.
+    pub fn with_ttl(self, ttl: Duration) -> Self {
+        Self { ttl, ..self }
+    }
+
+    /// Look up the call identified by `key`, recording it as in progress if
+    /// it is not a duplicate.
+    pub fn begin(&self, key: &RequestKey) -> CacheLookup {
+        let now = Instant::now();
+        let mut state = self.lock();
+
+        match state.entries.get(key).map(|v| &v.status) {
+            Some(Status::InProgress) => {
+                state.stats.in_progress += 1;
+                return CacheLookup::InProgress;
+            }
+            Some(Status::Completed { reply, at }) if now.duration_since(*at) < self.ttl => {
+                let reply = reply.clone();
+                state.stats.hits += 1;
+                return CacheLookup::Completed(reply);
+            }
+            Some(Status::Completed { .. }) | None => {}
+        }
+
+        state.stats.misses += 1;
+        state.insert(key.clone(), Status::InProgress);
+        state.evict(now, self.capacity, self.ttl);
+
+        CacheLookup::New
+    }
+
+    /// Record the serialised reply datagram sent for the call identified by
+    /// `key`, or `None` if no reply was sent.
+    ///
+    /// Duplicates of the call are answered with `reply` until it expires.
+    pub fn complete(&self, key: &RequestKey, reply: Option<Arc<[u8]>>) {
+        let mut state = self.lock();
+        if let Some(Status::InProgress) = state.entries.get(key).map(|v| &v.status) {
+            let at = Instant::now();
+            state.insert(key.clone(), Status::Completed { reply, at });
+        }
+    }
+
+    /// Forget the in-progress call identified by `key` without recording a
+    /// reply, allowing a duplicate to be executed.
+    ///
+    /// This should be called if the call is not executed to completion, such
+    /// as when the connection it was received on fails.
+    pub fn abandon(&self, key: &RequestKey) {
+        let mut state = self.lock();
+        if let Some(Status::InProgress) = state.entries.get(key).map(|v| &v.status) {
+            state.entries.remove(key);
+        }
+    }
+
+    /// The number of calls currently remembered, including expired calls not
+    /// yet evicted.
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    /// Returns true if no calls are remembered.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The hit and miss counters of this cache.
+    pub fn stats(&self) -> CacheStats {
+        self.lock().stats
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        // The state is always left consistent.
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Default for DuplicateRequestCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug)]
+enum Status {
+    InProgress,
+    Completed {
+        reply: Option<Arc<[u8]>>,
+        at: Instant,
+    },
+}
+
+#[derive(Debug)]
+struct Entry {
+    /// Matches the queue item for the latest update of this entry - earlier
+    /// queue items are stale.
+    generation: u64,
+    status: Status,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    entries: HashMap<RequestKey, Entry>,
+
+    /// Entry keys, in the order they were last updated.
+    order: VecDeque<(RequestKey, u64)>,
+    next_generation: u64,
+    stats: CacheStats,
+}
+
+impl State {
+    fn insert(&mut self, key: RequestKey, status: Status) {
+        let generation = self.next_generation;
+        self.next_generation += 1;
+
+        self.order.push_back((key.clone(), generation));
+        self.entries.insert(key, Entry { generation, status });
+    }
+
+    /// Remove entries from the front of the queue while the cache exceeds
+    /// `capacity`, or the oldest entry has expired.
+    ///
+    /// Calls still executing are never evicted, as a retransmission would then
+    /// execute the call again - they are moved to the back of the queue
+    /// instead, allowing the cache to exceed `capacity` while all remembered
+    /// calls are executing.
+    fn evict(&mut self, now: Instant, capacity: usize, ttl: Duration) {
+        let mut skipped = 0;
+        while skipped < self.order.len() {
+            let (key, generation) = self.order.front().unwrap();
+            let evict = match self.entries.get(key) {
+                Some(e) if e.generation != *generation => true,
+                Some(Entry {
+                    status: Status::InProgress,
+                    ..
+                }) => {
+                    if self.entries.len() <= capacity {
+                        break;
+                    }
+                    let v = self.order.pop_front().unwrap();
+                    self.order.push_back(v);
+                    skipped += 1;
+                    continue;
+                }
+                Some(_) if self.entries.len() > capacity => true,
+                Some(Entry {
+                    status: Status::Completed { at, .. },
+                    ..
+                }) => now.duration_since(*at) >= ttl,
+                None => true,
+            };
+
+            if !evict {
+                break;
+            }
+
+            let (key, generation) = self.order.pop_front().unwrap();
+            if self.entries.get(&key).map(|v| v.generation) == Some(generation) {
+                self.entries.remove(&key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::AuthFlavor;
+
+    fn key(xid: u32, payload: &[u8]) -> RequestKey {
+        let peer = Peer::Inet("127.0.0.1:1234".parse().unwrap());
+        let call = CallBody::new(
+            100003,
+            3,
+            12,
+            AuthFlavor::<&[u8]>::AuthNone(None),
+            AuthFlavor::AuthNone(None),
+            payload,
+        );
+        RequestKey::new(&peer, xid, &call)
+    }
+
+    fn reply(v: &[u8]) -> Option<Arc<[u8]>> {
+        Some(Arc::from(v))
+    }
+
+    #[test]
+    fn test_key() {
+        assert_eq!(key(1, b"a"), key(1, b"a"));
+        assert_ne!(key(1, b"a"), key(2, b"a"));
+        assert_ne!(key(1, b"a"), key(1, b"b"));
+
+        let call = CallBody::new(
+            100003,
+            3,
+            12,
+            AuthFlavor::<&[u8]>::AuthNone(None),
+            AuthFlavor::AuthNone(None),
+            b"a".as_slice(),
+        );
+
+        // The source port is ignored, but not the address.
+        let port = Peer::Inet("127.0.0.1:1235".parse().unwrap());
+        assert_eq!(key(1, b"a"), RequestKey::new(&port, 1, &call));
+
+        let other = Peer::Inet("127.0.0.2:1234".parse().unwrap());
+        assert_ne!(key(1, b"a"), RequestKey::new(&other, 1, &call));
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let cache = DuplicateRequestCache::new();
+        let k = key(1, b"rename");
+
+        assert_eq!(cache.begin(&k), CacheLookup::New);
+        assert_eq!(cache.begin(&k), CacheLookup::InProgress);
+
+        cache.complete(&k, reply(b"done"));
+        assert_eq!(cache.begin(&k), CacheLookup::Completed(reply(b"done")));
+
+        // Calls completed without a reply are still not executed again.
+        let k2 = key(2, b"batched");
+        assert_eq!(cache.begin(&k2), CacheLookup::New);
+        cache.complete(&k2, None);
+        assert_eq!(cache.begin(&k2), CacheLookup::Completed(None));
+
+        let stats = cache.stats();
+        assert_eq!(stats.misses(), 2);
+        assert_eq!(stats.in_progress(), 1);
+        assert_eq!(stats.hits(), 2);
+    }
+
+    #[test]
+    fn test_abandon() {
+        let cache = DuplicateRequestCache::new();
+        let k = key(1, b"remove");
+
+        assert_eq!(cache.begin(&k), CacheLookup::New);
+        cache.abandon(&k);
+        assert!(cache.is_empty());
+        assert_eq!(cache.begin(&k), CacheLookup::New);
+
+        // Completed calls are not abandoned.
+        cache.complete(&k, reply(b"done"));
+        cache.abandon(&k);
+        assert_eq!(cache.begin(&k), CacheLookup::Completed(reply(b"done")));
+    }
+
+    #[test]
+    fn test_ttl() {
+        let cache = DuplicateRequestCache::new().with_ttl(Duration::from_millis(50));
+        let k = key(1, b"remove");
+
+        assert_eq!(cache.begin(&k), CacheLookup::New);
+
+        // In-progress calls do not expire.
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(cache.begin(&k), CacheLookup::InProgress);
+
+        cache.complete(&k, reply(b"done"));
+        assert_eq!(cache.begin(&k), CacheLookup::Completed(reply(b"done")));
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(cache.begin(&k), CacheLookup::New);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_capacity() {
+        let cache = DuplicateRequestCache::new().with_capacity(2);
+
+        for xid in 0..3 {
+            assert_eq!(cache.begin(&key(xid, b"")), CacheLookup::New);
+            cache.complete(&key(xid, b""), reply(&[xid as u8]));
+        }
+        assert_eq!(cache.len(), 2);
+
+        // The oldest call was evicted.
+        assert_eq!(cache.begin(&key(0, b"")), CacheLookup::New);
+        assert_eq!(
+            cache.begin(&key(2, b"")),
+            CacheLookup::Completed(reply(&[2]))
+        );
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_capacity_keeps_in_progress() {
+        let cache = DuplicateRequestCache::new().with_capacity(2);
+
+        // The oldest call is still executing when the cache fills.
+        assert_eq!(cache.begin(&key(0, b"")), CacheLookup::New);
+        for xid in 1..4 {
+            assert_eq!(cache.begin(&key(xid, b"")), CacheLookup::New);
+            cache.complete(&key(xid, b""), reply(&[xid as u8]));
+        }
+        assert_eq!(cache.len(), 2);
+
+        // The executing call is kept, and the oldest completed calls evicted.
+        assert_eq!(cache.begin(&key(0, b"")), CacheLookup::InProgress);
+        assert_eq!(cache.begin(&key(2, b"")), CacheLookup::New);
+        cache.complete(&key(0, b""), reply(&[0]));
+        assert_eq!(
+            cache.begin(&key(0, b"")),
+            CacheLookup::Completed(reply(&[0]))
+        );
+
+        // The cache only exceeds its capacity while all remembered calls are
+        // executing.
+        let cache = DuplicateRequestCache::new().with_capacity(1);
+        for xid in 0..3 {
+            assert_eq!(cache.begin(&key(xid, b"")), CacheLookup::New);
+        }
+        assert_eq!(cache.len(), 3);
+        for xid in 0..3 {
+            assert_eq!(cache.begin(&key(xid, b"")), CacheLookup::InProgress);
+        }
+    }
+}
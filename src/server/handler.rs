@@ -0,0 +1,247 @@
+use std::{fmt::Debug, future::Future, pin::Pin, sync::Arc};
+
+use thiserror::Error;
+
+use crate::{
+    auth::{AuthFlavor, Peer, Principal},
+    AuthError,
+};
+
+/// The future returned by a [`Handler`], resolving to the serialised result of
+/// the procedure.
+pub type HandlerFuture = Pin<Box<dyn Future<Output = Result<Vec<u8>, ProcedureError>> + Send>>;
+
+/// The reason a [`Handler`] failed to execute a call, determining the error
+/// reply sent to the caller.
+#[derive(Debug, Clone, PartialEq, Error)]
+#[non_exhaustive]
+pub enum ProcedureError {
+    /// The call arguments could not be decoded.
+    ///
+    /// This is replied to as `GARBAGE_ARGS`.
+    #[error("garbage arguments")]
+    GarbageArgs,
+
+    /// The handler experienced an internal error.
+    ///
+    /// This is replied to as `SYSTEM_ERR`.
+    #[error("system error")]
+    SystemError,
+
+    /// The caller is not permitted to make the call.
+    ///
+    /// This is replied to as `AUTH_ERROR` with the given reason, such as
+    /// [`AuthError::TooWeak`].
+    #[error("auth error: {0}")]
+    Auth(AuthError),
+}
+
+/// Decoding errors are reported to the caller as `GARBAGE_ARGS`.
+impl From<crate::Error> for ProcedureError {
+    fn from(_v: crate::Error) -> Self {
+        Self::GarbageArgs
+    }
+}
+
+impl From<AuthError> for ProcedureError {
+    fn from(v: AuthError) -> Self {
+        Self::Auth(v)
+    }
+}
+
+/// The details of a call passed to a [`Handler`].
+#[derive(Debug, Clone)]
+pub struct CallContext {
+    xid: u32,
+    program: u32,
+    program_version: u32,
+    procedure: u32,
+    peer: Peer,
+    credentials: AuthFlavor<Vec<u8>>,
+    principal: Option<Principal>,
+}
+
+impl CallContext {
+    pub(crate) fn new(
+        xid: u32,
+        program: u32,
+        program_version: u32,
+        procedure: u32,
+        peer: Peer,
+        credentials: AuthFlavor<Vec<u8>>,
+        principal: Option<Principal>,
+    ) -> Self {
+        Self {
+            xid,
+            program,
+            program_version,
+            procedure,
+            peer,
+            credentials,
+            principal,
+        }
+    }
+
+    /// The transaction ID of the call.
+    pub fn xid(&self) -> u32 {
+        self.xid
+    }
+
+    /// The program number of the call.
+    pub fn program(&self) -> u32 {
+        self.program
+    }
+
+    /// The program version of the call.
+    pub fn program_version(&self) -> u32 {
+        self.program_version
+    }
+
+    /// The procedure number of the call.
+    pub fn procedure(&self) -> u32 {
+        self.procedure
+    }
+
+    /// The transport endpoint the call was received from.
+    pub fn peer(&self) -> &Peer {
+        &self.peer
+    }
+
+    /// The credentials sent by the caller.
+    pub fn credentials(&self) -> &AuthFlavor<Vec<u8>> {
+        &self.credentials
+    }
+
+    /// The identity of the caller, or `None` if the [`Dispatcher`] has no
+    /// [`Authenticator`].
+    ///
+    /// [`Dispatcher`]: crate::server::Dispatcher
+    /// [`Authenticator`]: crate::auth::Authenticator
+    pub fn principal(&self) -> Option<&Principal> {
+        self.principal.as_ref()
+    }
+}
+
+/// A procedure implementation, receiving the serialised call arguments and
+/// returning the serialised result.
+///
+/// `Handler` is implemented for async functions and closures with the
+/// signature `Fn(CallContext, Vec<u8>) -> impl Future<Output = Result<Vec<u8>,
+/// ProcedureError>>`. Synchronous functions can be used with
+/// [`Procedure::sync()`].
+///
+/// A handler that fails to decode the call arguments should return
+/// [`ProcedureError::GarbageArgs`] - the conversion from [`crate::Error`]
+/// does this when using `?`.
+pub trait Handler: Send + Sync + 'static {
+    /// Execute a call with the serialised `args`.
+    fn call(&self, ctx: CallContext, args: Vec<u8>) -> HandlerFuture;
+}
+
+impl<F, Fut> Handler for F
+where
+    F: Fn(CallContext, Vec<u8>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<Vec<u8>, ProcedureError>> + Send + 'static,
+{
+    fn call(&self, ctx: CallContext, args: Vec<u8>) -> HandlerFuture {
+        Box::pin(self(ctx, args))
+    }
+}
+
+/// A procedure registered with a [`Dispatcher`](crate::server::Dispatcher).
+///
+/// Any [`Handler`] can be converted into a `Procedure`.
+#[derive(Clone)]
+pub struct Procedure {
+    handler: Arc<dyn Handler>,
+    suppress_reply: bool,
+    reply_cache: bool,
+}
+
+impl Procedure {
+    /// Construct a procedure executed by `handler`.
+    pub fn new<H>(handler: H) -> Self
+    where
+        H: Handler,
+    {
+        Self {
+            handler: Arc::new(handler),
+            suppress_reply: false,
+            reply_cache: false,
+        }
+    }
+
+    /// Construct a procedure executed by the synchronous function `f`.
+    ///
+    /// ```
+    /// use onc_rpc::server::{CallContext, Procedure, ProcedureError};
+    ///
+    /// let echo = Procedure::sync(|_ctx: CallContext, args: Vec<u8>| {
+    ///     Ok::<_, ProcedureError>(args)
+    /// });
+    /// ```
+    pub fn sync<F>(f: F) -> Self
+    where
+        F: Fn(CallContext, Vec<u8>) -> Result<Vec<u8>, ProcedureError> + Send + Sync + 'static,
+    {
+        Self::new(move |ctx, args| std::future::ready(f(ctx, args)))
+    }
+
+    /// Do not reply to successful calls of this procedure.
+    ///
+    /// This allows clients to batch calls to the procedure (see
+    /// [`Client::batch()`](crate::client::Client::batch)). Error replies are
+    /// still sent.
+    pub fn with_suppressed_reply(self) -> Self {
+        Self {
+            suppress_reply: true,
+            ..self
+        }
+    }
+
+    /// Returns true if successful replies to this procedure are suppressed.
+    pub fn suppresses_reply(&self) -> bool {
+        self.suppress_reply
+    }
+
+    /// Answer retransmissions of calls to this procedure from the
+    /// dispatcher's [`DuplicateRequestCache`](crate::server::DuplicateRequestCache)
+    /// instead of executing them again.
+    ///
+    /// This should be set for non-idempotent procedures, for which executing
+    /// a call twice returns a different result.
+    pub fn with_reply_cache(self) -> Self {
+        Self {
+            reply_cache: true,
+            ..self
+        }
+    }
+
+    /// Returns true if replies to this procedure are cached for
+    /// retransmissions.
+    pub fn caches_reply(&self) -> bool {
+        self.reply_cache
+    }
+
+    pub(crate) fn call(&self, ctx: CallContext, args: Vec<u8>) -> HandlerFuture {
+        self.handler.call(ctx, args)
+    }
+}
+
+impl<H> From<H> for Procedure
+where
+    H: Handler,
+{
+    fn from(v: H) -> Self {
+        Self::new(v)
+    }
+}
+
+impl Debug for Procedure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Procedure")
+            .field("suppress_reply", &self.suppress_reply)
+            .field("reply_cache", &self.reply_cache)
+            .finish_non_exhaustive()
+    }
+}
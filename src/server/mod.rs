@@ -0,0 +1,43 @@
+//! RPC server building blocks, routing calls to the handlers registered for
+//! each program, version and procedure.
+//!
+//! A [`Dispatcher`] holds the [`Procedure`] handlers of one or more programs,
+//! authenticates each call with an optional
+//! [`Authenticator`](crate::auth::Authenticator), and builds the reply the
+//! spec requires when a call cannot be handled - such as `PROG_MISMATCH` with
+//! the range of registered versions.
+//!
+//! Non-idempotent procedures can be protected from retransmitted calls with a
+//! [`DuplicateRequestCache`], replaying the reply to the original call.
+//!
+//! The dispatcher is independent of any transport - it consumes call messages
+//! and produces reply messages, leaving the transport to read and write them.
+//! [`BlockingServer`] serves a dispatcher over TCP using only the standard
+//! library, executing calls on a fixed-size pool of worker threads.
+//!
+//! With the `tokio` feature enabled, [`AsyncServer`] serves a dispatcher over
+//! TCP and Unix domain socket connections, executing the calls received on
+//! each connection concurrently, and [`UdpServer`] serves it over UDP, batching
+//! the datagrams it receives and sends.
+
+mod blocking;
+mod dispatcher;
+mod drc;
+mod handler;
+#[cfg(feature = "tower")]
+mod service;
+#[cfg(feature = "tokio")]
+mod stream;
+#[cfg(feature = "tokio")]
+mod udp;
+
+pub use blocking::*;
+pub use dispatcher::*;
+pub use drc::*;
+pub use handler::*;
+#[cfg(feature = "tower")]
+pub use service::*;
+#[cfg(feature = "tokio")]
+pub use stream::*;
+#[cfg(feature = "tokio")]
+pub use udp::*;
@@ -0,0 +1,247 @@
+use std::{
+    convert::Infallible,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use crate::{
+    auth::Peer,
+    server::{Dispatcher, Reply},
+    RpcMessage,
+};
+
+/// A call message received from a [`Peer`], the request type of a
+/// [`DispatcherService`].
+#[derive(Debug, PartialEq)]
+pub struct RpcCall<T = Vec<u8>, P = Vec<u8>>
+where
+    T: AsRef<[u8]>,
+    P: AsRef<[u8]>,
+{
+    peer: Peer,
+    message: RpcMessage<T, P>,
+}
+
+impl<T, P> RpcCall<T, P>
+where
+    T: AsRef<[u8]>,
+    P: AsRef<[u8]>,
+{
+    /// Construct a request for the call `message` received from `peer`.
+    pub fn new(peer: Peer, message: RpcMessage<T, P>) -> Self {
+        Self { peer, message }
+    }
+
+    /// The transport endpoint the call was received from.
+    pub fn peer(&self) -> &Peer {
+        &self.peer
+    }
+
+    /// The call message.
+    pub fn message(&self) -> &RpcMessage<T, P> {
+        &self.message
+    }
+
+    /// Consume the request, returning the peer and call message.
+    pub fn into_parts(self) -> (Peer, RpcMessage<T, P>) {
+        (self.peer, self.message)
+    }
+}
+
+/// A [`tower_service::Service`] handling [`RpcCall`] requests with a
+/// [`Dispatcher`].
+///
+/// Each request is handled as [`Dispatcher::dispatch()`] does, except that
+/// retransmitted calls to procedures registered with
+/// [`Procedure::with_reply_cache()`](crate::server::Procedure::with_reply_cache)
+/// are answered from the dispatcher's
+/// [`DuplicateRequestCache`](crate::server::DuplicateRequestCache). The reply
+/// is `None` if the request is not a call, the reply is suppressed, or the
+/// request is a retransmission of a call still being executed. The service is
+/// always ready and never fails.
+///
+/// This allows tower middleware such as timeouts, rate limits and metrics to
+/// wrap the dispatcher.
+///
+/// ```
+/// use onc_rpc::{
+///     auth::{AuthFlavor, Peer},
+///     server::{Dispatcher, DispatcherService, Procedure, RpcCall},
+///     CallBody, MessageType, RpcMessage,
+/// };
+/// use tower_service::Service;
+/// # async fn example() {
+/// let mut service = DispatcherService::new(Dispatcher::new().with_procedure(
+///     100099,
+///     1,
+///     1,
+///     Procedure::sync(|_ctx, args| Ok(args)),
+/// ));
+///
+/// let msg = RpcMessage::new(
+///     42,
+///     MessageType::Call(CallBody::new(
+///         100099,
+///         1,
+///         1,
+///         AuthFlavor::<Vec<u8>>::AuthNone(None),
+///         AuthFlavor::AuthNone(None),
+///         b"hello".to_vec(),
+///     )),
+/// );
+///
+/// let peer = Peer::Inet("127.0.0.1:1234".parse().unwrap());
+/// let reply = service.call(RpcCall::new(peer, msg)).await.unwrap();
+/// assert_eq!(reply.unwrap().xid(), 42);
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct DispatcherService {
+    dispatcher: Arc<Dispatcher>,
+}
+
+impl DispatcherService {
+    /// Construct a service handling calls with `dispatcher`.
+    pub fn new(dispatcher: impl Into<Arc<Dispatcher>>) -> Self {
+        Self {
+            dispatcher: dispatcher.into(),
+        }
+    }
+
+    /// The dispatcher handling calls made to this service.
+    pub fn dispatcher(&self) -> &Arc<Dispatcher> {
+        &self.dispatcher
+    }
+}
+
+impl<T, P> tower_service::Service<RpcCall<T, P>> for DispatcherService
+where
+    T: AsRef<[u8]> + Send + Sync + 'static,
+    P: AsRef<[u8]> + Send + Sync + 'static,
+{
+    type Response = Option<Reply>;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Option<Reply>, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: RpcCall<T, P>) -> Self::Future {
+        let dispatcher = Arc::clone(&self.dispatcher);
+        Box::pin(async move { Ok(dispatcher.dispatch_message(&req.peer, &req.message).await) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use tower::{timeout::TimeoutLayer, Service, ServiceBuilder, ServiceExt};
+
+    use super::*;
+    use crate::{
+        auth::AuthFlavor,
+        server::{CallContext, DuplicateRequestCache, Procedure},
+        AcceptedStatus, CallBody, MessageType, ReplyBody,
+    };
+
+    const PROGRAM: u32 = 100099;
+
+    fn call(procedure: u32, args: &[u8]) -> RpcCall {
+        RpcCall::new(
+            Peer::Inet("127.0.0.1:1234".parse().unwrap()),
+            RpcMessage::new(
+                42,
+                MessageType::Call(CallBody::new(
+                    PROGRAM,
+                    1,
+                    procedure,
+                    AuthFlavor::AuthNone(None),
+                    AuthFlavor::AuthNone(None),
+                    args.to_vec(),
+                )),
+            ),
+        )
+    }
+
+    fn status(reply: &Reply) -> &AcceptedStatus<Vec<u8>> {
+        match reply.reply_body().unwrap() {
+            ReplyBody::Accepted(r) => r.status(),
+            v => panic!("unexpected reply {v:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_middleware() {
+        let dispatcher = Dispatcher::new()
+            .with_procedure(PROGRAM, 1, 1, Procedure::sync(|_ctx, args| Ok(args)))
+            .with_procedure(
+                PROGRAM,
+                1,
+                2,
+                |_ctx: CallContext, args: Vec<u8>| async move {
+                    tokio::time::sleep(Duration::from_secs(10)).await;
+                    Ok(args)
+                },
+            );
+
+        let service = ServiceBuilder::new()
+            .concurrency_limit(4)
+            .layer(TimeoutLayer::new(Duration::from_millis(50)))
+            .service(DispatcherService::new(dispatcher));
+
+        let reply = service
+            .clone()
+            .oneshot(call(1, b"hello"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(reply.xid(), 42);
+        assert_eq!(*status(&reply), AcceptedStatus::Success(b"hello".to_vec()));
+
+        // The slow procedure is cancelled by the timeout middleware.
+        let err = service.oneshot(call(2, &[])).await.unwrap_err();
+        assert!(err.is::<tower::timeout::error::Elapsed>());
+    }
+
+    #[tokio::test]
+    async fn test_unrouted() {
+        let mut service = DispatcherService::new(Dispatcher::new());
+
+        let reply = service.call(call(1, &[])).await.unwrap().unwrap();
+        assert_eq!(*status(&reply), AcceptedStatus::ProgramUnavailable);
+
+        let (peer, msg) = call(1, &[]).into_parts();
+        assert_eq!(peer, Peer::Inet("127.0.0.1:1234".parse().unwrap()));
+        assert_eq!(msg.xid(), 42);
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_request_cache() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let dispatcher = Dispatcher::new()
+            .with_duplicate_request_cache(DuplicateRequestCache::new())
+            .with_procedure(PROGRAM, 1, 1, {
+                let calls = Arc::clone(&calls);
+                Procedure::sync(move |_ctx, _args| {
+                    let n = calls.fetch_add(1, Ordering::Relaxed) as u8;
+                    Ok(vec![n])
+                })
+                .with_reply_cache()
+            });
+        let mut service = DispatcherService::new(dispatcher);
+
+        // The retransmission is answered with the original reply.
+        let first = service.call(call(1, b"remove")).await.unwrap().unwrap();
+        let again = service.call(call(1, b"remove")).await.unwrap().unwrap();
+        assert_eq!(*status(&first), AcceptedStatus::Success(vec![0]));
+        assert_eq!(again, first);
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,735 @@
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt},
+    net::TcpListener,
+    sync::{mpsc, Semaphore},
+    task::JoinSet,
+};
+
+use crate::{
+    auth::Peer,
+    read_record_async,
+    server::{Dispatcher, DEFAULT_MAX_RECORD_LEN},
+    Error,
+};
+
+/// The default maximum number of connections served at any one time.
+pub const DEFAULT_MAX_CONNECTIONS: usize = 1024;
+
+/// The default maximum number of calls executing at any one time on one
+/// connection or UDP socket.
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 128;
+
+/// Configuration for an [`AsyncServer`].
+#[derive(Debug, Clone)]
+pub struct AsyncServerBuilder {
+    max_connections: usize,
+    max_in_flight: usize,
+    max_record_len: usize,
+    idle_timeout: Option<Duration>,
+}
+
+impl AsyncServerBuilder {
+    /// Serve at most `max_connections` connections at any one time.
+    ///
+    /// Once the limit is reached, new connections are left in the listen
+    /// backlog until a connection closes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_connections` is 0.
+    pub fn with_max_connections(self, max_connections: usize) -> Self {
+        assert!(max_connections > 0, "max_connections must be non-zero");
+        Self {
+            max_connections,
+            ..self
+        }
+    }
+
+    /// Execute at most `max_in_flight` calls at any one time on each
+    /// connection.
+    ///
+    /// Once the limit is reached, no further calls are read from the
+    /// connection until an executing call completes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_in_flight` is 0.
+    pub fn with_max_in_flight(self, max_in_flight: usize) -> Self {
+        assert!(max_in_flight > 0, "max_in_flight must be non-zero");
+        Self {
+            max_in_flight,
+            ..self
+        }
+    }
+
+    /// Reject call records longer than `max_record_len` bytes.
+    ///
+    /// Receiving an oversized record closes the connection, as the stream
+    /// cannot be resynchronised.
+    pub fn with_max_record_len(self, max_record_len: usize) -> Self {
+        Self {
+            max_record_len,
+            ..self
+        }
+    }
+
+    /// Close connections that have no calls executing, and have neither sent
+    /// a call nor been sent a reply for `idle_timeout`.
+    ///
+    /// By default, idle connections are never closed.
+    pub fn with_idle_timeout(self, idle_timeout: Duration) -> Self {
+        Self {
+            idle_timeout: Some(idle_timeout),
+            ..self
+        }
+    }
+
+    /// Construct a server handling calls with `dispatcher`.
+    pub fn build(self, dispatcher: impl Into<Arc<Dispatcher>>) -> AsyncServer {
+        AsyncServer {
+            dispatcher: dispatcher.into(),
+            config: self,
+        }
+    }
+}
+
+impl Default for AsyncServerBuilder {
+    fn default() -> Self {
+        Self {
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+            max_record_len: DEFAULT_MAX_RECORD_LEN,
+            idle_timeout: None,
+        }
+    }
+}
+
+/// An async RPC server for stream transports, executing the calls received on
+/// each connection concurrently.
+///
+/// Call records are read from each connection (reassembling fragmented
+/// records) and handed to the [`Dispatcher`] as they arrive, with the replies
+/// written back in the order the calls complete - clients match replies to
+/// calls by transaction ID. Each connection executes at most
+/// [`AsyncServerBuilder::with_max_in_flight()`] calls at once.
+///
+/// `AsyncServer` is cheap to clone, with all clones sharing the same
+/// dispatcher.
+///
+/// ```no_run
+/// use onc_rpc::server::{AsyncServer, Dispatcher, Procedure};
+/// use tokio::net::TcpListener;
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let dispatcher = Dispatcher::new().with_procedure(
+///     100099,
+///     1,
+///     1,
+///     Procedure::sync(|_ctx, args| Ok(args)),
+/// );
+///
+/// let server = AsyncServer::builder()
+///     .with_max_in_flight(16)
+///     .build(dispatcher);
+///
+/// server.serve(TcpListener::bind("127.0.0.1:4242").await?).await;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct AsyncServer {
+    dispatcher: Arc<Dispatcher>,
+    config: AsyncServerBuilder,
+}
+
+impl AsyncServer {
+    /// Configure a new server.
+    pub fn builder() -> AsyncServerBuilder {
+        AsyncServerBuilder::default()
+    }
+
+    /// Construct a server with the default configuration, handling calls with
+    /// `dispatcher`.
+    pub fn new(dispatcher: impl Into<Arc<Dispatcher>>) -> Self {
+        AsyncServerBuilder::default().build(dispatcher)
+    }
+
+    /// The dispatcher handling calls received by this server.
+    pub fn dispatcher(&self) -> &Arc<Dispatcher> {
+        &self.dispatcher
+    }
+
+    /// Accept and serve TCP connections from `listener`.
+    ///
+    /// This runs until the returned future is dropped, which closes all
+    /// connections accepted by it. Errors accepting a connection are logged
+    /// and do not stop the server.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime.
+    pub async fn serve(&self, listener: TcpListener) {
+        self.accept_loop(|| async {
+            let (stream, addr) = listener.accept().await?;
+            stream.set_nodelay(true)?;
+            Ok((stream, Peer::Inet(addr)))
+        })
+        .await;
+    }
+
+    /// Accept and serve connections to the Unix domain socket `listener`.
+    ///
+    /// The [`Peer`] of each connection carries the
+    /// [`PeerCredentials`](crate::auth::PeerCredentials) of the connecting
+    /// process where the platform reports them.
+    ///
+    /// This runs until the returned future is dropped, which closes all
+    /// connections accepted by it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime.
+    #[cfg(unix)]
+    pub async fn serve_unix(&self, listener: tokio::net::UnixListener) {
+        use crate::auth::PeerCredentials;
+
+        self.accept_loop(|| async {
+            let (stream, _addr) = listener.accept().await?;
+            let creds = stream.peer_cred().ok().map(|v| {
+                let pid = v.pid().and_then(|v| u32::try_from(v).ok());
+                PeerCredentials::new(pid, v.uid(), v.gid())
+            });
+            Ok((stream, Peer::Unix(creds)))
+        })
+        .await;
+    }
+
+    /// Serve calls received from `peer` over the already-connected `stream`,
+    /// returning once the connection is closed.
+    ///
+    /// Returns `Ok` if the peer closed the connection, or it was closed for
+    /// being idle, and an error if the connection failed or the peer sent an
+    /// invalid record. Calls still executing when the peer closes the
+    /// connection are replied to before returning, if possible.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime.
+    pub async fn serve_connection<S>(&self, stream: S, peer: Peer) -> Result<(), Error>
+    where
+        S: AsyncRead + AsyncWrite + Send,
+    {
+        let (reader, writer) = tokio::io::split(stream);
+        let (tx, rx) = mpsc::channel(self.config.max_in_flight);
+        let conn = Arc::new(Connection {
+            peer,
+            in_flight: Arc::new(Semaphore::new(self.config.max_in_flight)),
+            last_active: Mutex::new(Instant::now()),
+        });
+
+        // Calls are executed in tasks owned by this connection, and aborted
+        // if the connection fails.
+        let mut calls = JoinSet::new();
+
+        let write = write_replies(writer, Arc::clone(&conn), rx);
+        tokio::pin!(write);
+
+        tokio::select! {
+            res = self.read_calls(reader, &conn, tx, &mut calls) => {
+                // Send the replies to calls that are still executing.
+                let write_res = write.await;
+                res.and(write_res)
+            }
+            res = &mut write => res,
+        }
+    }
+
+    /// Accept connections with `accept`, serving each in a new task.
+    async fn accept_loop<F, Fut, S>(&self, mut accept: F)
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<(S, Peer), std::io::Error>>,
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let mut conns = JoinSet::new();
+
+        loop {
+            // Leave new connections in the backlog until there is capacity.
+            if conns.len() >= self.config.max_connections {
+                conns.join_next().await;
+                continue;
+            }
+
+            let res = tokio::select! {
+                Some(_) = conns.join_next() => continue,
+                res = accept() => res,
+            };
+
+            match res {
+                Ok((stream, peer)) => {
+                    let server = self.clone();
+                    conns.spawn(async move {
+                        if let Err(e) = server.serve_connection(stream, peer).await {
+                            tracing::debug!(error=%e, "rpc server connection closed");
+                        }
+                    });
+                }
+                Err(e) => {
+                    // Back off to avoid spinning on persistent errors, such
+                    // as running out of file descriptors.
+                    tracing::warn!(error=%e, "rpc server accept failed");
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+            }
+        }
+    }
+
+    /// Read call records from `reader`, executing each in a task spawned into
+    /// `calls` that sends the serialised reply to `tx`.
+    async fn read_calls<R>(
+        &self,
+        mut reader: R,
+        conn: &Arc<Connection>,
+        tx: mpsc::Sender<Vec<u8>>,
+        calls: &mut JoinSet<()>,
+    ) -> Result<(), Error>
+    where
+        R: AsyncRead + Unpin + Send,
+    {
+        loop {
+            // Wait for capacity before reading the next call.
+            let permit = Arc::clone(&conn.in_flight)
+                .acquire_owned()
+                .await
+                .expect("in-flight semaphore is never closed");
+
+            // Reap completed calls.
+            while calls.try_join_next().is_some() {}
+
+            let mut buf = Vec::new();
+            let res = tokio::select! {
+                res = read_record_async(&mut reader, &mut buf, self.config.max_record_len) => res,
+                _ = conn.idle(self.config.idle_timeout, self.config.max_in_flight) => {
+                    tracing::debug!("closing idle rpc server connection");
+                    return Ok(());
+                }
+            };
+
+            match res {
+                Ok(()) => {}
+                Err(Error::IOError(std::io::ErrorKind::UnexpectedEof, _)) => return Ok(()),
+                Err(e) => return Err(e),
+            }
+
+            conn.touch();
+
+            let dispatcher = Arc::clone(&self.dispatcher);
+            let conn = Arc::clone(conn);
+            let tx = tx.clone();
+            calls.spawn(async move {
+                let mut out = Vec::new();
+                match dispatcher.dispatch_record(&conn.peer, &buf, &mut out).await {
+                    Ok(true) => {
+                        // The connection may have failed since the call was
+                        // received, in which case the reply is discarded.
+                        let _ = tx.send(out).await;
+                    }
+                    Ok(false) => {}
+                    Err(e) => tracing::warn!(error=%e, "failed to serialise rpc reply"),
+                }
+
+                drop(permit);
+            });
+        }
+    }
+}
+
+/// State shared between the tasks serving one connection.
+#[derive(Debug)]
+struct Connection {
+    peer: Peer,
+    in_flight: Arc<Semaphore>,
+
+    /// The time the last call was received or reply sent, or the connection
+    /// was established.
+    last_active: Mutex<Instant>,
+}
+
+impl Connection {
+    /// Record activity on the connection.
+    fn touch(&self) {
+        *self.last_active.lock().unwrap() = Instant::now();
+    }
+
+    /// Resolve once the connection has no calls executing and no activity for
+    /// `timeout`, or never if `timeout` is `None`.
+    async fn idle(&self, timeout: Option<Duration>, max_in_flight: usize) {
+        let Some(timeout) = timeout else {
+            return std::future::pending().await;
+        };
+
+        loop {
+            let deadline = *self.last_active.lock().unwrap() + timeout;
+            if deadline > Instant::now() {
+                tokio::time::sleep_until(deadline.into()).await;
+                continue;
+            }
+
+            // The permit held by the caller waiting to read the next call is
+            // not an executing call.
+            if self.in_flight.available_permits() + 1 >= max_in_flight {
+                return;
+            }
+
+            // Completing the executing calls resets the deadline.
+            tokio::time::sleep(timeout).await;
+        }
+    }
+}
+
+/// Write serialised reply records received from `rx` to `writer`, until all
+/// senders are dropped.
+async fn write_replies<W>(
+    mut writer: W,
+    conn: Arc<Connection>,
+    mut rx: mpsc::Receiver<Vec<u8>>,
+) -> Result<(), Error>
+where
+    W: AsyncWrite + Unpin + Send,
+{
+    while let Some(buf) = rx.recv().await {
+        writer.write_all(&buf).await?;
+        conn.touch();
+    }
+
+    writer.shutdown().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use tokio::{
+        io::AsyncReadExt,
+        net::TcpStream,
+        sync::{oneshot, Notify},
+    };
+
+    use super::*;
+    use crate::{
+        auth::AuthFlavor,
+        client::{AsyncClient, ClientError},
+        server::{CallContext, DuplicateRequestCache, Procedure, ProcedureError},
+        CallBody, MessageType, RpcError, RpcMessage,
+    };
+
+    const PROGRAM: u32 = 100099;
+
+    /// Serve `dispatcher` on a new loopback listener, returning its address.
+    async fn serve(server: AsyncServer) -> std::net::SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { server.serve(listener).await });
+        addr
+    }
+
+    fn echo() -> Dispatcher {
+        Dispatcher::new().with_procedure(PROGRAM, 1, 1, Procedure::sync(|_ctx, args| Ok(args)))
+    }
+
+    fn call_record(xid: u32, procedure: u32, args: &[u8]) -> Vec<u8> {
+        RpcMessage::new(
+            xid,
+            MessageType::Call(CallBody::new(
+                PROGRAM,
+                1,
+                procedure,
+                AuthFlavor::<&[u8]>::AuthNone(None),
+                AuthFlavor::AuthNone(None),
+                args,
+            )),
+        )
+        .serialise()
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_serve() {
+        let addr = serve(AsyncServer::new(echo())).await;
+        let client = AsyncClient::builder().connect(addr).await.unwrap();
+
+        let got = client.call(PROGRAM, 1, 1, b"hello").await.unwrap();
+        assert_eq!(got, b"hello");
+
+        client.ping(PROGRAM, 1).await.unwrap();
+
+        let err = client.call(PROGRAM, 2, 1, &[]).await.unwrap_err();
+        assert!(matches!(
+            err,
+            ClientError::Reply(RpcError::ProgramMismatch { low: 1, high: 1 })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_fragmented_call() {
+        let addr = serve(AsyncServer::new(echo())).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        // Split the call record into two fragments.
+        let record = call_record(42, 1, b"fragmented");
+        let (first, second) = record[4..].split_at(10);
+        stream
+            .write_all(&(first.len() as u32).to_be_bytes())
+            .await
+            .unwrap();
+        stream.write_all(first).await.unwrap();
+        stream
+            .write_all(&(second.len() as u32 | 1 << 31).to_be_bytes())
+            .await
+            .unwrap();
+        stream.write_all(second).await.unwrap();
+
+        let mut buf = Vec::new();
+        read_record_async(&mut stream, &mut buf, 1024)
+            .await
+            .unwrap();
+        let reply = RpcMessage::try_from(buf.as_slice()).unwrap();
+        assert_eq!(reply.xid(), 42);
+        assert_eq!(
+            reply.into_reply_body().unwrap().into_result(),
+            Ok(b"fragmented".as_slice())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_request_cache_reconnect() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let dispatcher = Dispatcher::new()
+            .with_duplicate_request_cache(DuplicateRequestCache::new())
+            .with_procedure(PROGRAM, 1, 1, {
+                let calls = Arc::clone(&calls);
+                Procedure::sync(move |_ctx, _args| {
+                    let n = calls.fetch_add(1, Ordering::Relaxed) as u8;
+                    Ok(vec![n])
+                })
+                .with_reply_cache()
+            });
+        let addr = serve(AsyncServer::new(dispatcher)).await;
+
+        // Send the same call over two connections, as a client replaying a
+        // call after reconnecting would.
+        let mut replies = Vec::new();
+        for _ in 0..2 {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            stream
+                .write_all(&call_record(42, 1, b"remove"))
+                .await
+                .unwrap();
+
+            let mut buf = Vec::new();
+            read_record_async(&mut stream, &mut buf, 1024)
+                .await
+                .unwrap();
+            replies.push(buf);
+        }
+
+        // The second call is answered with the cached reply.
+        assert_eq!(replies[0], replies[1]);
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_replies_in_completion_order() {
+        let (unblock_tx, unblock_rx) = oneshot::channel::<()>();
+        let unblock_rx = Arc::new(Mutex::new(Some(unblock_rx)));
+
+        let dispatcher = echo().with_procedure(PROGRAM, 1, 2, move |_ctx, args| {
+            let rx = unblock_rx.lock().unwrap().take().unwrap();
+            async move {
+                rx.await.unwrap();
+                Ok(args)
+            }
+        });
+
+        let addr = serve(AsyncServer::new(dispatcher)).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        // The slow call is sent first, but the fast call is replied to first.
+        stream.write_all(&call_record(1, 2, b"slow")).await.unwrap();
+        stream.write_all(&call_record(2, 1, b"fast")).await.unwrap();
+
+        let mut buf = Vec::new();
+        read_record_async(&mut stream, &mut buf, 1024)
+            .await
+            .unwrap();
+        assert_eq!(RpcMessage::try_from(buf.as_slice()).unwrap().xid(), 2);
+
+        unblock_tx.send(()).unwrap();
+        read_record_async(&mut stream, &mut buf, 1024)
+            .await
+            .unwrap();
+        assert_eq!(RpcMessage::try_from(buf.as_slice()).unwrap().xid(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_max_in_flight() {
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let notify = Arc::new(Notify::new());
+
+        let dispatcher = {
+            let running = Arc::clone(&running);
+            let peak = Arc::clone(&peak);
+            let notify = Arc::clone(&notify);
+            Dispatcher::new().with_procedure(PROGRAM, 1, 1, move |_ctx, _args| {
+                let running = Arc::clone(&running);
+                let peak = Arc::clone(&peak);
+                let notify = Arc::clone(&notify);
+                async move {
+                    let n = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(n, Ordering::SeqCst);
+                    notify.notified().await;
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    Ok(vec![])
+                }
+            })
+        };
+
+        let server = AsyncServer::builder()
+            .with_max_in_flight(2)
+            .build(dispatcher);
+        let addr = serve(server).await;
+        let client = AsyncClient::builder().connect(addr).await.unwrap();
+
+        let calls = (0..5)
+            .map(|_| {
+                let client = client.clone();
+                tokio::spawn(async move { client.call(PROGRAM, 1, 1, &[]).await })
+            })
+            .collect::<Vec<_>>();
+
+        // Release the calls as they start, until all have completed.
+        while !calls.iter().all(|v| v.is_finished()) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            notify.notify_waiters();
+        }
+
+        for c in calls {
+            c.await.unwrap().unwrap();
+        }
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_max_record_len() {
+        let server = AsyncServer::builder().with_max_record_len(64).build(echo());
+
+        let (client, server_end) = tokio::io::duplex(1024);
+        let handle =
+            tokio::spawn(
+                async move { server.serve_connection(server_end, Peer::Unix(None)).await },
+            );
+
+        let mut client = client;
+        client
+            .write_all(&call_record(1, 1, &[0; 100]))
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            handle.await.unwrap(),
+            Err(Error::RecordTooLarge { max: 64, .. })
+        ));
+
+        // The connection is closed without a reply.
+        let mut buf = Vec::new();
+        client.read_to_end(&mut buf).await.unwrap();
+        assert!(buf.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_idle_timeout() {
+        let server = AsyncServer::builder()
+            .with_idle_timeout(Duration::from_millis(100))
+            .build(echo());
+        let addr = serve(server).await;
+
+        let client = AsyncClient::builder().connect(addr).await.unwrap();
+        client.call(PROGRAM, 1, 1, &[]).await.unwrap();
+        assert!(!client.is_closed());
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(client.is_closed());
+    }
+
+    #[tokio::test]
+    async fn test_idle_timeout_waits_for_calls() {
+        let dispatcher = Dispatcher::new().with_procedure(PROGRAM, 1, 1, |_ctx, _args| async {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            Ok(vec![42])
+        });
+
+        let server = AsyncServer::builder()
+            .with_idle_timeout(Duration::from_millis(100))
+            .build(dispatcher);
+        let addr = serve(server).await;
+
+        // The call outlives the idle timeout, but is still replied to.
+        let client = AsyncClient::builder().connect(addr).await.unwrap();
+        assert_eq!(client.call(PROGRAM, 1, 1, &[]).await.unwrap(), [42]);
+    }
+
+    #[tokio::test]
+    async fn test_max_connections() {
+        let server = AsyncServer::builder().with_max_connections(1).build(echo());
+        let addr = serve(server).await;
+
+        let first = AsyncClient::builder().connect(addr).await.unwrap();
+        first.call(PROGRAM, 1, 1, &[]).await.unwrap();
+
+        // The second connection is not served until the first closes.
+        let second = AsyncClient::builder().connect(addr).await.unwrap();
+        let call = second.call(PROGRAM, 1, 1, &[]);
+        tokio::pin!(call);
+        assert!(tokio::time::timeout(Duration::from_millis(100), &mut call)
+            .await
+            .is_err());
+
+        drop(first);
+        call.await.unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_serve_unix() {
+        let path = std::env::temp_dir().join(format!("onc-rpc-server-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = tokio::net::UnixListener::bind(&path).unwrap();
+
+        // Reply with the UID of the peer process.
+        let dispatcher = Dispatcher::new().with_procedure(
+            PROGRAM,
+            1,
+            1,
+            Procedure::sync(|ctx: CallContext, _args| match ctx.peer() {
+                Peer::Unix(Some(creds)) => Ok(creds.uid().to_be_bytes().to_vec()),
+                _ => Err(ProcedureError::SystemError),
+            }),
+        );
+        let server = AsyncServer::new(dispatcher);
+        tokio::spawn(async move { server.serve_unix(listener).await });
+
+        let client = AsyncClient::builder().connect_unix(&path).await.unwrap();
+        let got = client.call(PROGRAM, 1, 1, &[]).await;
+        let _ = std::fs::remove_file(&path);
+
+        let uid = rustix::process::geteuid().as_raw();
+        assert_eq!(got.unwrap(), uid.to_be_bytes());
+    }
+}
@@ -0,0 +1,703 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use tokio::{
+    net::UdpSocket,
+    sync::{mpsc, OwnedSemaphorePermit, Semaphore},
+    task::JoinSet,
+};
+
+use crate::{
+    auth::Peer,
+    server::{Dispatcher, Reply, DEFAULT_MAX_IN_FLIGHT},
+    AcceptedStatus, MessageType, PartialHeader, ReplyBody, RpcMessage,
+};
+
+/// The default maximum length of a call datagram.
+///
+/// This is the largest UDP payload that can be carried over IPv4.
+pub const DEFAULT_MAX_UDP_CALL_LEN: usize = 65_507;
+
+/// The default maximum length of a reply datagram.
+///
+/// This is the largest UDP payload that fits in a 1500 byte Ethernet frame
+/// without IP fragmentation.
+pub const DEFAULT_MAX_UDP_REPLY_LEN: usize = 1472;
+
+/// The default maximum number of replies sent with a single system call, and
+/// of calls received for each socket readiness notification.
+pub const DEFAULT_UDP_BATCH_SIZE: usize = 32;
+
+/// A reply datagram and its destination.
+type Outgoing = (SocketAddr, Vec<u8>);
+
+/// Configuration for a [`UdpServer`].
+#[derive(Debug, Clone)]
+pub struct UdpServerBuilder {
+    max_in_flight: usize,
+    max_call_len: usize,
+    max_reply_len: usize,
+    batch_size: usize,
+}
+
+impl UdpServerBuilder {
+    /// Execute at most `max_in_flight` calls at any one time.
+    ///
+    /// Once the limit is reached, no further calls are read from the socket
+    /// until an executing call completes, leaving them queued in the socket
+    /// receive buffer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_in_flight` is 0.
+    pub fn with_max_in_flight(self, max_in_flight: usize) -> Self {
+        assert!(max_in_flight > 0, "max_in_flight must be non-zero");
+        Self {
+            max_in_flight,
+            ..self
+        }
+    }
+
+    /// Discard call datagrams longer than `max_call_len` bytes.
+    pub fn with_max_call_len(self, max_call_len: usize) -> Self {
+        Self {
+            max_call_len,
+            ..self
+        }
+    }
+
+    /// Never send reply datagrams longer than `max_reply_len` bytes.
+    ///
+    /// Calls with a longer reply are instead replied to with `SYSTEM_ERR`,
+    /// causing the caller to fail the call rather than retransmit it. This
+    /// should be set to the largest datagram that can be sent without IP
+    /// fragmentation on the network path to callers.
+    pub fn with_max_reply_len(self, max_reply_len: usize) -> Self {
+        Self {
+            max_reply_len,
+            ..self
+        }
+    }
+
+    /// Send at most `batch_size` replies with a single system call, and receive
+    /// at most `batch_size` calls each time the socket becomes readable.
+    ///
+    /// On Linux, replies are sent with a single `sendmmsg` system call and calls
+    /// are received with a single `recvmmsg` system call, using a receive
+    /// buffer of [`UdpServerBuilder::with_max_call_len()`] bytes for each
+    /// datagram in the batch. On other platforms each datagram is sent and
+    /// received with a separate system call, though queued calls are still
+    /// received without waiting for readiness again between each.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `batch_size` is 0.
+    pub fn with_batch_size(self, batch_size: usize) -> Self {
+        assert!(batch_size > 0, "batch_size must be non-zero");
+        Self { batch_size, ..self }
+    }
+
+    /// Construct a server handling calls with `dispatcher`.
+    pub fn build(self, dispatcher: impl Into<Arc<Dispatcher>>) -> UdpServer {
+        UdpServer {
+            dispatcher: dispatcher.into(),
+            config: self,
+        }
+    }
+}
+
+impl Default for UdpServerBuilder {
+    fn default() -> Self {
+        Self {
+            max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+            max_call_len: DEFAULT_MAX_UDP_CALL_LEN,
+            max_reply_len: DEFAULT_MAX_UDP_REPLY_LEN,
+            batch_size: DEFAULT_UDP_BATCH_SIZE,
+        }
+    }
+}
+
+/// An async RPC server for UDP, executing the calls received on a socket
+/// concurrently.
+///
+/// Each datagram holds a single call without a record marking header, and is
+/// handed to the [`Dispatcher`] as it arrives. When the socket becomes
+/// readable, all queued call datagrams (up to the batch size) are received
+/// before waiting for readiness again - with a single `recvmmsg` system call on
+/// Linux. Replies are queued as calls complete, and the queued replies are sent
+/// together - with a single `sendmmsg` system call on Linux - reducing the
+/// per-datagram cost during bursts of small calls.
+///
+/// Replies are limited to [`UdpServerBuilder::with_max_reply_len()`] bytes to
+/// avoid IP fragmentation. Datagrams that are not calls, or cannot be parsed
+/// well enough to reply, are discarded.
+///
+/// UDP offers no protection against retransmitted calls being executed more
+/// than once - non-idempotent procedures should be protected with a duplicate
+/// request cache.
+///
+/// ```no_run
+/// use onc_rpc::server::{Dispatcher, Procedure, UdpServer};
+/// use tokio::net::UdpSocket;
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let dispatcher = Dispatcher::new().with_procedure(
+///     100099,
+///     1,
+///     1,
+///     Procedure::sync(|_ctx, args| Ok(args)),
+/// );
+///
+/// let server = UdpServer::new(dispatcher);
+/// server.serve(UdpSocket::bind("127.0.0.1:4242").await?).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct UdpServer {
+    dispatcher: Arc<Dispatcher>,
+    config: UdpServerBuilder,
+}
+
+impl UdpServer {
+    /// Configure a new server.
+    pub fn builder() -> UdpServerBuilder {
+        UdpServerBuilder::default()
+    }
+
+    /// Construct a server with the default configuration, handling calls with
+    /// `dispatcher`.
+    pub fn new(dispatcher: impl Into<Arc<Dispatcher>>) -> Self {
+        UdpServerBuilder::default().build(dispatcher)
+    }
+
+    /// The dispatcher handling calls received by this server.
+    pub fn dispatcher(&self) -> &Arc<Dispatcher> {
+        &self.dispatcher
+    }
+
+    /// Serve the calls received on `socket`.
+    ///
+    /// This runs until the returned future is dropped, which aborts any
+    /// executing calls, or until receiving from `socket` fails.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime.
+    pub async fn serve(&self, socket: UdpSocket) -> Result<(), std::io::Error> {
+        let (tx, rx) = mpsc::channel(self.config.max_in_flight);
+
+        // Calls are executed in tasks owned by this future, and aborted when
+        // it is dropped.
+        let mut calls = JoinSet::new();
+
+        tokio::select! {
+            res = self.read_calls(&socket, tx, &mut calls) => res,
+            () = send_replies(&socket, rx, self.config.batch_size) => Ok(()),
+        }
+    }
+
+    /// Read call datagrams from `socket`, executing each in a task spawned
+    /// into `calls` that sends the serialised reply to `tx`.
+    async fn read_calls(
+        &self,
+        socket: &UdpSocket,
+        tx: mpsc::Sender<Outgoing>,
+        calls: &mut JoinSet<()>,
+    ) -> Result<(), std::io::Error> {
+        let in_flight = Arc::new(Semaphore::new(self.config.max_in_flight));
+
+        // One spare byte detects datagrams truncated to fit the buffer.
+        let mut bufs = vec![vec![0; self.config.max_call_len + 1]; self.config.batch_size];
+        let mut received = Vec::with_capacity(self.config.batch_size);
+        let mut permits = Vec::with_capacity(self.config.batch_size);
+
+        loop {
+            permits.push(
+                Arc::clone(&in_flight)
+                    .acquire_owned()
+                    .await
+                    .expect("in-flight semaphore is never closed"),
+            );
+
+            // Reap completed calls.
+            while calls.try_join_next().is_some() {}
+
+            socket.readable().await?;
+
+            // Receive the datagrams queued in the receive buffer, for as long
+            // as there is capacity to execute them.
+            while permits.len() < bufs.len() {
+                match Arc::clone(&in_flight).try_acquire_owned() {
+                    Ok(p) => permits.push(p),
+                    Err(_) => break,
+                }
+            }
+
+            received.clear();
+            match recv_batch(socket, &mut bufs[..permits.len()], &mut received) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                // ICMP errors for earlier replies may be reported here on
+                // some platforms.
+                Err(e) if is_transient(&e) => {}
+                Err(e) => return Err(e),
+            }
+
+            // Permits left over once all received calls are spawned are
+            // released.
+            for (&(n, from), (buf, p)) in received.iter().zip(bufs.iter().zip(permits.drain(..))) {
+                if n > self.config.max_call_len {
+                    tracing::debug!(%from, "discarding oversized rpc call datagram");
+                    continue;
+                }
+
+                self.spawn_call(calls, &tx, p, from, buf[..n].to_vec());
+            }
+        }
+    }
+
+    /// Execute `call` received `from` a peer in a task spawned into `calls`,
+    /// sending the serialised reply to `tx` and releasing `permit` once done.
+    fn spawn_call(
+        &self,
+        calls: &mut JoinSet<()>,
+        tx: &mpsc::Sender<Outgoing>,
+        permit: OwnedSemaphorePermit,
+        from: SocketAddr,
+        call: Vec<u8>,
+    ) {
+        let dispatcher = Arc::clone(&self.dispatcher);
+        let max_reply_len = self.config.max_reply_len;
+        let tx = tx.clone();
+        calls.spawn(async move {
+            let mut out = Vec::new();
+            match dispatcher
+                .dispatch_datagram(&Peer::Inet(from), &call, &mut out)
+                .await
+            {
+                Ok(true) => {}
+                Ok(false) => return,
+                Err(e) => {
+                    tracing::warn!(error=%e, "failed to serialise rpc reply");
+                    return;
+                }
+            }
+
+            if out.len() > max_reply_len {
+                tracing::warn!(
+                    %from,
+                    len = out.len(),
+                    max = max_reply_len,
+                    "rpc reply exceeds maximum datagram length"
+                );
+                // The reply was built from a parsed call, so the xid is
+                // always present.
+                let xid = PartialHeader::from_datagram(&call).xid().unwrap();
+                let err: Reply = RpcMessage::new(
+                    xid,
+                    MessageType::Reply(ReplyBody::accepted(None, AcceptedStatus::SystemError)),
+                );
+                out = err.serialise_datagram().expect("serialise error reply");
+            }
+
+            // The server may have stopped since the call was received, in
+            // which case the reply is discarded.
+            let _ = tx.send((from, out)).await;
+
+            drop(permit);
+        });
+    }
+}
+
+/// Returns true if `e` reports a failure to deliver an earlier datagram,
+/// rather than a failure of the socket.
+fn is_transient(e: &std::io::Error) -> bool {
+    matches!(
+        e.kind(),
+        std::io::ErrorKind::ConnectionReset
+            | std::io::ErrorKind::ConnectionRefused
+            | std::io::ErrorKind::Interrupted
+    )
+}
+
+/// Receive up to `bufs.len()` datagrams from `socket` with a single `recvmmsg`
+/// call, pushing the length and source of each datagram received into the
+/// corresponding buffer to `received`.
+#[cfg(target_os = "linux")]
+fn recv_batch(
+    socket: &UdpSocket,
+    bufs: &mut [Vec<u8>],
+    received: &mut Vec<(usize, SocketAddr)>,
+) -> Result<(), std::io::Error> {
+    use std::{mem, os::fd::AsRawFd, ptr};
+
+    use tokio::io::Interest;
+
+    // SAFETY: sockaddr_storage is plain data, valid when zeroed.
+    let mut addrs = vec![unsafe { mem::zeroed::<libc::sockaddr_storage>() }; bufs.len()];
+    let mut iovs = bufs
+        .iter_mut()
+        .map(|buf| libc::iovec {
+            iov_base: buf.as_mut_ptr().cast(),
+            iov_len: buf.len(),
+        })
+        .collect::<Vec<_>>();
+
+    let mut msgs = addrs
+        .iter_mut()
+        .zip(&mut iovs)
+        .map(|(addr, iov)| {
+            // SAFETY: msghdr is plain data, valid when zeroed.
+            let mut hdr = unsafe { mem::zeroed::<libc::msghdr>() };
+            hdr.msg_name = ptr::from_mut(addr).cast();
+            hdr.msg_namelen = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+            hdr.msg_iov = iov;
+            hdr.msg_iovlen = 1;
+            libc::mmsghdr {
+                msg_hdr: hdr,
+                msg_len: 0,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let n = socket.try_io(Interest::READABLE, || {
+        // SAFETY: each message points at an address and a single iovec that
+        // outlive the call, and each iovec points at a buffer of iov_len bytes.
+        let n = unsafe {
+            libc::recvmmsg(
+                socket.as_raw_fd(),
+                msgs.as_mut_ptr(),
+                msgs.len() as _,
+                0,
+                ptr::null_mut(),
+            )
+        };
+        if n < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(n as usize)
+    })?;
+
+    for (msg, addr) in msgs[..n].iter().zip(&addrs) {
+        received.push((msg.msg_len as usize, socket_addr(addr)?));
+    }
+
+    Ok(())
+}
+
+/// Convert the IPv4 or IPv6 `addr` filled in by the kernel.
+#[cfg(target_os = "linux")]
+fn socket_addr(addr: &libc::sockaddr_storage) -> Result<SocketAddr, std::io::Error> {
+    use std::{
+        net::{Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6},
+        ptr,
+    };
+
+    match libc::c_int::from(addr.ss_family) {
+        libc::AF_INET => {
+            // SAFETY: the kernel filled in a sockaddr_in for AF_INET, which
+            // fits within (and is less aligned than) sockaddr_storage.
+            let a = unsafe { &*ptr::from_ref(addr).cast::<libc::sockaddr_in>() };
+            Ok(SocketAddrV4::new(
+                Ipv4Addr::from(u32::from_be(a.sin_addr.s_addr)),
+                u16::from_be(a.sin_port),
+            )
+            .into())
+        }
+        libc::AF_INET6 => {
+            // SAFETY: as above, for sockaddr_in6 and AF_INET6.
+            let a = unsafe { &*ptr::from_ref(addr).cast::<libc::sockaddr_in6>() };
+            Ok(SocketAddrV6::new(
+                Ipv6Addr::from(a.sin6_addr.s6_addr),
+                u16::from_be(a.sin6_port),
+                a.sin6_flowinfo,
+                a.sin6_scope_id,
+            )
+            .into())
+        }
+        family => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("unexpected address family {family}"),
+        )),
+    }
+}
+
+/// Receive up to `bufs.len()` datagrams queued in the receive buffer of
+/// `socket`, one at a time, pushing the length and source of each datagram
+/// received into the corresponding buffer to `received`.
+#[cfg(not(target_os = "linux"))]
+fn recv_batch(
+    socket: &UdpSocket,
+    bufs: &mut [Vec<u8>],
+    received: &mut Vec<(usize, SocketAddr)>,
+) -> Result<(), std::io::Error> {
+    for buf in bufs {
+        match socket.try_recv_from(buf) {
+            Ok(v) => received.push(v),
+            // Errors once some datagrams were received are reported by the
+            // next call.
+            Err(_) if !received.is_empty() => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Send the replies received from `rx` over `socket` in batches of up to
+/// `batch_size`, until all senders are dropped.
+///
+/// Replies that cannot be sent are logged and discarded.
+async fn send_replies(socket: &UdpSocket, mut rx: mpsc::Receiver<Outgoing>, batch_size: usize) {
+    let mut batch = Vec::with_capacity(batch_size);
+    while rx.recv_many(&mut batch, batch_size).await > 0 {
+        send_batch(socket, &batch).await;
+        batch.clear();
+    }
+}
+
+/// Send all of the datagrams in `batch`, using as few `sendmmsg` calls as
+/// possible.
+#[cfg(target_os = "linux")]
+async fn send_batch(socket: &UdpSocket, mut batch: &[Outgoing]) {
+    use tokio::io::Interest;
+
+    while !batch.is_empty() {
+        match socket
+            .async_io(Interest::WRITABLE, || sendmmsg(socket, batch))
+            .await
+        {
+            Ok(n) => batch = &batch[n.max(1)..],
+            Err(e) => {
+                // The first datagram failed - skip it and send the rest.
+                tracing::debug!(error=%e, to=%batch[0].0, "failed to send rpc reply");
+                batch = &batch[1..];
+            }
+        }
+    }
+}
+
+/// Send `batch` with a single `sendmmsg` call, returning the number of
+/// datagrams sent.
+#[cfg(target_os = "linux")]
+fn sendmmsg(socket: &UdpSocket, batch: &[Outgoing]) -> Result<usize, std::io::Error> {
+    use std::io::IoSlice;
+
+    use rustix::net::{addr::SocketAddrArg, MMsgHdr, SendAncillaryBuffer, SendFlags};
+
+    let addrs = batch.iter().map(|(a, _)| a.as_any()).collect::<Vec<_>>();
+    let iovs = batch
+        .iter()
+        .map(|(_, v)| [IoSlice::new(v)])
+        .collect::<Vec<_>>();
+    let mut control = batch
+        .iter()
+        .map(|_| SendAncillaryBuffer::default())
+        .collect::<Vec<_>>();
+
+    let mut msgs = addrs
+        .iter()
+        .zip(&iovs)
+        .zip(&mut control)
+        .map(|((addr, iov), control)| MMsgHdr::new_with_addr(addr, iov, control))
+        .collect::<Vec<_>>();
+
+    Ok(rustix::net::sendmmsg(
+        socket,
+        &mut msgs,
+        SendFlags::empty(),
+    )?)
+}
+
+/// Send all of the datagrams in `batch`, one at a time.
+#[cfg(not(target_os = "linux"))]
+async fn send_batch(socket: &UdpSocket, batch: &[Outgoing]) {
+    for (to, buf) in batch {
+        if let Err(e) = socket.send_to(buf, to).await {
+            tracing::debug!(error=%e, %to, "failed to send rpc reply");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{
+        auth::AuthFlavor,
+        client::{ClientError, UdpClient},
+        server::Procedure,
+        AcceptedStatus, CallBody, MessageType, RpcError, RpcMessage,
+    };
+
+    const PROGRAM: u32 = 100099;
+
+    /// Serve `server` on a new loopback socket, returning its address.
+    async fn serve(server: UdpServer) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(async move { server.serve(socket).await });
+        addr
+    }
+
+    fn dispatcher() -> Dispatcher {
+        Dispatcher::new()
+            .with_procedure(PROGRAM, 1, 1, Procedure::sync(|_ctx, args| Ok(args)))
+            .with_procedure(
+                PROGRAM,
+                1,
+                2,
+                Procedure::sync(|_ctx, _args| Ok(vec![42; 2000])),
+            )
+    }
+
+    fn call_datagram(xid: u32, args: &[u8]) -> Vec<u8> {
+        RpcMessage::new(
+            xid,
+            MessageType::Call(CallBody::new(
+                PROGRAM,
+                1,
+                1,
+                AuthFlavor::<&[u8]>::AuthNone(None),
+                AuthFlavor::AuthNone(None),
+                args,
+            )),
+        )
+        .serialise_datagram()
+        .unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_serve() {
+        let addr = serve(UdpServer::new(dispatcher())).await;
+
+        let got = tokio::task::spawn_blocking(move || {
+            let mut client = UdpClient::connect(addr).unwrap();
+            let echo = client.call(PROGRAM, 1, 1, b"hello").unwrap();
+            let err = client.call(PROGRAM, 2, 1, &[]).unwrap_err();
+            (echo, err)
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(got.0, b"hello");
+        assert!(matches!(
+            got.1,
+            ClientError::Reply(RpcError::ProgramMismatch { low: 1, high: 1 })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_burst() {
+        let server = UdpServer::builder()
+            .with_batch_size(4)
+            .with_max_in_flight(3)
+            .build(dispatcher());
+        let addr = serve(server).await;
+
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        for xid in 0..50_u32 {
+            let call = call_datagram(xid, &xid.to_be_bytes());
+            socket.send_to(&call, addr).await.unwrap();
+        }
+
+        let mut seen = [false; 50];
+        let mut buf = vec![0; 1500];
+        for _ in 0..50 {
+            let n = tokio::time::timeout(Duration::from_secs(5), socket.recv(&mut buf))
+                .await
+                .unwrap()
+                .unwrap();
+
+            let reply = RpcMessage::from_datagram(&buf[..n]).unwrap();
+            let xid = reply.xid();
+            let body = reply.into_reply_body().unwrap().into_result().unwrap();
+            assert_eq!(body, xid.to_be_bytes());
+            seen[xid as usize] = true;
+        }
+        assert!(seen.iter().all(|v| *v));
+    }
+
+    #[tokio::test]
+    async fn test_max_reply_len() {
+        let addr = serve(UdpServer::new(dispatcher())).await;
+
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut call = call_datagram(42, &[]);
+
+        // Call procedure 2, which replies with more than the default limit.
+        call[20..24].copy_from_slice(&2_u32.to_be_bytes());
+        socket.send_to(&call, addr).await.unwrap();
+
+        let mut buf = vec![0; 4096];
+        let n = socket.recv(&mut buf).await.unwrap();
+        let reply = RpcMessage::from_datagram(&buf[..n]).unwrap();
+        assert_eq!(reply.xid(), 42);
+        match reply.reply_body() {
+            Some(crate::ReplyBody::Accepted(r)) => {
+                assert_eq!(*r.status(), AcceptedStatus::SystemError)
+            }
+            v => panic!("unexpected reply {v:?}"),
+        }
+
+        // A larger limit allows the reply.
+        let server = UdpServer::builder()
+            .with_max_reply_len(4096)
+            .build(dispatcher());
+        let addr = serve(server).await;
+        socket.send_to(&call, addr).await.unwrap();
+        let n = socket.recv(&mut buf).await.unwrap();
+        assert!(n > 2000);
+    }
+
+    #[tokio::test]
+    async fn test_ipv6() {
+        // IPv6 may be unavailable in the test environment.
+        let Ok(socket) = UdpSocket::bind("[::1]:0").await else {
+            return;
+        };
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(async move { UdpServer::new(dispatcher()).serve(socket).await });
+
+        // Replies are sent to the source address of each call.
+        let client = UdpSocket::bind("[::1]:0").await.unwrap();
+        for xid in 0..3_u32 {
+            client
+                .send_to(&call_datagram(xid, b"v6"), addr)
+                .await
+                .unwrap();
+        }
+
+        let mut buf = vec![0; 1500];
+        for _ in 0..3 {
+            let (n, from) = client.recv_from(&mut buf).await.unwrap();
+            assert_eq!(from, addr);
+            let reply = RpcMessage::from_datagram(&buf[..n]).unwrap();
+            let body = reply.into_reply_body().unwrap().into_result().unwrap();
+            assert_eq!(body, b"v6");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_invalid_datagrams_discarded() {
+        let server = UdpServer::builder()
+            .with_max_call_len(64)
+            .build(dispatcher());
+        let addr = serve(server).await;
+
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        socket.send_to(&[1, 2, 3], addr).await.unwrap();
+        socket
+            .send_to(&call_datagram(1, &[0; 100]), addr)
+            .await
+            .unwrap();
+        socket
+            .send_to(&call_datagram(2, b"ok"), addr)
+            .await
+            .unwrap();
+
+        // Only the valid call is replied to.
+        let mut buf = vec![0; 1500];
+        let n = socket.recv(&mut buf).await.unwrap();
+        assert_eq!(RpcMessage::from_datagram(&buf[..n]).unwrap().xid(), 2);
+    }
+}
@@ -0,0 +1,448 @@
+//! RPC-over-TLS, as described in [RFC 9289].
+//!
+//! A client probes the server for TLS support by sending a NULL procedure call
+//! with [`AuthFlavor::AuthTls`] credentials. A server willing to upgrade the
+//! connection replies with an [`AuthFlavor::AuthNone`] verifier containing
+//! [`STARTTLS_VERIFIER`], after which both peers perform a TLS handshake over
+//! the same stream.
+//!
+//! Once upgraded, RPC messages continue to be sent over the TLS stream using
+//! the usual record marking framing - [`RpcMessage::serialise_into()`] can be
+//! used to write messages, and [`read_record()`] to read them.
+//!
+//! The [`connect()`] and [`accept()`] functions perform the probe and the TLS
+//! handshake for clients and servers respectively, using [`rustls`]
+//! configurations provided by the caller. RFC 9289 requires peers to negotiate
+//! the [`ALPN_PROTOCOL`] identifier, which must be set in the
+//! `alpn_protocols` field of both configurations - the handshake fails with
+//! [`TlsError::AlpnMismatch`] if it is not negotiated.
+//!
+//! [RFC 9289]: https://www.rfc-editor.org/rfc/rfc9289
+
+use std::{
+    convert::TryFrom,
+    io::{Read, Write},
+    sync::Arc,
+};
+
+use rustls::{
+    pki_types::ServerName, ClientConfig, ClientConnection, ServerConfig, ServerConnection,
+    StreamOwned,
+};
+use thiserror::Error;
+
+use crate::{
+    auth::{AuthFlavor, STARTTLS_VERIFIER},
+    read_record, AcceptedReply, AcceptedStatus, AuthError, CallBody, MessageType, ReplyBody,
+    RpcMessage,
+};
+
+/// The ALPN protocol identifier for RPC-over-TLS.
+///
+/// See [RFC 9289 section 7.2](https://www.rfc-editor.org/rfc/rfc9289#section-7.2).
+pub const ALPN_PROTOCOL: &[u8] = b"sunrpc";
+
+/// The maximum length of a probe call or reply record.
+///
+/// Probe messages carry no payload, so this is deliberately small.
+const MAX_PROBE_LEN: usize = 1024;
+
+/// The NULL procedure number used for the `AUTH_TLS` probe.
+const NULL_PROCEDURE: u32 = 0;
+
+/// Errors returned when establishing an RPC-over-TLS connection.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum TlsError {
+    /// An I/O error occurred on the underlying stream.
+    #[error("i/o error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// A probe message could not be read or parsed.
+    #[error("invalid probe message: {0}")]
+    Rpc(#[from] crate::Error),
+
+    /// The server replied to the probe, but did not agree to upgrade the
+    /// connection to TLS.
+    #[error("server does not support rpc-over-tls")]
+    NotSupported,
+
+    /// The peer sent a message that is not part of the `AUTH_TLS` probe
+    /// exchange.
+    #[error("unexpected message during rpc-over-tls probe")]
+    UnexpectedMessage,
+
+    /// The TLS handshake completed without negotiating the [`ALPN_PROTOCOL`]
+    /// identifier.
+    #[error("peer did not negotiate the sunrpc alpn protocol")]
+    AlpnMismatch,
+
+    /// The TLS session could not be configured.
+    #[error("tls error: {0}")]
+    Tls(#[from] rustls::Error),
+}
+
+/// Send an `AUTH_TLS` probe for `program` / `program_version` over `stream`,
+/// and wait for the server to agree to upgrade the connection.
+///
+/// Returns [`TlsError::NotSupported`] if the server replies without the
+/// `STARTTLS` verifier, in which case the stream remains usable as a plain-text
+/// RPC connection.
+pub fn probe<S: Read + Write>(
+    stream: &mut S,
+    xid: u32,
+    program: u32,
+    program_version: u32,
+) -> Result<(), TlsError> {
+    let msg = RpcMessage::new(
+        xid,
+        MessageType::Call(CallBody::new(
+            program,
+            program_version,
+            NULL_PROCEDURE,
+            AuthFlavor::<&[u8]>::AuthTls,
+            AuthFlavor::AuthNone(None),
+            &[],
+        )),
+    );
+    stream.write_all(&msg.serialise()?)?;
+    stream.flush()?;
+
+    let mut buf = Vec::new();
+    read_record(&mut *stream, &mut buf, MAX_PROBE_LEN)?;
+    let reply = RpcMessage::try_from(buf.as_slice())?;
+
+    if reply.xid() != xid {
+        return Err(TlsError::UnexpectedMessage);
+    }
+
+    match reply.reply_body() {
+        Some(ReplyBody::Accepted(r)) if is_starttls(r) => Ok(()),
+        Some(_) => Err(TlsError::NotSupported),
+        None => Err(TlsError::UnexpectedMessage),
+    }
+}
+
+/// Probe the server for RPC-over-TLS support, and once accepted, perform a TLS
+/// handshake over `stream`.
+///
+/// Returns [`TlsError::AlpnMismatch`] if the server does not negotiate the
+/// [`ALPN_PROTOCOL`] identifier.
+///
+/// The returned stream is a plain TLS stream - RPC messages sent over it must
+/// still be framed with record marking by the caller, as described in the
+/// [module documentation](self).
+pub fn connect<S: Read + Write>(
+    mut stream: S,
+    xid: u32,
+    program: u32,
+    program_version: u32,
+    config: Arc<ClientConfig>,
+    server_name: ServerName<'static>,
+) -> Result<StreamOwned<ClientConnection, S>, TlsError> {
+    probe(&mut stream, xid, program, program_version)?;
+
+    let conn = ClientConnection::new(config, server_name)?;
+    let mut tls = StreamOwned::new(conn, stream);
+    while tls.conn.is_handshaking() {
+        tls.conn.complete_io(&mut tls.sock)?;
+    }
+
+    if tls.conn.alpn_protocol() != Some(ALPN_PROTOCOL) {
+        return Err(TlsError::AlpnMismatch);
+    }
+
+    Ok(tls)
+}
+
+/// Read an `AUTH_TLS` probe from `stream` and reply agreeing to upgrade the
+/// connection to TLS.
+///
+/// Returns [`TlsError::UnexpectedMessage`] if the first record read from
+/// `stream` is not a NULL procedure call with `AUTH_TLS` credentials. If it is
+/// any other call, it is rejected with an [`AuthError::TooWeak`] reply rather
+/// than left unanswered.
+pub fn accept_probe<S: Read + Write>(stream: &mut S) -> Result<(), TlsError> {
+    let mut buf = Vec::new();
+    read_record(&mut *stream, &mut buf, MAX_PROBE_LEN)?;
+    let call = RpcMessage::try_from(buf.as_slice())?;
+
+    match call.call_body() {
+        Some(b)
+            if b.procedure() == NULL_PROCEDURE
+                && matches!(b.auth_credentials(), AuthFlavor::AuthTls) => {}
+        Some(b) => {
+            let reply = RpcMessage::new(
+                call.xid(),
+                MessageType::Reply(b.auth_error::<&[u8], &[u8]>(AuthError::TooWeak)),
+            );
+            stream.write_all(&reply.serialise()?)?;
+            stream.flush()?;
+            return Err(TlsError::UnexpectedMessage);
+        }
+        None => return Err(TlsError::UnexpectedMessage),
+    }
+
+    let reply = RpcMessage::new(
+        call.xid(),
+        MessageType::Reply(ReplyBody::Accepted(AcceptedReply::new(
+            AuthFlavor::AuthNone(Some(STARTTLS_VERIFIER)),
+            AcceptedStatus::Success(&[] as &[u8]),
+        ))),
+    );
+    stream.write_all(&reply.serialise()?)?;
+    stream.flush()?;
+
+    Ok(())
+}
+
+/// Answer an `AUTH_TLS` probe from a client, and perform a TLS handshake over
+/// `stream`.
+///
+/// Returns [`TlsError::AlpnMismatch`] if the client does not negotiate the
+/// [`ALPN_PROTOCOL`] identifier.
+///
+/// The returned stream is a plain TLS stream - RPC messages sent over it must
+/// still be framed with record marking by the caller, as described in the
+/// [module documentation](self).
+pub fn accept<S: Read + Write>(
+    mut stream: S,
+    config: Arc<ServerConfig>,
+) -> Result<StreamOwned<ServerConnection, S>, TlsError> {
+    accept_probe(&mut stream)?;
+
+    let conn = ServerConnection::new(config)?;
+    let mut tls = StreamOwned::new(conn, stream);
+    while tls.conn.is_handshaking() {
+        tls.conn.complete_io(&mut tls.sock)?;
+    }
+
+    if tls.conn.alpn_protocol() != Some(ALPN_PROTOCOL) {
+        return Err(TlsError::AlpnMismatch);
+    }
+
+    Ok(tls)
+}
+
+fn is_starttls<T, P>(reply: &AcceptedReply<T, P>) -> bool
+where
+    T: AsRef<[u8]>,
+    P: AsRef<[u8]>,
+{
+    matches!(reply.status(), AcceptedStatus::Success(_))
+        && matches!(
+            reply.auth_verifier(),
+            AuthFlavor::AuthNone(Some(v)) if v.as_ref() == STARTTLS_VERIFIER
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{TcpListener, TcpStream},
+        thread,
+    };
+
+    use rustls::{
+        crypto::ring,
+        pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer},
+        RootCertStore,
+    };
+
+    use super::*;
+    use crate::RejectedReply;
+
+    fn configs() -> (Arc<ClientConfig>, Arc<ServerConfig>) {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])
+            .expect("generate certificate");
+        let cert_der = CertificateDer::from(cert.cert.der().to_vec());
+        let key_der =
+            PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(cert.signing_key.serialize_der()));
+
+        let provider = Arc::new(ring::default_provider());
+
+        let mut server = ServerConfig::builder_with_provider(Arc::clone(&provider))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert_der.clone()], key_der)
+            .expect("server config");
+        server.alpn_protocols = vec![ALPN_PROTOCOL.to_vec()];
+
+        let mut roots = RootCertStore::empty();
+        roots.add(cert_der).expect("add root");
+        let mut client = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        client.alpn_protocols = vec![ALPN_PROTOCOL.to_vec()];
+
+        (Arc::new(client), Arc::new(server))
+    }
+
+    #[test]
+    fn test_loopback_upgrade() {
+        let (client_config, server_config) = configs();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut tls = accept(stream, server_config).expect("server accept");
+            assert_eq!(tls.conn.alpn_protocol(), Some(ALPN_PROTOCOL));
+
+            // Read a call over TLS and echo the payload back in the reply.
+            let mut buf = Vec::new();
+            read_record(&mut tls, &mut buf, 1024).expect("read call");
+            let call = RpcMessage::try_from(buf.as_slice()).expect("parse call");
+            let body = call.call_body().expect("not a call");
+
+            let reply = RpcMessage::new(
+                call.xid(),
+                MessageType::Reply(ReplyBody::Accepted(AcceptedReply::new(
+                    AuthFlavor::<&[u8]>::AuthNone(None),
+                    AcceptedStatus::Success(*body.payload()),
+                ))),
+            );
+            reply.serialise_into(&mut tls).expect("write reply");
+            tls.flush().unwrap();
+        });
+
+        let stream = TcpStream::connect(addr).unwrap();
+        let mut tls = connect(
+            stream,
+            1,
+            100003,
+            4,
+            client_config,
+            ServerName::try_from("localhost").unwrap(),
+        )
+        .expect("client connect");
+        assert_eq!(tls.conn.alpn_protocol(), Some(ALPN_PROTOCOL));
+
+        let call = RpcMessage::new(
+            2,
+            MessageType::Call(CallBody::new(
+                100003,
+                4,
+                1,
+                AuthFlavor::<&[u8]>::AuthNone(None),
+                AuthFlavor::AuthNone(None),
+                b"ping".as_slice(),
+            )),
+        );
+        call.serialise_into(&mut tls).expect("write call");
+        tls.flush().unwrap();
+
+        let mut buf = Vec::new();
+        read_record(&mut tls, &mut buf, 1024).expect("read reply");
+        let reply = RpcMessage::try_from(buf.as_slice()).expect("parse reply");
+        assert_eq!(reply.xid(), 2);
+        match reply.reply_body() {
+            Some(ReplyBody::Accepted(r)) => {
+                assert_eq!(r.status(), &AcceptedStatus::Success(b"ping".as_slice()))
+            }
+            v => panic!("unexpected reply {:?}", v),
+        }
+
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_probe_not_supported() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // A server without TLS support replies with a plain AUTH_NONE
+        // verifier.
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = Vec::new();
+            read_record(&mut stream, &mut buf, 1024).expect("read call");
+            let call = RpcMessage::try_from(buf.as_slice()).expect("parse call");
+
+            let reply = RpcMessage::new(
+                call.xid(),
+                MessageType::Reply(ReplyBody::Accepted(AcceptedReply::new(
+                    AuthFlavor::<&[u8]>::AuthNone(None),
+                    AcceptedStatus::Success(&[] as &[u8]),
+                ))),
+            );
+            reply.serialise_into(&mut stream).expect("write reply");
+        });
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let err = probe(&mut stream, 42, 100003, 4).expect_err("should not support tls");
+        assert!(matches!(err, TlsError::NotSupported));
+
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_accept_rejects_non_probe() {
+        let call = RpcMessage::new(
+            1,
+            MessageType::Call(CallBody::new(
+                100003,
+                4,
+                1,
+                AuthFlavor::<&[u8]>::AuthNone(None),
+                AuthFlavor::AuthNone(None),
+                &[] as &[u8],
+            )),
+        )
+        .serialise()
+        .unwrap();
+
+        let len = call.len();
+        let mut stream = std::io::Cursor::new(call);
+        let err = accept_probe(&mut stream).expect_err("should reject");
+        assert!(matches!(err, TlsError::UnexpectedMessage));
+
+        // The call is answered with AUTH_ERROR rather than left unanswered.
+        let written = stream.into_inner();
+        let reply = RpcMessage::try_from(&written[len..]).expect("parse reply");
+        assert_eq!(reply.xid(), 1);
+        assert_eq!(
+            reply.reply_body(),
+            Some(&ReplyBody::Denied(RejectedReply::AuthError(
+                AuthError::TooWeak
+            )))
+        );
+    }
+
+    #[test]
+    fn test_alpn_required() {
+        let (client_config, server_config) = configs();
+
+        // A server that does not offer the sunrpc protocol.
+        let mut server_config = (*server_config).clone();
+        server_config.alpn_protocols.clear();
+        let server_config = Arc::new(server_config);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let err = accept(stream, server_config).expect_err("should require alpn");
+            assert!(matches!(err, TlsError::AlpnMismatch));
+        });
+
+        let stream = TcpStream::connect(addr).unwrap();
+        let err = connect(
+            stream,
+            1,
+            100003,
+            4,
+            client_config,
+            ServerName::try_from("localhost").unwrap(),
+        )
+        .expect_err("should require alpn");
+        assert!(matches!(err, TlsError::AlpnMismatch));
+
+        server.join().unwrap();
+    }
+}